@@ -0,0 +1,112 @@
+use std::time::Instant;
+
+use anyhow::Result;
+use tiny_rdbms::buffer_pool_manager::{BufferPool, BufferPoolManager};
+use tiny_rdbms::disk_manager::DiskManager;
+use tiny_rdbms::relly::btree::{BTree, Layout};
+use tiny_rdbms::relly::tuple;
+use tiny_rdbms::relly::value::Value;
+use tiny_rdbms::sql::format::format_table;
+
+const NUM_KEYS: u64 = 50_000;
+
+// visits 0..NUM_KEYS in a scattered order, as the step is coprime to it
+fn shuffled() -> impl Iterator<Item = u64> {
+    (0..NUM_KEYS).map(|n| n * 7919 % NUM_KEYS)
+}
+
+fn encode(elems: &[Vec<u8>]) -> Vec<u8> {
+    let mut key = vec![];
+    tuple::encode(elems.iter(), &mut key);
+    key
+}
+
+// keys shaped like those of tables and their indexes
+fn workloads() -> Vec<(&'static str, Vec<Vec<u8>>)> {
+    vec![
+        (
+            "(id) ascending",
+            (0..NUM_KEYS)
+                .map(|i| encode(&[i.to_be_bytes().to_vec()]))
+                .collect(),
+        ),
+        (
+            "(tenant, id) shuffled",
+            shuffled()
+                .map(|i| {
+                    encode(&[
+                        format!("tenant-{:02}", i % 50).into_bytes(),
+                        i.to_be_bytes().to_vec(),
+                    ])
+                })
+                .collect(),
+        ),
+        (
+            "(path) shuffled",
+            shuffled()
+                .map(|i| {
+                    let path = format!("/home/user{:02}/projects/src/file{:05}.rs", i % 20, i);
+                    encode(&[path.into_bytes()])
+                })
+                .collect(),
+        ),
+    ]
+}
+
+// builds a tree from scratch for every workload and layout and reports its shape
+fn main() -> Result<()> {
+    let path = std::env::temp_dir().join(format!("tiny_rdbms-bench-{}", std::process::id()));
+    let layouts = [
+        ("plain", false, false),
+        ("truncated", true, false),
+        ("compressed", false, true),
+        ("both", true, true),
+    ];
+    let mut rows = vec![];
+    for (workload, keys) in workloads() {
+        for (name, suffix_truncation, prefix_compression) in layouts {
+            let _ = std::fs::remove_file(&path);
+            let disk = DiskManager::open(&path)?;
+            let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(256));
+            let layout = Layout {
+                suffix_truncation,
+                prefix_compression,
+            };
+            let btree = BTree::create_with_layout(&mut bufmgr, layout)?;
+            let start = Instant::now();
+            for key in &keys {
+                btree.insert(&mut bufmgr, key, b"")?;
+            }
+            let elapsed = start.elapsed();
+            let shape = btree.shape(&mut bufmgr)?;
+            rows.push(vec![
+                Value::Text(workload.to_string()),
+                Value::Text(name.to_string()),
+                Value::UInt(shape.height as u64),
+                Value::UInt(shape.branch_pages),
+                Value::UInt(shape.leaf_pages),
+                Value::UInt(elapsed.as_millis() as u64),
+            ]);
+        }
+    }
+    let _ = std::fs::remove_file(&path);
+    let mut log_path = path.into_os_string();
+    log_path.push(".wal");
+    let _ = std::fs::remove_file(log_path);
+
+    print!(
+        "{}",
+        format_table(
+            &[
+                "keys",
+                "layout",
+                "height",
+                "branches",
+                "leaves",
+                "insert ms"
+            ],
+            &rows
+        )
+    );
+    Ok(())
+}
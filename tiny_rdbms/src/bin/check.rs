@@ -0,0 +1,67 @@
+use std::collections::HashSet;
+use std::env;
+use std::path::Path;
+use std::process;
+
+use anyhow::{bail, Result};
+use tiny_rdbms::buffer_pool_manager::{BufferPool, BufferPoolManager};
+use tiny_rdbms::catalog::{Catalog, CATALOG_META_PAGE_ID};
+use tiny_rdbms::disk_manager::DiskManager;
+use tiny_rdbms::relly::btree::BTree;
+
+// verifies every btree of a database file that is not in use by another process
+fn main() -> Result<()> {
+    let path = match env::args().nth(1) {
+        Some(path) => path,
+        None => bail!("usage: check <heap file>"),
+    };
+    if !Path::new(&path).is_file() {
+        bail!("no such file: {}", path);
+    }
+    let disk = DiskManager::open(&path)?;
+    let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(64));
+    if bufmgr.num_pages() == 1 {
+        println!("{}: empty database", path);
+        return Ok(());
+    }
+
+    let catalog = Catalog::open(&mut bufmgr)?;
+    let mut trees = vec![("catalog".to_string(), BTree::new(CATALOG_META_PAGE_ID))];
+    for schema in catalog.list_tables(&mut bufmgr)? {
+        trees.push((schema.name.clone(), BTree::new(schema.meta_page_id)));
+        for index in &schema.unique_indices {
+            let columns: Vec<_> = index
+                .skey
+                .iter()
+                .map(|&column| schema.columns[column].name.as_str())
+                .collect();
+            let name = format!("{}({})", schema.name, columns.join(", "));
+            trees.push((name, BTree::new(index.meta_page_id)));
+        }
+    }
+    if let Some(page_id) = bufmgr.commit_log_page_id().valid() {
+        trees.push(("commit log".to_string(), BTree::new(page_id)));
+    }
+
+    let mut visited = HashSet::new();
+    let mut num_corruptions = 0;
+    for (name, btree) in &trees {
+        for corruption in btree.check(&mut bufmgr, &mut visited)? {
+            println!("{}: {}", name, corruption);
+            num_corruptions += 1;
+        }
+    }
+    let num_live_pages = bufmgr.num_pages() - bufmgr.num_free_pages() - 1;
+    println!(
+        "{}: {} trees, {} of {} live pages reachable, {} problems",
+        path,
+        trees.len(),
+        visited.len(),
+        num_live_pages,
+        num_corruptions
+    );
+    if num_corruptions > 0 {
+        process::exit(1);
+    }
+    Ok(())
+}
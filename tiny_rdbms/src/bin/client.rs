@@ -0,0 +1,64 @@
+use std::env;
+use std::io::{self, BufRead, IsTerminal, Write};
+
+use anyhow::Result;
+use tiny_rdbms::net::client::{Client, Response};
+use tiny_rdbms::net::{self, DEFAULT_ADDR};
+use tiny_rdbms::sql::format::{self, format_table};
+
+// statements end with ';' like in the local shell; meta-commands are not available remotely
+fn main() -> Result<()> {
+    let addr = env::args()
+        .nth(1)
+        .unwrap_or_else(|| DEFAULT_ADDR.to_string());
+    let mut client = Client::connect(&addr)?;
+
+    let interactive = io::stdin().is_terminal();
+    if interactive {
+        println!("connected to {}", addr);
+    }
+    let mut sql = String::new();
+    let mut lines = io::stdin().lock().lines();
+    loop {
+        if interactive {
+            print!("{}", if sql.is_empty() { "trdms> " } else { "  ...> " });
+            io::stdout().flush()?;
+        }
+        let line = match lines.next() {
+            Some(line) => line?,
+            None => break,
+        };
+        if sql.is_empty() && line.trim().is_empty() {
+            continue;
+        }
+        sql.push_str(&line);
+        sql.push('\n');
+        if line.trim_end().ends_with(';') {
+            run(&mut client, &sql)?;
+            sql.clear();
+        }
+    }
+    if !sql.trim().is_empty() {
+        run(&mut client, &sql)?;
+    }
+    Ok(())
+}
+
+// errors from the statement are printed, those of the connection end the session
+fn run(client: &mut Client, sql: &str) -> Result<()> {
+    match client.query(sql) {
+        Ok(response) => print_response(&response),
+        Err(net::Error::Server(message)) => eprintln!("Error: {}", message),
+        Err(err) => return Err(err.into()),
+    }
+    Ok(())
+}
+
+fn print_response(response: &Response) {
+    if response.columns.is_empty() {
+        println!("{}", response.tag);
+    } else {
+        print!("{}", format_table(&response.columns, &response.rows));
+        println!("{}", format::row_count(response.rows.len()));
+    }
+}
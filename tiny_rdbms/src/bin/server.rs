@@ -0,0 +1,33 @@
+use std::env;
+
+use anyhow::{bail, Result};
+use tiny_rdbms::buffer_pool_manager::{BufferPool, BufferPoolManager};
+use tiny_rdbms::disk_manager::DiskManager;
+use tiny_rdbms::net::server::{Protocol, Server};
+use tiny_rdbms::net::{DEFAULT_ADDR, DEFAULT_POSTGRES_ADDR};
+
+fn main() -> Result<()> {
+    let mut args: Vec<_> = env::args().skip(1).collect();
+    let protocol = match args.iter().position(|arg| arg == "--postgres") {
+        Some(i) => {
+            args.remove(i);
+            Protocol::Postgres
+        }
+        None => Protocol::Native,
+    };
+    let (path, addr) = match &args[..] {
+        [path] => (path.clone(), None),
+        [path, addr] => (path.clone(), Some(addr.clone())),
+        _ => bail!("usage: server [--postgres] <database file> [address]"),
+    };
+    let addr = addr.unwrap_or_else(|| match protocol {
+        Protocol::Native => DEFAULT_ADDR.to_string(),
+        Protocol::Postgres => DEFAULT_POSTGRES_ADDR.to_string(),
+    });
+    let server = Server::bind(&addr, protocol)?;
+    println!("serving {} on {}", path, server.local_addr()?);
+    server.run(move || {
+        let disk = DiskManager::open(&path)?;
+        Ok(BufferPoolManager::new(disk, BufferPool::new(64)))
+    })
+}
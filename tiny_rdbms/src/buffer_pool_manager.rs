@@ -3,20 +3,42 @@ use std::collections::HashMap;
 use std::io;
 use std::ops::{Index, IndexMut};
 use std::rc::Rc;
+use std::sync::Arc;
 
-use crate::disk_manager::{DiskManager, PageId, PAGE_SIZE};
+use crate::disk_manager::{ChecksumMismatch, DiskManager, PageId, PAGE_SIZE};
+
+pub mod concurrent;
+pub mod replacement;
+
+use replacement::{Clock, ReplacementPolicy};
 
 pub type Page = [u8; PAGE_SIZE];
 
 #[derive(Copy, Clone, Default)]
-pub struct BufferId(usize);
+pub struct BufferId(pub usize);
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error(transparent)]
-    Io(#[from] io::Error),
+    Io(io::Error),
     #[error("no free buffer available in buffer pool")]
     NoFreeBuffer,
+    #[error("page is still in use: {0:?}")]
+    PagePinned(PageId),
+    #[error("page is corrupted: {0:?}")]
+    Corrupted(PageId),
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        let mismatch = err
+            .get_ref()
+            .and_then(|inner| inner.downcast_ref::<ChecksumMismatch>());
+        match mismatch {
+            Some(&ChecksumMismatch(page_id)) => Error::Corrupted(page_id),
+            None => Error::Io(err),
+        }
+    }
 }
 
 pub struct Buffer {
@@ -35,72 +57,110 @@ impl Default for Buffer {
     }
 }
 
+// read access to a fetched page, shared by both buffer pool flavours so that btree lookups and
+// scans can run on either of them
+pub trait ReadPage {
+    fn read_page<R>(&self, f: impl FnOnce(&[u8]) -> R) -> R;
+}
+
+pub trait FetchPage {
+    type Buffer: ReadPage;
+
+    fn fetch_page(&mut self, page_id: PageId) -> Result<Self::Buffer, Error>;
+}
+
+impl ReadPage for Rc<Buffer> {
+    fn read_page<R>(&self, f: impl FnOnce(&[u8]) -> R) -> R {
+        f(&self.page.borrow()[..])
+    }
+}
+
+// a frame may be evicted once the pool holds the only handle to its buffer
+pub trait Pinnable {
+    fn is_pinned(&mut self) -> bool;
+}
+
+impl<T> Pinnable for Rc<T> {
+    fn is_pinned(&mut self) -> bool {
+        Rc::get_mut(self).is_none()
+    }
+}
+
+impl<T> Pinnable for Arc<T> {
+    fn is_pinned(&mut self) -> bool {
+        Arc::get_mut(self).is_none()
+    }
+}
+
+// counters since the pool was created; a write-back is a dirty page written out to make room
+// for another one, explicit flushes are not counted
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct Stats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    pub write_backs: u64,
+}
+
+impl Stats {
+    pub fn hit_ratio(&self) -> f64 {
+        let fetches = self.hits + self.misses;
+        if fetches == 0 {
+            return 0.0;
+        }
+        self.hits as f64 / fetches as f64
+    }
+}
+
 #[derive(Default)]
-pub struct Frame {
-    usage_count: u64,
-    buffer: Rc<Buffer>,
+pub struct Frame<B = Rc<Buffer>> {
+    buffer: B,
 }
 
-pub struct BufferPool {
-    buffers: Vec<Frame>,
-    next_victim_id: BufferId,
+pub struct BufferPool<B = Rc<Buffer>> {
+    buffers: Vec<Frame<B>>,
+    policy: Box<dyn ReplacementPolicy + Send>,
 }
 
-impl BufferPool {
+impl<B: Default + Pinnable> BufferPool<B> {
     pub fn new(pool_size: usize) -> Self {
+        Self::with_policy(pool_size, Box::new(Clock::new(pool_size)))
+    }
+
+    pub fn with_policy(pool_size: usize, policy: Box<dyn ReplacementPolicy + Send>) -> Self {
         let mut buffers = vec![];
         buffers.resize_with(pool_size, Default::default);
-        let next_victim_id = BufferId::default();
-        Self {
-            buffers,
-            next_victim_id,
-        }
+        Self { buffers, policy }
     }
 
-    fn size(&self) -> usize {
+    pub fn size(&self) -> usize {
         self.buffers.len()
     }
 
     fn evict(&mut self) -> Option<BufferId> {
-        let pool_size = self.size();
-        let mut consecutive_pinned = 0;
-
-        let victim_id = loop {
-            let next_victim_id = self.next_victim_id;
-            let frame = &mut self[next_victim_id];
-            if frame.usage_count == 0 {
-                break self.next_victim_id;
-            }
-
-            if Rc::get_mut(&mut frame.buffer).is_some() {
-                frame.usage_count -= 1;
-                consecutive_pinned = 0;
-            } else {
-                consecutive_pinned += 1;
-                if consecutive_pinned > pool_size {
-                    return None;
-                }
-            }
-            self.next_victim_id = self.increment_id(self.next_victim_id);
-        };
+        let buffers = &mut self.buffers;
+        self.policy
+            .evict(&mut |buffer_id| buffers[buffer_id.0].buffer.is_pinned())
+    }
 
-        Some(victim_id)
+    fn record_access(&mut self, buffer_id: BufferId) {
+        self.policy.record_access(buffer_id);
     }
 
-    fn increment_id(&self, buffer_id: BufferId) -> BufferId {
-        BufferId((buffer_id.0 + 1) % self.size())
+    fn record_free(&mut self, buffer_id: BufferId) {
+        self.policy.record_free(buffer_id);
     }
 }
 
-impl Index<BufferId> for BufferPool {
-    type Output = Frame;
+impl<B> Index<BufferId> for BufferPool<B> {
+    type Output = Frame<B>;
 
     fn index(&self, index: BufferId) -> &Self::Output {
         &self.buffers[index.0]
     }
 }
 
-impl IndexMut<BufferId> for BufferPool {
+impl<B> IndexMut<BufferId> for BufferPool<B> {
     fn index_mut(&mut self, index: BufferId) -> &mut Self::Output {
         &mut self.buffers[index.0]
     }
@@ -110,6 +170,7 @@ pub struct BufferPoolManager {
     disk: DiskManager,
     pool: BufferPool,
     page_table: HashMap<PageId, BufferId>,
+    stats: Stats,
 }
 
 impl BufferPoolManager {
@@ -119,30 +180,79 @@ impl BufferPoolManager {
             disk,
             pool,
             page_table,
+            stats: Stats::default(),
         }
     }
 
+    pub fn num_pages(&self) -> u64 {
+        self.disk.num_pages()
+    }
+
+    pub fn num_free_pages(&self) -> u64 {
+        self.disk.num_free_pages()
+    }
+
+    pub fn next_xid(&self) -> u64 {
+        self.disk.next_xid()
+    }
+
+    // written out with the next flush
+    pub fn set_next_xid(&mut self, next_xid: u64) {
+        self.disk.set_next_xid(next_xid)
+    }
+
+    pub fn commit_log_page_id(&self) -> PageId {
+        self.disk.commit_log_page_id()
+    }
+
+    // written out with the next flush, like `set_next_xid`
+    pub fn set_commit_log_page_id(&mut self, page_id: PageId) {
+        self.disk.set_commit_log_page_id(page_id)
+    }
+
+    pub fn stats(&self) -> Stats {
+        self.stats
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.stats = Stats::default();
+    }
+
     pub fn fetch_page(&mut self, page_id: PageId) -> Result<Rc<Buffer>, Error> {
         if let Some(&buffer_id) = self.page_table.get(&page_id) {
-            let frame = &mut self.pool[buffer_id];
-            frame.usage_count += 1;
-            return Ok(frame.buffer.clone());
+            self.stats.hits += 1;
+            self.pool.record_access(buffer_id);
+            return Ok(self.pool[buffer_id].buffer.clone());
         }
 
+        self.stats.misses += 1;
         let buffer_id = self.pool.evict().ok_or(Error::NoFreeBuffer)?;
         let frame = &mut self.pool[buffer_id];
         let evict_page_id = frame.buffer.page_id;
         {
             let buffer = Rc::get_mut(&mut frame.buffer).unwrap();
+            if evict_page_id.valid().is_some() {
+                self.stats.evictions += 1;
+            }
             if buffer.is_dirty.get() {
                 self.disk
                     .write_page_data(evict_page_id, buffer.page.get_mut())?;
+                self.stats.write_backs += 1;
             }
             buffer.page_id = page_id;
             buffer.is_dirty.set(false);
-            self.disk.read_page_data(page_id, buffer.page.get_mut())?;
-            frame.usage_count += 1;
+            if let Err(err) = self.disk.read_page_data(page_id, buffer.page.get_mut()) {
+                // the frame holds neither the evicted page nor a usable copy of the requested
+                // one, so it must not be found or flushed under either id
+                *buffer = Buffer::default();
+                self.pool.record_free(buffer_id);
+                self.page_table.remove(&evict_page_id);
+                return Err(err.into());
+            }
         }
+        self.pool.record_free(buffer_id);
+        self.pool.record_access(buffer_id);
+        let frame = &self.pool[buffer_id];
         let page = Rc::clone(&frame.buffer);
         self.page_table.remove(&evict_page_id);
         self.page_table.insert(page_id, buffer_id);
@@ -155,26 +265,53 @@ impl BufferPoolManager {
         let evict_page_id = frame.buffer.page_id;
         let page_id = {
             let buffer = Rc::get_mut(&mut frame.buffer).unwrap();
+            if evict_page_id.valid().is_some() {
+                self.stats.evictions += 1;
+            }
             if buffer.is_dirty.get() {
                 self.disk
                     .write_page_data(evict_page_id, buffer.page.get_mut())?;
+                self.stats.write_backs += 1;
             }
-            let page_id = self.disk.allocate_page();
+            let page_id = self.disk.allocate_page()?;
             *buffer = Buffer::default();
             buffer.page_id = page_id;
             buffer.is_dirty.set(true);
-            frame.usage_count = 1;
             page_id
         };
+        self.pool.record_free(buffer_id);
+        self.pool.record_access(buffer_id);
+        let frame = &self.pool[buffer_id];
         let page = Rc::clone(&frame.buffer);
         self.page_table.remove(&evict_page_id);
         self.page_table.insert(page_id, buffer_id);
         Ok(page)
     }
 
+    pub fn deallocate_page(&mut self, page_id: PageId) -> Result<(), Error> {
+        if let Some(&buffer_id) = self.page_table.get(&page_id) {
+            let frame = &mut self.pool[buffer_id];
+            let buffer = Rc::get_mut(&mut frame.buffer).ok_or(Error::PagePinned(page_id))?;
+            *buffer = Buffer::default();
+            self.pool.record_free(buffer_id);
+            self.page_table.remove(&page_id);
+        }
+        self.disk.deallocate_page(page_id)?;
+        Ok(())
+    }
+
+    pub fn vacuum(&mut self) -> Result<u64, Error> {
+        self.flush()?;
+        Ok(self.disk.vacuum()?)
+    }
+
     pub fn flush(&mut self) -> Result<(), Error> {
+        // clean pages are left alone, so that a commit logs only what changed
         for (&page_id, &buffer_id) in self.page_table.iter() {
             let frame = &self.pool[buffer_id];
+            if !frame.buffer.is_dirty.get() {
+                continue;
+            }
             let mut page = frame.buffer.page.borrow_mut();
             self.disk.write_page_data(page_id, page.as_mut())?;
             frame.buffer.is_dirty.set(false);
@@ -183,3 +320,127 @@ impl BufferPoolManager {
         Ok(())
     }
 }
+
+impl FetchPage for BufferPoolManager {
+    type Buffer = Rc<Buffer>;
+
+    fn fetch_page(&mut self, page_id: PageId) -> Result<Rc<Buffer>, Error> {
+        BufferPoolManager::fetch_page(self, page_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Seek, SeekFrom, Write};
+
+    use super::replacement::{Lru, LruK};
+    use super::*;
+    use crate::disk_manager;
+    use crate::relly::btree::BTree;
+
+    // two hot pages interleaved with a scan over cold pages that never repeat within the pool
+    fn run(policy: Box<dyn ReplacementPolicy + Send>) -> Stats {
+        let disk = DiskManager::new(disk_manager::tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::with_policy(4, policy));
+        let page_ids: Vec<_> = (0..20)
+            .map(|_| bufmgr.create_page().unwrap().page_id)
+            .collect();
+        bufmgr.flush().unwrap();
+        bufmgr.reset_stats();
+
+        let (hot, cold) = page_ids.split_at(2);
+        for &page_id in hot.iter().chain(hot) {
+            bufmgr.fetch_page(page_id).unwrap();
+        }
+        for cold in cold.chunks(3).cycle().take(20) {
+            for &page_id in hot.iter().chain(cold) {
+                let buffer = bufmgr.fetch_page(page_id).unwrap();
+                assert_eq!(page_id, buffer.page_id);
+            }
+        }
+        bufmgr.stats()
+    }
+
+    #[test]
+    fn test_stats() {
+        let clock = run(Box::new(Clock::new(4)));
+        let lru = run(Box::new(Lru::new(4)));
+        let lru_k = run(Box::new(LruK::new(4, 2)));
+        for stats in [clock, lru, lru_k] {
+            assert_eq!(4 + 20 * 5, stats.hits + stats.misses);
+            assert_eq!(0, stats.write_backs);
+            assert!(stats.evictions <= stats.misses);
+        }
+        assert_eq!(4, lru.hits);
+        assert_eq!(2 + 2 * 20, lru_k.hits);
+        assert!(lru_k.hit_ratio() > lru.hit_ratio());
+    }
+
+    #[test]
+    fn test_flush_dirty_only() {
+        let log_file = disk_manager::tempfile().unwrap();
+        let disk = DiskManager::with_wal(
+            disk_manager::tempfile().unwrap(),
+            log_file.try_clone().unwrap(),
+        )
+        .unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let btree = BTree::create(&mut bufmgr).unwrap();
+        for i in 0u64..10 {
+            btree
+                .insert(&mut bufmgr, &i.to_be_bytes(), b"value")
+                .unwrap();
+        }
+        bufmgr.flush().unwrap();
+        let log_len = || log_file.metadata().unwrap().len();
+
+        // reads leave the pages clean, so the next commit logs no page images
+        let before = log_len();
+        for i in 0u64..10 {
+            btree.get(&mut bufmgr, &i.to_be_bytes()).unwrap();
+        }
+        bufmgr.flush().unwrap();
+        assert!(log_len() - before < PAGE_SIZE as u64);
+
+        let before = log_len();
+        btree
+            .update(&mut bufmgr, &3u64.to_be_bytes(), b"other")
+            .unwrap();
+        bufmgr.flush().unwrap();
+        assert!(log_len() - before > PAGE_SIZE as u64);
+        assert!(log_len() - before < 2 * PAGE_SIZE as u64);
+    }
+
+    #[test]
+    fn test_corrupted() {
+        let mut heap_file = disk_manager::tempfile().unwrap();
+        let disk = DiskManager::new(heap_file.try_clone().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(4));
+        let good_page_id = bufmgr.create_page().unwrap().page_id;
+        let page_id = bufmgr.create_page().unwrap().page_id;
+        bufmgr.flush().unwrap();
+
+        heap_file.seek(SeekFrom::End(-100)).unwrap();
+        heap_file.write_all(b"garbage").unwrap();
+        let disk = DiskManager::new(heap_file.try_clone().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(1));
+        let buffer = bufmgr.fetch_page(good_page_id).unwrap();
+        buffer.page.borrow_mut()[..4].copy_from_slice(b"good");
+        buffer.is_dirty.set(true);
+        drop(buffer);
+        assert!(matches!(
+            bufmgr.fetch_page(page_id),
+            Err(Error::Corrupted(corrupted)) if corrupted == page_id
+        ));
+
+        // the evicted page was written back, and the failed read leaves no trace of it behind
+        let buffer = bufmgr.fetch_page(good_page_id).unwrap();
+        assert_eq!(b"good", &buffer.page.borrow()[..4]);
+        drop(buffer);
+        bufmgr.flush().unwrap();
+        let disk = DiskManager::new(heap_file).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(1));
+        let buffer = bufmgr.fetch_page(good_page_id).unwrap();
+        assert_eq!(b"good", &buffer.page.borrow()[..4]);
+    }
+}
@@ -0,0 +1,278 @@
+use std::collections::HashMap;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+
+use super::{BufferId, BufferPool, Error, FetchPage, Page, ReadPage, Stats};
+use crate::disk_manager::{DiskManager, PageId, PAGE_SIZE};
+
+// the zerocopy node views need 8-byte alignment, which a byte array inside a `RwLock` lacks
+#[repr(C, align(8))]
+pub struct AlignedPage(pub Page);
+
+impl Deref for AlignedPage {
+    type Target = Page;
+
+    fn deref(&self) -> &Page {
+        &self.0
+    }
+}
+
+impl DerefMut for AlignedPage {
+    fn deref_mut(&mut self) -> &mut Page {
+        &mut self.0
+    }
+}
+
+// the page latch is taken shared by readers and exclusively by writers; the page id only
+// changes while the pool holds the sole handle to the buffer
+pub struct Buffer {
+    pub page_id: PageId,
+    pub page: RwLock<AlignedPage>,
+    pub is_dirty: AtomicBool,
+}
+
+impl Default for Buffer {
+    fn default() -> Self {
+        Self {
+            page_id: Default::default(),
+            page: RwLock::new(AlignedPage([0u8; PAGE_SIZE])),
+            is_dirty: AtomicBool::new(false),
+        }
+    }
+}
+
+impl ReadPage for Arc<Buffer> {
+    fn read_page<R>(&self, f: impl FnOnce(&[u8]) -> R) -> R {
+        f(&self.page.read().unwrap()[..])
+    }
+}
+
+struct State {
+    disk: DiskManager,
+    pool: BufferPool<Arc<Buffer>>,
+    page_table: HashMap<PageId, BufferId>,
+    stats: Stats,
+}
+
+// a buffer pool that can be shared between threads; frame lookup, eviction and disk I/O are
+// serialized by one mutex, while page contents are guarded by the per-buffer latches
+pub struct BufferPoolManager {
+    state: Mutex<State>,
+}
+
+impl BufferPoolManager {
+    pub fn new(disk: DiskManager, pool: BufferPool<Arc<Buffer>>) -> Self {
+        Self {
+            state: Mutex::new(State {
+                disk,
+                pool,
+                page_table: HashMap::new(),
+                stats: Stats::default(),
+            }),
+        }
+    }
+
+    pub fn num_pages(&self) -> u64 {
+        self.state.lock().unwrap().disk.num_pages()
+    }
+
+    pub fn stats(&self) -> Stats {
+        self.state.lock().unwrap().stats
+    }
+
+    pub fn fetch_page(&self, page_id: PageId) -> Result<Arc<Buffer>, Error> {
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+        if let Some(&buffer_id) = state.page_table.get(&page_id) {
+            state.stats.hits += 1;
+            state.pool.record_access(buffer_id);
+            return Ok(Arc::clone(&state.pool[buffer_id].buffer));
+        }
+
+        state.stats.misses += 1;
+        let buffer_id = state.pool.evict().ok_or(Error::NoFreeBuffer)?;
+        let frame = &mut state.pool[buffer_id];
+        let evict_page_id = frame.buffer.page_id;
+        {
+            // nobody else can hold the buffer: handles are only cloned under the state lock
+            let buffer = Arc::get_mut(&mut frame.buffer).unwrap();
+            let page = &mut buffer.page.get_mut().unwrap().0;
+            if evict_page_id.valid().is_some() {
+                state.stats.evictions += 1;
+            }
+            if *buffer.is_dirty.get_mut() {
+                state.disk.write_page_data(evict_page_id, page)?;
+                state.stats.write_backs += 1;
+            }
+            buffer.page_id = page_id;
+            *buffer.is_dirty.get_mut() = false;
+            if let Err(err) = state.disk.read_page_data(page_id, page) {
+                // see the single-threaded pool: the frame must not keep either page id
+                *buffer = Buffer::default();
+                state.pool.record_free(buffer_id);
+                state.page_table.remove(&evict_page_id);
+                return Err(err.into());
+            }
+        }
+        state.pool.record_free(buffer_id);
+        state.pool.record_access(buffer_id);
+        let frame = &state.pool[buffer_id];
+        let page = Arc::clone(&frame.buffer);
+        state.page_table.remove(&evict_page_id);
+        state.page_table.insert(page_id, buffer_id);
+        Ok(page)
+    }
+
+    pub fn create_page(&self) -> Result<Arc<Buffer>, Error> {
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+        let buffer_id = state.pool.evict().ok_or(Error::NoFreeBuffer)?;
+        let frame = &mut state.pool[buffer_id];
+        let evict_page_id = frame.buffer.page_id;
+        let page_id = {
+            let buffer = Arc::get_mut(&mut frame.buffer).unwrap();
+            if evict_page_id.valid().is_some() {
+                state.stats.evictions += 1;
+            }
+            if *buffer.is_dirty.get_mut() {
+                state
+                    .disk
+                    .write_page_data(evict_page_id, &buffer.page.get_mut().unwrap()[..])?;
+                state.stats.write_backs += 1;
+            }
+            let page_id = state.disk.allocate_page()?;
+            *buffer = Buffer::default();
+            buffer.page_id = page_id;
+            *buffer.is_dirty.get_mut() = true;
+            page_id
+        };
+        state.pool.record_free(buffer_id);
+        state.pool.record_access(buffer_id);
+        let frame = &state.pool[buffer_id];
+        let page = Arc::clone(&frame.buffer);
+        state.page_table.remove(&evict_page_id);
+        state.page_table.insert(page_id, buffer_id);
+        Ok(page)
+    }
+
+    pub fn flush(&self) -> Result<(), Error> {
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+        for (&page_id, &buffer_id) in state.page_table.iter() {
+            let buffer = &state.pool[buffer_id].buffer;
+            // cleared before the page is read, so that a write made meanwhile marks it again
+            if !buffer.is_dirty.swap(false, Ordering::AcqRel) {
+                continue;
+            }
+            let page = buffer.page.read().unwrap();
+            if let Err(err) = state.disk.write_page_data(page_id, &page[..]) {
+                buffer.is_dirty.store(true, Ordering::Release);
+                return Err(err.into());
+            }
+        }
+        state.disk.sync()?;
+        Ok(())
+    }
+}
+
+impl FetchPage for &BufferPoolManager {
+    type Buffer = Arc<Buffer>;
+
+    fn fetch_page(&mut self, page_id: PageId) -> Result<Arc<Buffer>, Error> {
+        BufferPoolManager::fetch_page(self, page_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Bound;
+    use std::thread;
+
+    use super::*;
+    use crate::buffer_pool_manager;
+    use crate::disk_manager;
+    use crate::relly::btree::{BTree, SearchMode};
+
+    fn value(i: u64) -> Vec<u8> {
+        let len = if i.is_multiple_of(100) { 5000 } else { 100 };
+        vec![i as u8; len]
+    }
+
+    #[test]
+    fn test_parallel_scan() {
+        let heap_file = disk_manager::tempfile().unwrap();
+        let meta_page_id = {
+            let disk = DiskManager::new(heap_file.try_clone().unwrap()).unwrap();
+            let mut bufmgr = buffer_pool_manager::BufferPoolManager::new(disk, BufferPool::new(10));
+            let btree = BTree::create(&mut bufmgr).unwrap();
+            for i in 0u64..3000 {
+                btree
+                    .insert(&mut bufmgr, &i.to_be_bytes(), &value(i))
+                    .unwrap();
+            }
+            bufmgr.flush().unwrap();
+            btree.meta_page_id
+        };
+
+        let disk = DiskManager::new(heap_file).unwrap();
+        let bufmgr = BufferPoolManager::new(disk, BufferPool::new(32));
+        let btree = BTree::new(meta_page_id);
+        thread::scope(|scope| {
+            for t in 0u64..8 {
+                let btree = &btree;
+                let mut bufmgr = &bufmgr;
+                scope.spawn(move || {
+                    for round in 0..5 {
+                        let mut iter = btree.search(&mut bufmgr, SearchMode::Start).unwrap();
+                        let mut expected = 0u64;
+                        while let Some((key, found)) = iter.next(&mut bufmgr).unwrap() {
+                            assert_eq!(expected.to_be_bytes().to_vec(), key);
+                            assert_eq!(value(expected), found);
+                            expected += 1;
+                        }
+                        assert_eq!(3000, expected);
+                        drop(iter);
+
+                        let low = (t * 300 + round * 17).to_be_bytes();
+                        let high = (t * 300 + round * 17 + 250).to_be_bytes();
+                        let mut iter = btree
+                            .range(
+                                &mut bufmgr,
+                                Bound::Included(&low[..]),
+                                Bound::Excluded(&high[..]),
+                                true,
+                            )
+                            .unwrap();
+                        let mut count = 0;
+                        while let Some((key, found)) = iter.prev(&mut bufmgr).unwrap() {
+                            let i = u64::from_be_bytes(key.try_into().unwrap());
+                            assert_eq!(t * 300 + round * 17 + 249 - count, i);
+                            assert_eq!(value(i), found);
+                            count += 1;
+                        }
+                        assert_eq!(250, count);
+                        drop(iter);
+
+                        for i in (t..3000).step_by(97) {
+                            let found = btree.get(&mut bufmgr, &i.to_be_bytes()).unwrap();
+                            assert_eq!(Some(value(i)), found);
+                        }
+                    }
+                });
+            }
+        });
+        assert!(bufmgr.num_pages() > 32);
+        let stats = bufmgr.stats();
+        assert!(stats.hits > 0 && stats.evictions > 0);
+        assert_eq!(0, stats.write_backs);
+
+        let buffer = bufmgr.create_page().unwrap();
+        buffer.page.write().unwrap()[..4].copy_from_slice(b"data");
+        let page_id = buffer.page_id;
+        drop(buffer);
+        bufmgr.flush().unwrap();
+        let buffer = bufmgr.fetch_page(page_id).unwrap();
+        assert_eq!(b"data", &buffer.page.read().unwrap()[..4]);
+    }
+}
@@ -0,0 +1,179 @@
+use std::collections::VecDeque;
+
+use super::BufferId;
+
+// decides which frame gives up its page when the pool is full; `evict` must never pick a frame
+// for which `is_pinned` holds
+pub trait ReplacementPolicy {
+    fn record_access(&mut self, buffer_id: BufferId);
+    fn record_free(&mut self, buffer_id: BufferId);
+    fn evict(&mut self, is_pinned: &mut dyn FnMut(BufferId) -> bool) -> Option<BufferId>;
+}
+
+// second chance with counters: the hand decrements usage counts of unpinned frames until it
+// finds one that has run out
+pub struct Clock {
+    usage_counts: Vec<u64>,
+    next_victim_id: usize,
+}
+
+impl Clock {
+    pub fn new(pool_size: usize) -> Self {
+        Self {
+            usage_counts: vec![0; pool_size],
+            next_victim_id: 0,
+        }
+    }
+}
+
+impl ReplacementPolicy for Clock {
+    fn record_access(&mut self, buffer_id: BufferId) {
+        self.usage_counts[buffer_id.0] += 1;
+    }
+
+    fn record_free(&mut self, buffer_id: BufferId) {
+        self.usage_counts[buffer_id.0] = 0;
+    }
+
+    fn evict(&mut self, is_pinned: &mut dyn FnMut(BufferId) -> bool) -> Option<BufferId> {
+        let pool_size = self.usage_counts.len();
+        let mut consecutive_pinned = 0;
+        loop {
+            let buffer_id = BufferId(self.next_victim_id);
+            if is_pinned(buffer_id) {
+                consecutive_pinned += 1;
+                if consecutive_pinned > pool_size {
+                    return None;
+                }
+            } else {
+                let usage_count = &mut self.usage_counts[buffer_id.0];
+                if *usage_count == 0 {
+                    return Some(buffer_id);
+                }
+                *usage_count -= 1;
+                consecutive_pinned = 0;
+            }
+            self.next_victim_id = (self.next_victim_id + 1) % pool_size;
+        }
+    }
+}
+
+pub struct Lru {
+    last_access: Vec<u64>,
+    now: u64,
+}
+
+impl Lru {
+    pub fn new(pool_size: usize) -> Self {
+        Self {
+            last_access: vec![0; pool_size],
+            now: 0,
+        }
+    }
+}
+
+impl ReplacementPolicy for Lru {
+    fn record_access(&mut self, buffer_id: BufferId) {
+        self.now += 1;
+        self.last_access[buffer_id.0] = self.now;
+    }
+
+    fn record_free(&mut self, buffer_id: BufferId) {
+        self.last_access[buffer_id.0] = 0;
+    }
+
+    fn evict(&mut self, is_pinned: &mut dyn FnMut(BufferId) -> bool) -> Option<BufferId> {
+        (0..self.last_access.len())
+            .map(BufferId)
+            .filter(|&buffer_id| !is_pinned(buffer_id))
+            .min_by_key(|buffer_id| self.last_access[buffer_id.0])
+    }
+}
+
+// evicts the frame whose k-th most recent access lies furthest back; frames seen fewer than k
+// times go first, oldest first, so a single scan cannot flush pages that are used repeatedly
+pub struct LruK {
+    k: usize,
+    history: Vec<VecDeque<u64>>,
+    now: u64,
+}
+
+impl LruK {
+    pub fn new(pool_size: usize, k: usize) -> Self {
+        assert!(k > 0);
+        Self {
+            k,
+            history: vec![VecDeque::new(); pool_size],
+            now: 0,
+        }
+    }
+}
+
+impl ReplacementPolicy for LruK {
+    fn record_access(&mut self, buffer_id: BufferId) {
+        self.now += 1;
+        let history = &mut self.history[buffer_id.0];
+        if history.len() == self.k {
+            history.pop_back();
+        }
+        history.push_front(self.now);
+    }
+
+    fn record_free(&mut self, buffer_id: BufferId) {
+        self.history[buffer_id.0].clear();
+    }
+
+    fn evict(&mut self, is_pinned: &mut dyn FnMut(BufferId) -> bool) -> Option<BufferId> {
+        (0..self.history.len())
+            .map(BufferId)
+            .filter(|&buffer_id| !is_pinned(buffer_id))
+            .min_by_key(|buffer_id| {
+                let history = &self.history[buffer_id.0];
+                let kth_access = history.get(self.k - 1).copied().unwrap_or(0);
+                (kth_access, history.front().copied().unwrap_or(0))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn victim(policy: &mut dyn ReplacementPolicy, pinned: &[usize]) -> Option<usize> {
+        policy
+            .evict(&mut |buffer_id| pinned.contains(&buffer_id.0))
+            .map(|buffer_id| buffer_id.0)
+    }
+
+    fn access(policy: &mut dyn ReplacementPolicy, buffer_ids: &[usize]) {
+        for &buffer_id in buffer_ids {
+            policy.record_access(BufferId(buffer_id));
+        }
+    }
+
+    #[test]
+    fn test_policies() {
+        let mut clock = Clock::new(3);
+        access(&mut clock, &[0, 1, 2, 0]);
+        assert_eq!(Some(1), victim(&mut clock, &[]));
+        access(&mut clock, &[1]);
+        assert_eq!(Some(2), victim(&mut clock, &[1]));
+        assert_eq!(None, victim(&mut clock, &[0, 1, 2]));
+
+        let mut lru = Lru::new(3);
+        access(&mut lru, &[0, 1, 2, 0]);
+        assert_eq!(Some(1), victim(&mut lru, &[]));
+        assert_eq!(Some(2), victim(&mut lru, &[1]));
+        lru.record_free(BufferId(0));
+        assert_eq!(Some(0), victim(&mut lru, &[]));
+        assert_eq!(None, victim(&mut lru, &[0, 1, 2]));
+
+        let mut lru_k = LruK::new(3, 2);
+        access(&mut lru_k, &[0, 0, 1, 1, 2]);
+        assert_eq!(Some(2), victim(&mut lru_k, &[]));
+        access(&mut lru_k, &[2, 0, 0]);
+        assert_eq!(Some(1), victim(&mut lru_k, &[]));
+        assert_eq!(Some(2), victim(&mut lru_k, &[1]));
+        assert_eq!(None, victim(&mut lru_k, &[0, 1, 2]));
+    }
+}
@@ -0,0 +1,346 @@
+use anyhow::Result;
+use bincode::Options;
+use serde::{Deserialize, Serialize};
+
+use crate::buffer_pool_manager::BufferPoolManager;
+use crate::disk_manager::PageId;
+use crate::mvcc;
+use crate::relly::btree::{BTree, SearchMode};
+use crate::relly::tuple;
+use crate::relly::value::DataType;
+use crate::table::{SimpleTable, UniqueIndex};
+use crate::transaction::Transaction;
+
+// the first page after the disk header, which takes page 0
+pub const CATALOG_META_PAGE_ID: PageId = PageId(1);
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("table already exists: {0}")]
+    TableExists(String),
+    #[error("no such table: {0}")]
+    NoSuchTable(String),
+    #[error("catalog must be bootstrapped in an empty file")]
+    NotEmpty,
+    #[error("table is not empty: {0}")]
+    TableNotEmpty(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Column {
+    pub name: String,
+    pub data_type: DataType,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexSchema {
+    pub skey: Vec<usize>,
+    pub meta_page_id: PageId,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TableSchema {
+    pub name: String,
+    pub columns: Vec<Column>,
+    pub num_key_elems: usize,
+    pub meta_page_id: PageId,
+    pub unique_indices: Vec<IndexSchema>,
+}
+
+impl TableSchema {
+    pub fn column_index(&self, name: &str) -> Option<usize> {
+        self.columns.iter().position(|column| column.name == name)
+    }
+
+    pub fn data_types(&self) -> Vec<DataType> {
+        self.columns.iter().map(|column| column.data_type).collect()
+    }
+
+    pub fn table(&self) -> SimpleTable {
+        SimpleTable {
+            meta_page_id: self.meta_page_id,
+            num_key_elems: self.num_key_elems,
+            unique_indices: self
+                .unique_indices
+                .iter()
+                .map(|index| UniqueIndex {
+                    meta_page_id: index.meta_page_id,
+                    skey: index.skey.clone(),
+                })
+                .collect(),
+        }
+    }
+
+    fn to_bytes(&self) -> bincode::Result<Vec<u8>> {
+        bincode::options().serialize(self)
+    }
+
+    fn from_bytes(bytes: &[u8]) -> bincode::Result<Self> {
+        bincode::options().deserialize(bytes)
+    }
+}
+
+#[derive(Clone)]
+pub struct Catalog {
+    btree: BTree,
+}
+
+impl Catalog {
+    pub fn bootstrap(bufmgr: &mut BufferPoolManager) -> Result<Self> {
+        let btree = BTree::create(bufmgr)?;
+        if btree.meta_page_id != CATALOG_META_PAGE_ID {
+            return Err(Error::NotEmpty.into());
+        }
+        Ok(Self { btree })
+    }
+
+    pub fn open(bufmgr: &mut BufferPoolManager) -> Result<Self> {
+        // a fresh file holds nothing but the disk header
+        if bufmgr.num_pages() == 1 {
+            return Self::bootstrap(bufmgr);
+        }
+        Ok(Self {
+            btree: BTree::new(CATALOG_META_PAGE_ID),
+        })
+    }
+
+    fn encode_key(name: &str) -> Vec<u8> {
+        let mut key = vec![];
+        tuple::encode([name].iter(), &mut key);
+        key
+    }
+
+    pub fn create_table(
+        &self,
+        bufmgr: &mut BufferPoolManager,
+        mut schema: TableSchema,
+    ) -> Result<TableSchema> {
+        let key = Self::encode_key(&schema.name);
+        if self.btree.get(bufmgr, &key)?.is_some() {
+            return Err(Error::TableExists(schema.name).into());
+        }
+        let mut table = schema.table();
+        table.create(bufmgr)?;
+        schema.meta_page_id = table.meta_page_id;
+        for (index, unique_index) in schema.unique_indices.iter_mut().zip(&table.unique_indices) {
+            index.meta_page_id = unique_index.meta_page_id;
+        }
+        self.btree.insert(bufmgr, &key, &schema.to_bytes()?)?;
+        Ok(schema)
+    }
+
+    pub fn get_table(
+        &self,
+        bufmgr: &mut BufferPoolManager,
+        name: &str,
+    ) -> Result<Option<TableSchema>> {
+        let key = Self::encode_key(name);
+        let value = self.btree.get(bufmgr, &key)?;
+        Ok(value
+            .map(|value| TableSchema::from_bytes(&value))
+            .transpose()?)
+    }
+
+    pub fn list_tables(&self, bufmgr: &mut BufferPoolManager) -> Result<Vec<TableSchema>> {
+        let mut iter = self.btree.search(bufmgr, SearchMode::Start)?;
+        let mut tables = vec![];
+        while let Some((_, value)) = iter.next(bufmgr)? {
+            tables.push(TableSchema::from_bytes(&value)?);
+        }
+        Ok(tables)
+    }
+
+    // fills an empty table from `records` in one pass instead of inserting them one by one;
+    // the caller holds an exclusive lock on the table in `txn`
+    pub fn load_table(
+        &self,
+        bufmgr: &mut BufferPoolManager,
+        name: &str,
+        records: &[Vec<Vec<u8>>],
+        txn: &mut Transaction,
+    ) -> Result<TableSchema> {
+        let schema = self
+            .get_table(bufmgr, name)?
+            .ok_or_else(|| Error::NoSuchTable(name.to_string()))?;
+        // the load throws the old trees away, so only versions that no running transaction can
+        // see any more, the ones vacuum would remove, leave the table empty
+        let horizon = txn.horizon();
+        let mut iter = BTree::new(schema.meta_page_id).search(bufmgr, SearchMode::Start)?;
+        while let Some((_, value)) = iter.next(bufmgr)? {
+            let versions = mvcc::decode(&value)?;
+            if versions
+                .iter()
+                .any(|version| version.xmax.is_none_or(|xmax| xmax >= horizon))
+            {
+                return Err(Error::TableNotEmpty(name.to_string()).into());
+            }
+        }
+        drop(iter);
+        // the meta page ids stay the same, so the catalog entry and the table locks keyed by
+        // them still hold
+        txn.bulk_load(bufmgr, &schema.table(), records)?;
+        Ok(schema)
+    }
+
+    pub fn drop_table(&self, bufmgr: &mut BufferPoolManager, name: &str) -> Result<TableSchema> {
+        let key = Self::encode_key(name);
+        let schema = self
+            .get_table(bufmgr, name)?
+            .ok_or_else(|| Error::NoSuchTable(name.to_string()))?;
+        self.btree.delete(bufmgr, &key)?;
+        schema.table().destroy(bufmgr)?;
+        Ok(schema)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer_pool_manager::BufferPool;
+    use crate::disk_manager::{self, DiskManager};
+    use crate::transaction::TransactionManager;
+
+    fn schema(name: &str) -> TableSchema {
+        TableSchema {
+            name: name.to_string(),
+            columns: vec![
+                Column {
+                    name: "id".to_string(),
+                    data_type: DataType::Text,
+                },
+                Column {
+                    name: "name".to_string(),
+                    data_type: DataType::Text,
+                },
+            ],
+            num_key_elems: 1,
+            meta_page_id: PageId::INVALID_PAGE_ID,
+            unique_indices: vec![IndexSchema {
+                skey: vec![1],
+                meta_page_id: PageId::INVALID_PAGE_ID,
+            }],
+        }
+    }
+
+    #[test]
+    fn test() {
+        let file = disk_manager::tempfile().unwrap();
+        let users = {
+            let disk = DiskManager::new(file.try_clone().unwrap()).unwrap();
+            let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+            let catalog = Catalog::open(&mut bufmgr).unwrap();
+            let users = catalog.create_table(&mut bufmgr, schema("users")).unwrap();
+            catalog.create_table(&mut bufmgr, schema("books")).unwrap();
+            assert!(catalog.create_table(&mut bufmgr, schema("users")).is_err());
+            users.table().insert(&mut bufmgr, &[b"x", b"Bob"]).unwrap();
+            bufmgr.flush().unwrap();
+            users
+        };
+        assert_ne!(PageId::INVALID_PAGE_ID, users.meta_page_id);
+        assert_ne!(
+            PageId::INVALID_PAGE_ID,
+            users.unique_indices[0].meta_page_id
+        );
+
+        let disk = DiskManager::new(file).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let catalog = Catalog::open(&mut bufmgr).unwrap();
+        assert_eq!(
+            Some(users.clone()),
+            catalog.get_table(&mut bufmgr, "users").unwrap()
+        );
+        let names: Vec<_> = catalog
+            .list_tables(&mut bufmgr)
+            .unwrap()
+            .into_iter()
+            .map(|schema| schema.name)
+            .collect();
+        assert_eq!(vec!["books", "users"], names);
+
+        let btree = BTree::new(users.meta_page_id);
+        let mut key = vec![];
+        tuple::encode([b"x"].iter(), &mut key);
+        assert!(btree.get(&mut bufmgr, &key).unwrap().is_some());
+
+        assert_eq!(0, bufmgr.num_free_pages());
+        assert_eq!(users, catalog.drop_table(&mut bufmgr, "users").unwrap());
+        assert_eq!(4, bufmgr.num_free_pages());
+        assert!(catalog.get_table(&mut bufmgr, "users").unwrap().is_none());
+        assert!(catalog.drop_table(&mut bufmgr, "users").is_err());
+
+        let record = |id: &str, name: &str| vec![id.as_bytes().to_vec(), name.as_bytes().to_vec()];
+        let manager = TransactionManager::open(&mut bufmgr).unwrap();
+        let books = catalog.get_table(&mut bufmgr, "books").unwrap().unwrap();
+        let table = books.table();
+        let mut txn = manager.begin(&mut bufmgr).unwrap();
+        txn.insert(&mut bufmgr, &table, &[b"c", b"Cid"]).unwrap();
+        txn.commit(&mut bufmgr).unwrap();
+        let duplicates = [record("a", "Ann"), record("b", "Ann")];
+        let mut txn = manager.begin(&mut bufmgr).unwrap();
+        let err = catalog
+            .load_table(&mut bufmgr, "books", &duplicates, &mut txn)
+            .unwrap_err();
+        assert!(matches!(err.downcast(), Ok(Error::TableNotEmpty(_))));
+        txn.rollback(&mut bufmgr).unwrap();
+
+        // a deleted record is in the way while a running snapshot still sees it
+        let reader = manager.begin(&mut bufmgr).unwrap();
+        let mut txn = manager.begin(&mut bufmgr).unwrap();
+        txn.delete(&mut bufmgr, &table, &[b"c"]).unwrap();
+        txn.commit(&mut bufmgr).unwrap();
+        let mut txn = manager.begin(&mut bufmgr).unwrap();
+        let err = catalog
+            .load_table(&mut bufmgr, "books", &duplicates, &mut txn)
+            .unwrap_err();
+        assert!(matches!(err.downcast(), Ok(Error::TableNotEmpty(_))));
+        txn.rollback(&mut bufmgr).unwrap();
+        reader.commit(&mut bufmgr).unwrap();
+        let mut txn = manager.begin(&mut bufmgr).unwrap();
+        assert!(catalog
+            .load_table(&mut bufmgr, "books", &duplicates, &mut txn)
+            .is_err());
+        txn.rollback(&mut bufmgr).unwrap();
+
+        // the loaded records belong to the loading transaction
+        let records = [record("a", "Ann"), record("b", "Ben")];
+        let mut txn = manager.begin(&mut bufmgr).unwrap();
+        let num_free_pages = bufmgr.num_free_pages();
+        // the trees keep their meta pages, so the schema stays as it was
+        assert_eq!(
+            books,
+            catalog
+                .load_table(&mut bufmgr, "books", &records, &mut txn)
+                .unwrap()
+        );
+        assert_eq!(num_free_pages + 2, bufmgr.num_free_pages());
+        assert_eq!(
+            Some(record("a", "Ann")),
+            txn.get(&mut bufmgr, &table, &[b"a"]).unwrap()
+        );
+        txn.rollback(&mut bufmgr).unwrap();
+        assert!(table.get(&mut bufmgr, &[b"a"]).unwrap().is_none());
+
+        let reader = manager.begin(&mut bufmgr).unwrap();
+        let mut txn = manager.begin(&mut bufmgr).unwrap();
+        catalog
+            .load_table(&mut bufmgr, "books", &records, &mut txn)
+            .unwrap();
+        txn.commit(&mut bufmgr).unwrap();
+        assert!(reader.get(&mut bufmgr, &table, &[b"a"]).unwrap().is_none());
+        reader.commit(&mut bufmgr).unwrap();
+        let txn = manager.begin(&mut bufmgr).unwrap();
+        assert_eq!(
+            Some(record("b", "Ben")),
+            txn.get(&mut bufmgr, &table, &[b"b"]).unwrap()
+        );
+        txn.commit(&mut bufmgr).unwrap();
+        let mut pkey = vec![];
+        tuple::encode([b"b"].iter(), &mut pkey);
+        assert_eq!(
+            vec![pkey],
+            table.unique_indices[0]
+                .lookup(&mut bufmgr, &record("b", "Ben"))
+                .unwrap()
+        );
+    }
+}
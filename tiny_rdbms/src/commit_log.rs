@@ -0,0 +1,130 @@
+use anyhow::Result;
+
+use crate::buffer_pool_manager::BufferPoolManager;
+use crate::mvcc::{Xid, FROZEN_XID};
+use crate::relly::btree::{BTree, SearchMode};
+
+// the outcome of every transaction, two bits per xid, kept in blocks of `BLOCK_SIZE` bytes that
+// are stored under their big-endian block number; the tree is created by the first outcome
+const BLOCK_SIZE: usize = 1024;
+const XIDS_PER_BLOCK: u64 = BLOCK_SIZE as u64 * 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    // still running, or cut off by a crash
+    InProgress,
+    Committed,
+    Aborted,
+}
+
+pub struct CommitLog {
+    btree: Option<BTree>,
+    blocks: Vec<Vec<u8>>,
+}
+
+impl CommitLog {
+    pub fn open(bufmgr: &mut BufferPoolManager) -> Result<Self> {
+        let btree = bufmgr.commit_log_page_id().valid().map(BTree::new);
+        let mut blocks = vec![];
+        if let Some(btree) = &btree {
+            let mut iter = btree.search(bufmgr, SearchMode::Start)?;
+            while let Some((key, block)) = iter.next(bufmgr)? {
+                let block_number = u64::from_be_bytes(key.as_slice().try_into()?) as usize;
+                blocks.resize(block_number + 1, vec![0; BLOCK_SIZE]);
+                blocks[block_number] = block;
+            }
+        }
+        Ok(Self { btree, blocks })
+    }
+
+    pub fn status(&self, xid: Xid) -> Status {
+        if xid == FROZEN_XID {
+            return Status::Committed;
+        }
+        let (block_number, byte, shift) = Self::locate(xid);
+        let bits = self
+            .blocks
+            .get(block_number)
+            .map_or(0, |block| (block[byte] >> shift) & 0b11);
+        match bits {
+            0b01 => Status::Committed,
+            0b10 => Status::Aborted,
+            _ => Status::InProgress,
+        }
+    }
+
+    // the new status is durable with the next flush
+    pub fn set_status(
+        &mut self,
+        bufmgr: &mut BufferPoolManager,
+        xid: Xid,
+        status: Status,
+    ) -> Result<()> {
+        let btree = match &self.btree {
+            Some(btree) => btree.clone(),
+            None => {
+                let btree = BTree::create(bufmgr)?;
+                bufmgr.set_commit_log_page_id(btree.meta_page_id);
+                self.btree = Some(btree.clone());
+                btree
+            }
+        };
+        let (block_number, byte, shift) = Self::locate(xid);
+        if self.blocks.len() <= block_number {
+            self.blocks.resize(block_number + 1, vec![0; BLOCK_SIZE]);
+        }
+        let bits = match status {
+            Status::InProgress => 0b00,
+            Status::Committed => 0b01,
+            Status::Aborted => 0b10,
+        };
+        let block = &mut self.blocks[block_number];
+        block[byte] = (block[byte] & !(0b11 << shift)) | (bits << shift);
+        btree.upsert(bufmgr, &(block_number as u64).to_be_bytes(), block)?;
+        Ok(())
+    }
+
+    fn locate(xid: Xid) -> (usize, usize, u32) {
+        let block_number = (xid / XIDS_PER_BLOCK) as usize;
+        let index = (xid % XIDS_PER_BLOCK) as usize;
+        (block_number, index / 4, (index % 4) as u32 * 2)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer_pool_manager::BufferPool;
+    use crate::disk_manager::{self, DiskManager};
+
+    #[test]
+    fn test_status() {
+        let file = disk_manager::tempfile().unwrap();
+        let outcomes = [
+            (1, Status::Committed),
+            (2, Status::Aborted),
+            (4, Status::Committed),
+            (XIDS_PER_BLOCK * 3 + 1, Status::Aborted),
+        ];
+        {
+            let disk = DiskManager::new(file.try_clone().unwrap()).unwrap();
+            let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+            let mut clog = CommitLog::open(&mut bufmgr).unwrap();
+            assert_eq!(Status::Committed, clog.status(FROZEN_XID));
+            assert_eq!(Status::InProgress, clog.status(1));
+            for (xid, status) in outcomes {
+                clog.set_status(&mut bufmgr, xid, status).unwrap();
+            }
+            bufmgr.flush().unwrap();
+        }
+
+        let disk = DiskManager::new(file).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let clog = CommitLog::open(&mut bufmgr).unwrap();
+        for (xid, status) in outcomes {
+            assert_eq!(status, clog.status(xid));
+        }
+        assert_eq!(Status::InProgress, clog.status(3));
+        assert_eq!(Status::InProgress, clog.status(XIDS_PER_BLOCK * 3));
+    }
+}
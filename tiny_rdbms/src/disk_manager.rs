@@ -1,16 +1,95 @@
 use std::fs::{File, OpenOptions};
 use std::io::{self, prelude::*, SeekFrom};
 use std::path::Path;
+
+use serde::{Deserialize, Serialize};
 use zerocopy::{AsBytes, FromBytes};
 
+use crate::wal::Wal;
+
 pub struct DiskManager {
     heap_file: File,
     next_page_id: u64,
+    free_list_head: PageId,
+    num_free_pages: u64,
+    next_xid: u64,
+    commit_log_page_id: PageId,
+    header_dirty: bool,
+    wal: Option<Wal>,
 }
 
 pub const PAGE_SIZE: usize = 4096;
+pub const CHECKPOINT_THRESHOLD: usize = 1000;
+pub const HEADER_PAGE_ID: PageId = PageId(0);
+
+const MAGIC: [u8; 8] = *b"TRDMS\0\0\x07";
+
+// every page is stored followed by a checksum over its page id and contents, so that torn,
+// corrupted or misplaced pages are caught when they are read back
+pub const CHECKSUM_SIZE: usize = 4;
+const SLOT_SIZE: u64 = (PAGE_SIZE + CHECKSUM_SIZE) as u64;
+
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                0xedb8_8320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+#[derive(Debug, thiserror::Error)]
+#[error("checksum mismatch in page {0:?}")]
+pub struct ChecksumMismatch(pub PageId);
+
+pub fn checksum(page_id: PageId, data: &[u8]) -> [u8; CHECKSUM_SIZE] {
+    let crc = page_id
+        .as_bytes()
+        .iter()
+        .chain(data)
+        .fold(!0u32, |crc, &byte| {
+            CRC_TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8)
+        });
+    (!crc).to_le_bytes()
+}
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, FromBytes, AsBytes)]
+// only whole pages carry a checksum; partial reads such as following the free list skip it
+pub fn verify_checksum(page_id: PageId, data: &[u8], stored: &[u8]) -> io::Result<()> {
+    if checksum(page_id, data) != stored {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            ChecksumMismatch(page_id),
+        ));
+    }
+    Ok(())
+}
+
+// page 0 of every heap file; free pages are chained through their first 8 bytes, `next_xid`
+// keeps transaction ids growing across restarts, and `commit_log_page_id` is the meta page of
+// the tree that records how transactions ended
+#[derive(Debug, FromBytes, AsBytes)]
+#[repr(C)]
+struct Header {
+    magic: [u8; 8],
+    num_pages: u64,
+    free_list_head: PageId,
+    num_free_pages: u64,
+    next_xid: u64,
+    commit_log_page_id: PageId,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, FromBytes, AsBytes, Serialize, Deserialize)]
 #[repr(C)]
 pub struct PageId(pub u64);
 
@@ -51,43 +130,325 @@ impl From<&[u8]> for PageId {
 
 impl DiskManager {
     pub fn new(heap_file: File) -> io::Result<Self> {
-        let heap_file_size = heap_file.metadata()?.len();
-        let next_page_id = heap_file_size / PAGE_SIZE as u64;
-        Ok(Self {
+        Self::init(heap_file, None)
+    }
+
+    pub fn with_wal(heap_file: File, log_file: File) -> io::Result<Self> {
+        Self::init(heap_file, Some(Wal::open(log_file)?))
+    }
+
+    fn init(heap_file: File, wal: Option<Wal>) -> io::Result<Self> {
+        let mut disk = Self {
             heap_file,
-            next_page_id,
-        })
+            next_page_id: HEADER_PAGE_ID.to_u64() + 1,
+            free_list_head: PageId::INVALID_PAGE_ID,
+            num_free_pages: 0,
+            next_xid: 1,
+            commit_log_page_id: PageId::INVALID_PAGE_ID,
+            header_dirty: true,
+            wal,
+        };
+        disk.checkpoint()?;
+        if disk.heap_file.metadata()?.len() > 0 {
+            disk.read_header()?;
+        }
+        Ok(disk)
     }
 
     pub fn open(heap_file_path: impl AsRef<Path>) -> io::Result<Self> {
-        let heap_file = OpenOptions::new()
-            .read(true)
-            .write(true)
-            .create(true)
-            .open(heap_file_path)?;
-        Self::new(heap_file)
+        let heap_file_path = heap_file_path.as_ref();
+        let mut log_file_path = heap_file_path.as_os_str().to_owned();
+        log_file_path.push(".wal");
+        let open = |path: &Path| {
+            OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(path)
+        };
+        Self::with_wal(open(heap_file_path)?, open(log_file_path.as_ref())?)
+    }
+
+    fn read_header(&mut self) -> io::Result<()> {
+        let mut page = vec![0u8; PAGE_SIZE];
+        self.read_page_data(HEADER_PAGE_ID, &mut page)?;
+        let header = Header::read_from_prefix(page.as_slice()).unwrap();
+        if header.magic != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a tiny_rdbms heap file",
+            ));
+        }
+        self.next_page_id = header.num_pages;
+        self.free_list_head = header.free_list_head;
+        self.num_free_pages = header.num_free_pages;
+        self.next_xid = header.next_xid;
+        self.commit_log_page_id = header.commit_log_page_id;
+        self.header_dirty = false;
+        Ok(())
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        let header = Header {
+            magic: MAGIC,
+            num_pages: self.next_page_id,
+            free_list_head: self.free_list_head,
+            num_free_pages: self.num_free_pages,
+            next_xid: self.next_xid,
+            commit_log_page_id: self.commit_log_page_id,
+        };
+        let mut page = vec![0u8; PAGE_SIZE];
+        page[..header.as_bytes().len()].copy_from_slice(header.as_bytes());
+        self.write_page_data(HEADER_PAGE_ID, &page)?;
+        self.header_dirty = false;
+        Ok(())
+    }
+
+    pub fn num_pages(&self) -> u64 {
+        self.next_page_id
+    }
+
+    pub fn num_free_pages(&self) -> u64 {
+        self.num_free_pages
+    }
+
+    pub fn next_xid(&self) -> u64 {
+        self.next_xid
+    }
+
+    pub fn set_next_xid(&mut self, next_xid: u64) {
+        if next_xid != self.next_xid {
+            self.next_xid = next_xid;
+            self.header_dirty = true;
+        }
+    }
+
+    pub fn commit_log_page_id(&self) -> PageId {
+        self.commit_log_page_id
+    }
+
+    pub fn set_commit_log_page_id(&mut self, page_id: PageId) {
+        self.commit_log_page_id = page_id;
+        self.header_dirty = true;
     }
 
-    pub fn allocate_page(&mut self) -> PageId {
+    pub fn allocate_page(&mut self) -> io::Result<PageId> {
+        self.header_dirty = true;
+        if let Some(page_id) = self.free_list_head.valid() {
+            let mut next = [0u8; 8];
+            self.read_page_data(page_id, &mut next)?;
+            self.free_list_head = PageId::from(&next[..]);
+            self.num_free_pages -= 1;
+            return Ok(page_id);
+        }
         let page_id = self.next_page_id;
         self.next_page_id += 1;
-        PageId(page_id)
+        Ok(PageId(page_id))
+    }
+
+    pub fn deallocate_page(&mut self, page_id: PageId) -> io::Result<()> {
+        // such ids come from corrupted pointers rather than from `allocate_page`
+        if page_id == HEADER_PAGE_ID || page_id.to_u64() >= self.next_page_id {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("cannot free page {:?}", page_id),
+            ));
+        }
+        let mut page = vec![0u8; PAGE_SIZE];
+        page[..8].copy_from_slice(self.free_list_head.as_bytes());
+        self.write_page_data(page_id, &page)?;
+        self.free_list_head = page_id;
+        self.num_free_pages += 1;
+        self.header_dirty = true;
+        Ok(())
+    }
+
+    // relinks the free list in ascending order and gives trailing free pages back to the file
+    // system; returns the number of pages removed from the end of the file
+    pub fn vacuum(&mut self) -> io::Result<u64> {
+        let mut free_pages = vec![];
+        let mut next = [0u8; 8];
+        while let Some(page_id) = self.free_list_head.valid() {
+            self.read_page_data(page_id, &mut next)?;
+            self.free_list_head = PageId::from(&next[..]);
+            free_pages.push(page_id.to_u64());
+        }
+        free_pages.sort_unstable();
+        let num_pages = self.next_page_id;
+        while free_pages.last() == Some(&(self.next_page_id - 1)) {
+            free_pages.pop();
+            self.next_page_id -= 1;
+        }
+        self.num_free_pages = 0;
+        for &page_id in free_pages.iter().rev() {
+            self.deallocate_page(PageId(page_id))?;
+        }
+        self.header_dirty = true;
+        self.sync()?;
+        self.checkpoint()?;
+        self.heap_file.set_len(self.next_page_id * SLOT_SIZE)?;
+        self.heap_file.sync_all()?;
+        Ok(num_pages - self.next_page_id)
     }
 
     pub fn read_page_data(&mut self, page_id: PageId, data: &mut [u8]) -> io::Result<()> {
-        let offset = PAGE_SIZE as u64 * page_id.to_u64();
-        self.heap_file.seek(SeekFrom::Start(offset))?;
-        self.heap_file.read_exact(data)
+        if let Some(wal) = &mut self.wal {
+            if wal.read(page_id, data)? {
+                return Ok(());
+            }
+        }
+        self.heap_file
+            .seek(SeekFrom::Start(SLOT_SIZE * page_id.to_u64()))?;
+        self.heap_file.read_exact(data)?;
+        if data.len() == PAGE_SIZE {
+            let mut stored = [0u8; CHECKSUM_SIZE];
+            self.heap_file.read_exact(&mut stored)?;
+            verify_checksum(page_id, data, &stored)?;
+        }
+        Ok(())
     }
 
     pub fn write_page_data(&mut self, page_id: PageId, data: &[u8]) -> io::Result<()> {
-        let offset = PAGE_SIZE as u64 * page_id.to_u64();
-        self.heap_file.seek(SeekFrom::Start(offset))?;
-        self.heap_file.write_all(data)
+        match &mut self.wal {
+            Some(wal) => wal.append(page_id, data),
+            None => write_heap_page(&mut self.heap_file, page_id, data),
+        }
     }
 
     pub fn sync(&mut self) -> io::Result<()> {
+        if self.header_dirty {
+            self.write_header()?;
+        }
+        if let Some(wal) = &mut self.wal {
+            wal.commit()?;
+            if wal.num_frames() >= CHECKPOINT_THRESHOLD {
+                self.checkpoint()?;
+            }
+            return Ok(());
+        }
         self.heap_file.flush()?;
         self.heap_file.sync_all()
     }
+
+    pub fn checkpoint(&mut self) -> io::Result<()> {
+        let wal = match &mut self.wal {
+            Some(wal) => wal,
+            None => return Ok(()),
+        };
+        if wal.num_frames() == 0 {
+            return Ok(());
+        }
+        let heap_file = &mut self.heap_file;
+        wal.checkpoint(|page_id, data| write_heap_page(heap_file, page_id, data))?;
+        heap_file.sync_all()?;
+        wal.reset()
+    }
+}
+
+fn write_heap_page(heap_file: &mut File, page_id: PageId, data: &[u8]) -> io::Result<()> {
+    heap_file.seek(SeekFrom::Start(SLOT_SIZE * page_id.to_u64()))?;
+    heap_file.write_all(data)?;
+    heap_file.write_all(&checksum(page_id, data))
+}
+
+#[cfg(test)]
+pub fn tempfile() -> io::Result<File> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let path = std::env::temp_dir().join(format!(
+        "tiny_rdbms-{}-{}",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(&path)?;
+    std::fs::remove_file(&path)?;
+    Ok(file)
+}
+
+#[cfg(test)]
+pub fn temp_bufmgr() -> crate::buffer_pool_manager::BufferPoolManager {
+    use crate::buffer_pool_manager::{BufferPool, BufferPoolManager};
+
+    let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+    BufferPoolManager::new(disk, BufferPool::new(10))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_free_list() {
+        let heap_file = tempfile().unwrap();
+        let mut disk = DiskManager::new(heap_file.try_clone().unwrap()).unwrap();
+        let page_ids: Vec<_> = (0..6).map(|_| disk.allocate_page().unwrap()).collect();
+        assert_eq!(PageId(1), page_ids[0]);
+        for &page_id in &page_ids {
+            disk.write_page_data(page_id, &[page_id.0 as u8; PAGE_SIZE])
+                .unwrap();
+        }
+        for i in [1, 4, 5] {
+            disk.deallocate_page(page_ids[i]).unwrap();
+        }
+        assert_eq!(page_ids[5], disk.allocate_page().unwrap());
+        disk.deallocate_page(page_ids[5]).unwrap();
+        for page_id in [HEADER_PAGE_ID, PageId(7)] {
+            let err = disk.deallocate_page(page_id).unwrap_err();
+            assert_eq!(io::ErrorKind::InvalidInput, err.kind());
+        }
+        disk.sync().unwrap();
+
+        let mut disk = DiskManager::new(heap_file.try_clone().unwrap()).unwrap();
+        assert_eq!(7, disk.num_pages());
+        assert_eq!(3, disk.num_free_pages());
+        assert_eq!(2, disk.vacuum().unwrap());
+        assert_eq!(5 * SLOT_SIZE, heap_file.metadata().unwrap().len());
+        assert_eq!(page_ids[1], disk.allocate_page().unwrap());
+        assert_eq!(PageId(5), disk.allocate_page().unwrap());
+
+        let mut data = vec![0u8; PAGE_SIZE];
+        disk.read_page_data(page_ids[2], &mut data).unwrap();
+        assert_eq!(vec![page_ids[2].0 as u8; PAGE_SIZE], data);
+    }
+
+    #[test]
+    fn test_checksum() {
+        let mut heap_file = tempfile().unwrap();
+        let mut disk = DiskManager::new(heap_file.try_clone().unwrap()).unwrap();
+        let page_id = disk.allocate_page().unwrap();
+        let other_page_id = disk.allocate_page().unwrap();
+        let data = [7u8; PAGE_SIZE];
+        disk.write_page_data(page_id, &data).unwrap();
+        disk.write_page_data(other_page_id, &data).unwrap();
+        disk.sync().unwrap();
+
+        let mut read = vec![0u8; PAGE_SIZE];
+        disk.read_page_data(page_id, &mut read).unwrap();
+        heap_file
+            .seek(SeekFrom::Start(SLOT_SIZE * page_id.to_u64() + 100))
+            .unwrap();
+        heap_file.write_all(&[8]).unwrap();
+        let err = disk.read_page_data(page_id, &mut read).unwrap_err();
+        let mismatch = err.get_ref().unwrap().downcast_ref::<ChecksumMismatch>();
+        assert_eq!(Some(page_id), mismatch.map(|mismatch| mismatch.0));
+
+        // a page written to the wrong slot does not verify either
+        let mut slot = vec![0u8; SLOT_SIZE as usize];
+        heap_file
+            .seek(SeekFrom::Start(SLOT_SIZE * other_page_id.to_u64()))
+            .unwrap();
+        heap_file.read_exact(&mut slot).unwrap();
+        heap_file
+            .seek(SeekFrom::Start(SLOT_SIZE * page_id.to_u64()))
+            .unwrap();
+        heap_file.write_all(&slot).unwrap();
+        assert!(disk.read_page_data(page_id, &mut read).is_err());
+        disk.read_page_data(other_page_id, &mut read).unwrap();
+    }
 }
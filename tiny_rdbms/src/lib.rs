@@ -0,0 +1,13 @@
+pub mod buffer_pool_manager;
+pub mod catalog;
+pub mod commit_log;
+pub mod disk_manager;
+pub mod lock_manager;
+pub mod mvcc;
+pub mod net;
+pub mod query_executor;
+pub mod relly;
+pub mod sql;
+pub mod table;
+pub mod transaction;
+pub mod wal;
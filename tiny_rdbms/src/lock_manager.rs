@@ -0,0 +1,420 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Condvar, Mutex};
+
+use crate::disk_manager::PageId;
+use crate::mvcc::Xid;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("deadlock detected: transaction {0} must be rolled back")]
+    Deadlock(Xid),
+    #[error("waiting for a lock: transaction {0} holds a conflicting one")]
+    Blocked(Xid),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LockMode {
+    Shared,
+    Exclusive,
+}
+
+// a table by its meta page, or one of its records by encoded primary key
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum LockTarget {
+    Table(PageId),
+    Record(PageId, Vec<u8>),
+}
+
+// record locks take the matching intention lock on their table first, so that a table lock
+// conflicts with the record locks below it
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Mode {
+    IntentionShared,
+    IntentionExclusive,
+    Shared,
+    Exclusive,
+}
+
+impl Mode {
+    fn is_compatible(self, other: Mode) -> bool {
+        use Mode::*;
+        match (self, other) {
+            (Exclusive, _) | (_, Exclusive) => false,
+            (IntentionShared, _) | (_, IntentionShared) => true,
+            (a, b) => a == b,
+        }
+    }
+
+    fn covers(self, other: Mode) -> bool {
+        use Mode::*;
+        match (self, other) {
+            (Exclusive, _) => true,
+            (Shared, Shared | IntentionShared) => true,
+            (IntentionExclusive, IntentionExclusive | IntentionShared) => true,
+            (a, b) => a == b,
+        }
+    }
+
+    // the weakest mode held along with a new one; shared with intention exclusive has no mode of
+    // its own here and becomes exclusive
+    fn combine(self, other: Mode) -> Mode {
+        if self.covers(other) {
+            self
+        } else if other.covers(self) {
+            other
+        } else {
+            Mode::Exclusive
+        }
+    }
+}
+
+impl From<LockMode> for Mode {
+    fn from(mode: LockMode) -> Self {
+        match mode {
+            LockMode::Shared => Mode::Shared,
+            LockMode::Exclusive => Mode::Exclusive,
+        }
+    }
+}
+
+#[derive(Default)]
+struct State {
+    locks: HashMap<LockTarget, HashMap<Xid, Mode>>,
+    held: HashMap<Xid, Vec<LockTarget>>,
+    // what each blocked transaction is waiting for; the edges of the wait-for graph follow
+    // from it and the current holders
+    waiting: HashMap<Xid, (LockTarget, Mode)>,
+}
+
+impl State {
+    fn blockers(&self, xid: Xid, target: &LockTarget, mode: Mode) -> Vec<Xid> {
+        self.locks.get(target).map_or(vec![], |holders| {
+            holders
+                .iter()
+                .filter(|&(&holder, &held)| holder != xid && !held.is_compatible(mode))
+                .map(|(&holder, _)| holder)
+                .collect()
+        })
+    }
+
+    fn waits_for(&self, from: Xid, to: Xid, visited: &mut HashSet<Xid>) -> bool {
+        let Some((target, mode)) = self.waiting.get(&from) else {
+            return false;
+        };
+        self.blockers(from, target, *mode)
+            .into_iter()
+            .any(|blocker| {
+                blocker == to || (visited.insert(blocker) && self.waits_for(blocker, to, visited))
+            })
+    }
+
+    fn grant(&mut self, xid: Xid, target: &LockTarget, mode: Mode) {
+        let holders = self.locks.entry(target.clone()).or_default();
+        match holders.get_mut(&xid) {
+            Some(held) => *held = held.combine(mode),
+            None => {
+                holders.insert(xid, mode);
+                self.held.entry(xid).or_default().push(target.clone());
+            }
+        }
+    }
+}
+
+// strict two-phase locking: a transaction acquires locks as it goes and gives all of them up
+// at once when it ends, so there is no way to release a single lock. Waiters are not queued
+// fairly; they retry whenever some transaction releases its locks.
+//
+// Transactions on their own threads block in `lock_table` and `lock_record`. Where every
+// transaction runs on one thread, as in the server's engine, blocking would never end, so
+// `poll_lock_table` and `poll_lock_record` record the wait and return `Error::Blocked`, and the
+// caller runs the request again once some transaction has ended. Either way the request that
+// closes a cycle of waits fails with `Error::Deadlock`
+#[derive(Default)]
+pub struct LockManager {
+    state: Mutex<State>,
+    released: Condvar,
+}
+
+impl LockManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn lock_table(&self, xid: Xid, table: PageId, mode: LockMode) -> Result<(), Error> {
+        self.acquire(xid, LockTarget::Table(table), mode.into(), true)
+    }
+
+    pub fn lock_record(
+        &self,
+        xid: Xid,
+        table: PageId,
+        key: &[u8],
+        mode: LockMode,
+    ) -> Result<(), Error> {
+        self.acquire_record(xid, table, key, mode, true)
+    }
+
+    // like `lock_table`, but returns `Error::Blocked` instead of waiting; the wait stays in the
+    // wait-for graph until the transaction gets a lock, ends, or calls `stop_waiting`
+    pub fn poll_lock_table(&self, xid: Xid, table: PageId, mode: LockMode) -> Result<(), Error> {
+        self.acquire(xid, LockTarget::Table(table), mode.into(), false)
+    }
+
+    pub fn poll_lock_record(
+        &self,
+        xid: Xid,
+        table: PageId,
+        key: &[u8],
+        mode: LockMode,
+    ) -> Result<(), Error> {
+        self.acquire_record(xid, table, key, mode, false)
+    }
+
+    fn acquire_record(
+        &self,
+        xid: Xid,
+        table: PageId,
+        key: &[u8],
+        mode: LockMode,
+        wait: bool,
+    ) -> Result<(), Error> {
+        let intention = match mode {
+            LockMode::Shared => Mode::IntentionShared,
+            LockMode::Exclusive => Mode::IntentionExclusive,
+        };
+        self.acquire(xid, LockTarget::Table(table), intention, wait)?;
+        self.acquire(
+            xid,
+            LockTarget::Record(table, key.to_vec()),
+            mode.into(),
+            wait,
+        )
+    }
+
+    // blocks until the lock is granted, or returns at once when `wait` is off; a request that
+    // would close a cycle in the wait-for graph fails instead, and the caller has to roll back
+    // and release what it holds
+    fn acquire(&self, xid: Xid, target: LockTarget, mode: Mode, wait: bool) -> Result<(), Error> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            let blockers = state.blockers(xid, &target, mode);
+            if blockers.is_empty() {
+                state.waiting.remove(&xid);
+                state.grant(xid, &target, mode);
+                return Ok(());
+            }
+            state.waiting.insert(xid, (target.clone(), mode));
+            if state.waits_for(xid, xid, &mut HashSet::new()) {
+                state.waiting.remove(&xid);
+                return Err(Error::Deadlock(xid));
+            }
+            if !wait {
+                return Err(Error::Blocked(blockers[0]));
+            }
+            state = self.released.wait(state).unwrap();
+        }
+    }
+
+    // forgets the wait a `poll_` call recorded, for a transaction that gave up on the request
+    pub fn stop_waiting(&self, xid: Xid) {
+        self.state.lock().unwrap().waiting.remove(&xid);
+    }
+
+    pub fn release_all(&self, xid: Xid) {
+        let mut state = self.state.lock().unwrap();
+        state.waiting.remove(&xid);
+        for target in state.held.remove(&xid).unwrap_or_default() {
+            if let Some(holders) = state.locks.get_mut(&target) {
+                holders.remove(&xid);
+                if holders.is_empty() {
+                    state.locks.remove(&target);
+                }
+            }
+        }
+        self.released.notify_all();
+    }
+
+    pub fn held(&self, xid: Xid) -> Vec<LockTarget> {
+        let state = self.state.lock().unwrap();
+        state.held.get(&xid).cloned().unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::{mpsc, Barrier};
+    use std::thread;
+
+    use super::*;
+    use crate::buffer_pool_manager::{BufferPool, BufferPoolManager};
+    use crate::disk_manager::{self, DiskManager};
+    use crate::table::SimpleTable;
+
+    type Job = Box<dyn FnOnce(&mut BufferPoolManager) + Send>;
+
+    // the buffer pool manager cannot move between threads, so one thread owns it and runs what
+    // the workers send, while the workers do their own locking
+    fn call<R: Send + 'static>(
+        jobs: &mpsc::Sender<Job>,
+        f: impl FnOnce(&mut BufferPoolManager) -> R + Send + 'static,
+    ) -> R {
+        let (reply, result) = mpsc::channel();
+        jobs.send(Box::new(move |bufmgr| reply.send(f(bufmgr)).unwrap()))
+            .unwrap();
+        result.recv().unwrap()
+    }
+
+    fn serve(jobs: mpsc::Receiver<Job>) {
+        let disk = DiskManager::new(disk_manager::tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        for job in jobs {
+            job(&mut bufmgr);
+        }
+    }
+
+    fn balance(bufmgr: &mut BufferPoolManager, table: &SimpleTable, account: &[u8]) -> i64 {
+        let record = table.get(bufmgr, &[account]).unwrap().unwrap();
+        std::str::from_utf8(&record[1]).unwrap().parse().unwrap()
+    }
+
+    #[test]
+    fn test_deadlock() {
+        let locks = LockManager::new();
+        let table = PageId(7);
+        let barrier = Barrier::new(2);
+        let results: Vec<_> = thread::scope(|scope| {
+            let workers: Vec<_> = [(1, b"a"), (2, b"b")]
+                .into_iter()
+                .map(|(xid, key)| {
+                    let (locks, barrier) = (&locks, &barrier);
+                    scope.spawn(move || {
+                        locks
+                            .lock_record(xid, table, key, LockMode::Exclusive)
+                            .unwrap();
+                        barrier.wait();
+                        // 1 waits for the intention lock 2 holds on the table, and 2 for the
+                        // record 1 holds
+                        let result = if xid == 1 {
+                            locks.lock_table(xid, table, LockMode::Shared)
+                        } else {
+                            locks.lock_record(xid, table, b"a", LockMode::Exclusive)
+                        };
+                        let held = locks.held(xid).len();
+                        locks.release_all(xid);
+                        let result = result.map_err(|err| match err {
+                            Error::Deadlock(victim) => victim,
+                            err => panic!("unexpected error: {}", err),
+                        });
+                        (result, held)
+                    })
+                })
+                .collect();
+            workers.into_iter().map(|w| w.join().unwrap()).collect()
+        });
+        let victims: Vec<_> = results.iter().filter_map(|(r, _)| r.err()).collect();
+        assert_eq!(1, victims.len());
+        // the victim held the table and its record; 1 upgraded its table lock on success, while 2
+        // also got the record it waited for
+        let expected = if victims == [1] { [2, 3] } else { [2, 2] };
+        assert_eq!(
+            expected.to_vec(),
+            results.iter().map(|r| r.1).collect::<Vec<_>>()
+        );
+        assert!(locks.held(1).is_empty() && locks.held(2).is_empty());
+    }
+
+    #[test]
+    fn test_transfers() {
+        const ACCOUNTS: u64 = 4;
+        let locks = LockManager::new();
+        let next_xid = AtomicU64::new(1);
+        let (jobs, receiver) = mpsc::channel::<Job>();
+        thread::scope(|scope| {
+            scope.spawn(move || serve(receiver));
+            let table = call(&jobs, |bufmgr| {
+                let mut table = SimpleTable {
+                    meta_page_id: PageId::INVALID_PAGE_ID,
+                    num_key_elems: 1,
+                    unique_indices: vec![],
+                };
+                table.create(bufmgr).unwrap();
+                for account in 0..ACCOUNTS {
+                    table
+                        .insert(bufmgr, &[&account.to_be_bytes(), b"100"])
+                        .unwrap();
+                }
+                table
+            });
+
+            let mut workers = vec![];
+            for t in 0u64..4 {
+                let (locks, next_xid) = (&locks, &next_xid);
+                let (jobs, table) = (jobs.clone(), table.clone());
+                workers.push(scope.spawn(move || {
+                    let mut seed = t + 1;
+                    for _ in 0..50 {
+                        seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+                        let from = (seed >> 33) % ACCOUNTS;
+                        let to = (from + 1 + (seed >> 40) % (ACCOUNTS - 1)) % ACCOUNTS;
+                        let (from, to) = (from.to_be_bytes(), to.to_be_bytes());
+                        let xid = next_xid.fetch_add(1, Ordering::Relaxed);
+                        let lock = |account: &[u8]| {
+                            let key = table.encode_key(&[account]);
+                            locks.lock_record(xid, table.meta_page_id, &key, LockMode::Exclusive)
+                        };
+                        // transfers in opposite directions lock in opposite orders
+                        while lock(&from).and_then(|()| lock(&to)).is_err() {
+                            locks.release_all(xid);
+                        }
+                        for (account, amount) in [(from, -10), (to, 10)] {
+                            let (reader, writer) = (table.clone(), table.clone());
+                            let old = call(&jobs, move |bufmgr| balance(bufmgr, &reader, &account));
+                            thread::yield_now();
+                            call(&jobs, move |bufmgr| {
+                                let new = (old + amount).to_string();
+                                writer.update(bufmgr, &[&account, new.as_bytes()]).unwrap()
+                            });
+                        }
+                        locks.release_all(xid);
+                    }
+                }));
+            }
+
+            // audits lock the whole table, so they never see a transfer half done
+            let auditor = {
+                let (jobs, table, locks, next_xid) =
+                    (jobs.clone(), table.clone(), &locks, &next_xid);
+                scope.spawn(move || {
+                    for _ in 0..20 {
+                        let xid = next_xid.fetch_add(1, Ordering::Relaxed);
+                        locks
+                            .lock_table(xid, table.meta_page_id, LockMode::Shared)
+                            .unwrap();
+                        let table = table.clone();
+                        let total: i64 = call(&jobs, move |bufmgr| {
+                            (0..ACCOUNTS)
+                                .map(|account| balance(bufmgr, &table, &account.to_be_bytes()))
+                                .sum()
+                        });
+                        assert_eq!(100 * ACCOUNTS as i64, total);
+                        locks.release_all(xid);
+                        thread::yield_now();
+                    }
+                })
+            };
+            for worker in workers {
+                worker.join().unwrap();
+            }
+            auditor.join().unwrap();
+
+            let balances = call(&jobs, move |bufmgr| {
+                (0..ACCOUNTS)
+                    .map(|account| balance(bufmgr, &table, &account.to_be_bytes()))
+                    .collect::<Vec<_>>()
+            });
+            assert_eq!(100 * ACCOUNTS as i64, balances.iter().sum());
+            drop(jobs);
+        });
+    }
+}
@@ -1,76 +1,306 @@
-mod buffer_pool_manager;
-mod disk_manager;
-mod query_executor;
-mod relly;
-mod table;
+use std::cell::Ref;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::io::{self, BufRead, IsTerminal, Write};
 
-use anyhow::Result;
-use buffer_pool_manager::{BufferPool, BufferPoolManager};
-use disk_manager::{DiskManager, PageId};
-use query_executor::{Filter, PlanNode, SeqScan, TupleSearchMode};
-use relly::btree::{BTree, SearchMode};
-use relly::tuple;
-use table::SimpleTable;
+use anyhow::{bail, Result};
+use tiny_rdbms::buffer_pool_manager::{self, BufferPool, BufferPoolManager};
+use tiny_rdbms::catalog::{TableSchema, CATALOG_META_PAGE_ID};
+use tiny_rdbms::disk_manager::{DiskManager, PageId};
+use tiny_rdbms::relly::btree::{node, BTree};
+use tiny_rdbms::relly::value::Value;
+use tiny_rdbms::sql::format::{self, format_table};
+use tiny_rdbms::sql::{Database, QueryResult};
 
-fn main() -> Result<()> {
-    let heap_file_path = "simple.trdms";
-
-    let disk = DiskManager::open(heap_file_path)?;
-    let pool = BufferPool::new(10);
-    let mut buffer_manager = BufferPoolManager::new(disk, pool);
+const HELP: &str = "\
+SQL statements end with ';' and may span several lines.
+.tables           list the tables
+.schema [TABLE]   show CREATE TABLE statements
+.dump [TABLE]     print the database as SQL
+.pages            show the type and occupancy of every page
+.stats            show buffer pool counters
+.help             show this message
+.quit             exit";
 
-    let mut table = SimpleTable {
-        meta_page_id: PageId::INVALID_PAGE_ID,
-        num_key_elems: 1,
+fn main() -> Result<()> {
+    let path = match env::args().nth(1) {
+        Some(path) => path,
+        None => bail!("usage: tiny_rdbms <database file>"),
     };
+    let disk = DiskManager::open(&path)?;
+    let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(64));
+    let mut db = Database::open(&mut bufmgr)?;
+    // the shell reads files on behalf of whoever is at the terminal
+    db.set_file_access(true);
 
-    _ = table.create(&mut buffer_manager);
+    // prompts only make sense on a terminal; piped input is run as a script
+    let interactive = io::stdin().is_terminal();
+    if interactive {
+        println!("{}: enter .help for usage hints", path);
+    }
+    let mut sql = String::new();
+    let mut lines = io::stdin().lock().lines();
+    loop {
+        if interactive {
+            print!("{}", if sql.is_empty() { "trdms> " } else { "  ...> " });
+            io::stdout().flush()?;
+        }
+        let line = match lines.next() {
+            Some(line) => line?,
+            None => break,
+        };
+        if sql.is_empty() && line.trim_start().starts_with('.') {
+            match meta_command(&mut bufmgr, &mut db, line.trim()) {
+                Ok(true) => continue,
+                Ok(false) => break,
+                Err(err) => eprintln!("Error: {:#}", err),
+            }
+            continue;
+        }
+        if sql.is_empty() && line.trim().is_empty() {
+            continue;
+        }
+        sql.push_str(&line);
+        sql.push('\n');
+        if line.trim_end().ends_with(';') {
+            run(&mut bufmgr, &mut db, &sql);
+            sql.clear();
+        }
+    }
+    // the last statement of a script may leave out its semicolon
+    if !sql.trim().is_empty() {
+        run(&mut bufmgr, &mut db, &sql);
+    }
+    if db.in_transaction() {
+        eprintln!("rolling back the open transaction");
+        db.execute(&mut bufmgr, "ROLLBACK")?;
+    }
+    bufmgr.flush()?;
+    Ok(())
+}
+
+fn run(bufmgr: &mut BufferPoolManager, db: &mut Database, sql: &str) {
+    match db.execute(bufmgr, sql) {
+        Ok(result) => print_result(&result),
+        Err(err) => eprintln!("Error: {:#}", err),
+    }
+}
 
-    table.insert(&mut buffer_manager, &[b"z", b"Alice", b"Smith"])?;
-    table.insert(&mut buffer_manager, &[b"x", b"Bob", b"Johnson"])?;
-    table.insert(&mut buffer_manager, &[b"y", b"Charlie", b"Williams"])?;
-    table.insert(&mut buffer_manager, &[b"w", b"Dave", b"Miller"])?;
-    table.insert(&mut buffer_manager, &[b"v", b"Eve", b"Brown"])?;
+fn print_result(result: &QueryResult) {
+    match result {
+        QueryResult::Vacuum { versions, pages } => {
+            println!("VACUUM {} dead versions, {} pages", versions, pages)
+        }
+        QueryResult::Select { columns, rows, .. } => {
+            print!("{}", format_table(columns, rows));
+            println!("{}", format::row_count(rows.len()));
+        }
+        result => println!("{}", result.tag()),
+    }
+}
 
-    buffer_manager.flush()?;
+// returns false when the shell should exit
+fn meta_command(bufmgr: &mut BufferPoolManager, db: &mut Database, line: &str) -> Result<bool> {
+    let mut args = line.split_whitespace();
+    let command = args.next().unwrap_or_default();
+    let table = args.next();
+    match command {
+        ".quit" | ".exit" => return Ok(false),
+        ".help" => println!("{}", HELP),
+        ".tables" => {
+            for schema in db.catalog().list_tables(bufmgr)? {
+                println!("{}", schema.name);
+            }
+        }
+        ".schema" => {
+            for schema in tables(bufmgr, db, table)? {
+                println!("{}", create_table_sql(&schema));
+            }
+        }
+        ".dump" => {
+            for schema in tables(bufmgr, db, table)? {
+                dump(bufmgr, db, &schema)?;
+            }
+        }
+        ".pages" => pages(bufmgr, db)?,
+        ".stats" => {
+            let stats = bufmgr.stats();
+            println!("hits:        {}", stats.hits);
+            println!("misses:      {}", stats.misses);
+            println!("hit ratio:   {:.1}%", stats.hit_ratio() * 100.0);
+            println!("evictions:   {}", stats.evictions);
+            println!("write-backs: {}", stats.write_backs);
+            println!("pages:       {}", bufmgr.num_pages());
+            println!("free pages:  {}", bufmgr.num_free_pages());
+        }
+        _ => bail!("unknown command {}; enter .help for usage hints", command),
+    }
+    Ok(true)
+}
 
-    println!("== procedural");
-    {
-        let disk = DiskManager::open(heap_file_path)?;
-        let pool = BufferPool::new(10);
-        let mut bufmgr = BufferPoolManager::new(disk, pool);
+fn tables(
+    bufmgr: &mut BufferPoolManager,
+    db: &Database,
+    name: Option<&str>,
+) -> Result<Vec<TableSchema>> {
+    match name {
+        Some(name) => match db.catalog().get_table(bufmgr, name)? {
+            Some(schema) => Ok(vec![schema]),
+            None => bail!("no such table: {}", name),
+        },
+        None => db.catalog().list_tables(bufmgr),
+    }
+}
 
-        let btree = BTree::new(PageId(0));
-        let mut iter = btree.search(&mut bufmgr, SearchMode::Start)?;
+fn create_table_sql(schema: &TableSchema) -> String {
+    let columns: Vec<_> = schema
+        .columns
+        .iter()
+        .map(|column| format!("{} {}", column.name, column.data_type))
+        .collect();
+    let key: Vec<_> = schema.columns[..schema.num_key_elems]
+        .iter()
+        .map(|column| column.name.as_str())
+        .collect();
+    let mut sql = format!(
+        "CREATE TABLE {} ({}, PRIMARY KEY ({}));",
+        schema.name,
+        columns.join(", "),
+        key.join(", ")
+    );
+    // unique indices have no SQL syntax yet, so they can only be mentioned
+    for index in &schema.unique_indices {
+        let columns: Vec<_> = index
+            .skey
+            .iter()
+            .map(|&column| schema.columns[column].name.as_str())
+            .collect();
+        sql.push_str(&format!("\n-- UNIQUE ({})", columns.join(", ")));
+    }
+    sql
+}
 
-        while let Some((key, value)) = iter.next(&mut bufmgr)? {
-            let mut record = vec![];
-            tuple::decode(&key, &mut record);
-            tuple::decode(&value, &mut record);
-            println!("{:?}", tuple::Pretty(&record));
+fn dump(bufmgr: &mut BufferPoolManager, db: &mut Database, schema: &TableSchema) -> Result<()> {
+    println!("{}", create_table_sql(schema));
+    let rows = match db.execute(bufmgr, &format!("SELECT * FROM {}", schema.name))? {
+        QueryResult::Select { rows, .. } => rows,
+        _ => unreachable!(),
+    };
+    if rows.is_empty() {
+        return Ok(());
+    }
+    println!("BEGIN;");
+    for row in &rows {
+        let values: Option<Vec<_>> = row.iter().map(sql_literal).collect();
+        match values {
+            Some(values) => println!(
+                "INSERT INTO {} VALUES ({});",
+                schema.name,
+                values.join(", ")
+            ),
+            None => println!("-- skipped a row with bytes that are not valid UTF-8"),
         }
     }
+    println!("COMMIT;");
+    Ok(())
+}
 
-    println!("== executor");
-    {
-        let disk = DiskManager::open(heap_file_path)?;
-        let pool = BufferPool::new(10);
-        let mut bufmgr = BufferPoolManager::new(disk, pool);
-
-        let plan = Filter {
-            cond: &|record| record[1].as_slice() < b"Dave",
-            inner_plan: &SeqScan {
-                table_meta_page_id: PageId(0),
-                search_mode: TupleSearchMode::Key(&[b"w"]),
-                while_cond: &|pkey| pkey[0].as_slice() < b"z",
-            },
-        };
-        let mut exec = plan.start(&mut bufmgr)?;
+// the literal that reads back as `value`; bytes can only be written as a string literal, so
+// those that are not UTF-8 have none
+fn sql_literal(value: &Value) -> Option<String> {
+    let quote = |s: &str| format!("'{}'", s.replace('\'', "''"));
+    let literal = match value {
+        Value::Null => "NULL".to_string(),
+        Value::Bool(true) => "TRUE".to_string(),
+        Value::Bool(false) => "FALSE".to_string(),
+        Value::Float(v) if v.fract() == 0.0 && v.is_finite() => format!("{:.1}", v),
+        Value::Text(v) => quote(v),
+        Value::Bytes(v) => quote(std::str::from_utf8(v).ok()?),
+        value => value.to_string(),
+    };
+    Some(literal)
+}
 
-        while let Some(record) = exec.next(&mut bufmgr)? {
-            println!("{:?}", tuple::Pretty(&record));
+// the trees of every table and index, and the commit log, reach all live pages, so walking them
+// tells overflow pages from free ones and which tree each page belongs to
+fn pages(bufmgr: &mut BufferPoolManager, db: &Database) -> Result<()> {
+    let mut trees = vec![("catalog".to_string(), CATALOG_META_PAGE_ID)];
+    for schema in db.catalog().list_tables(bufmgr)? {
+        trees.push((schema.name.clone(), schema.meta_page_id));
+        for (i, index) in schema.unique_indices.iter().enumerate() {
+            trees.push((format!("{}#{}", schema.name, i + 1), index.meta_page_id));
+        }
+    }
+    if let Some(page_id) = bufmgr.commit_log_page_id().valid() {
+        trees.push(("commit log".to_string(), page_id));
+    }
+    let mut visited = HashSet::new();
+    let mut owners = HashMap::new();
+    for (name, meta_page_id) in &trees {
+        BTree::new(*meta_page_id).check(bufmgr, &mut visited)?;
+        for &page_id in &visited {
+            owners.entry(page_id).or_insert(name.as_str());
         }
     }
+    let meta_page_ids: HashSet<_> = trees.iter().map(|&(_, page_id)| page_id).collect();
 
+    let blank = || Value::Text(String::new());
+    let mut rows = vec![];
+    for page_id in (1..bufmgr.num_pages()).map(PageId) {
+        let owner = owners.get(&page_id).copied();
+        let (kind, pairs, used) = match owner {
+            None => ("free", blank(), blank()),
+            Some(_) if meta_page_ids.contains(&page_id) => ("meta", blank(), blank()),
+            Some(_) => match bufmgr.fetch_page(page_id) {
+                Ok(buffer) => {
+                    let node = node::Node::new(buffer.page.borrow() as Ref<[_]>);
+                    if node.is_node() {
+                        let kind = if node.header.node_type == node::NODE_TYPE_LEAF {
+                            "leaf"
+                        } else {
+                            "branch"
+                        };
+                        let body = node::Body::new(node.header.node_type, node.body);
+                        let used = body.capacity() - body.free_space();
+                        (
+                            kind,
+                            Value::UInt(body.num_pairs() as u64),
+                            Value::Text(format!("{}%", used * 100 / body.capacity())),
+                        )
+                    } else {
+                        ("overflow", blank(), blank())
+                    }
+                }
+                Err(buffer_pool_manager::Error::Corrupted(_)) => ("corrupted", blank(), blank()),
+                Err(err) => return Err(err.into()),
+            },
+        };
+        rows.push(vec![
+            Value::UInt(page_id.to_u64()),
+            Value::Text(kind.to_string()),
+            Value::Text(owner.unwrap_or_default().to_string()),
+            pairs,
+            used,
+        ]);
+    }
+    print!(
+        "{}",
+        format_table(&["page", "type", "tree", "pairs", "used"], &rows)
+    );
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sql_literal() {
+        let literal = |value| sql_literal(&value).unwrap();
+        assert_eq!("'it''s'", literal(Value::Text("it's".to_string())));
+        assert_eq!("2.0", literal(Value::Float(2.0)));
+        assert_eq!("-3", literal(Value::Int(-3)));
+        assert_eq!("NULL", literal(Value::Null));
+        assert_eq!(None, sql_literal(&Value::Bytes(vec![0xff])));
+    }
+}
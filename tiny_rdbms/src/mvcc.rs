@@ -0,0 +1,139 @@
+use std::collections::BTreeSet;
+
+use bincode::Options;
+use serde::{Deserialize, Serialize};
+
+pub type Xid = u64;
+
+// the creator of versions written outside any transaction; every snapshot sees it
+pub const FROZEN_XID: Xid = 0;
+
+// one state of a record: created by `xmin` and, once deleted or replaced, ended by `xmax`;
+// `data` holds the non-key columns
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Version {
+    pub xmin: Xid,
+    pub xmax: Option<Xid>,
+    pub data: Vec<u8>,
+}
+
+impl Version {
+    pub fn frozen(data: Vec<u8>) -> Self {
+        Self {
+            xmin: FROZEN_XID,
+            xmax: None,
+            data,
+        }
+    }
+}
+
+// the versions of a record, newest first, as stored under its primary key
+pub fn encode(versions: &[Version]) -> Vec<u8> {
+    bincode::options().serialize(versions).unwrap()
+}
+
+pub fn decode(bytes: &[u8]) -> bincode::Result<Vec<Version>> {
+    bincode::options().deserialize(bytes)
+}
+
+// the newest version that has not been deleted, regardless of who wrote it
+pub fn latest(versions: &[Version]) -> Option<&Version> {
+    versions.first().filter(|version| version.xmax.is_none())
+}
+
+// the transactions whose effects a reader sees: its own, and those that had committed when the
+// snapshot was taken
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    pub xid: Option<Xid>,
+    pub xmax: Xid,
+    pub active: BTreeSet<Xid>,
+}
+
+impl Snapshot {
+    pub fn sees(&self, xid: Xid) -> bool {
+        Some(xid) == self.xid || (xid < self.xmax && !self.active.contains(&xid))
+    }
+
+    pub fn is_visible(&self, version: &Version) -> bool {
+        self.sees(version.xmin) && !version.xmax.is_some_and(|xmax| self.sees(xmax))
+    }
+
+    // an update ends the old version with the xid that creates the new one, so at most one
+    // version of a record is visible
+    pub fn visible<'a>(&self, versions: &'a [Version]) -> Option<&'a Version> {
+        versions.iter().find(|version| self.is_visible(version))
+    }
+
+    // no other transaction has touched the newest version since the snapshot was taken, so it
+    // is safe to write over
+    pub fn can_write(&self, versions: &[Version]) -> bool {
+        versions.first().is_none_or(|newest| {
+            self.sees(newest.xmin) && newest.xmax.is_none_or(|xmax| self.sees(xmax))
+        })
+    }
+
+    // the oldest transaction whose effects this snapshot might not see
+    pub fn horizon(&self) -> Xid {
+        self.active.first().copied().unwrap_or(self.xmax)
+    }
+}
+
+// drops the versions that no snapshot from `horizon` on can see; `None` when nothing changed
+pub fn prune(versions: &[Version], horizon: Xid) -> Option<Vec<Version>> {
+    let live: Vec<_> = versions
+        .iter()
+        .filter(|version| version.xmax.is_none_or(|xmax| xmax >= horizon))
+        .cloned()
+        .collect();
+    (live.len() < versions.len()).then_some(live)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(xmin: Xid, xmax: Option<Xid>, data: &str) -> Version {
+        Version {
+            xmin,
+            xmax,
+            data: data.as_bytes().to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_visibility() {
+        // 3 inserted "a", 5 replaced it with "b" and 7 deleted that, while 6 is still running
+        let versions = vec![
+            version(5, Some(7), "b"),
+            version(3, Some(5), "a"),
+            version(FROZEN_XID, Some(3), "old"),
+        ];
+        assert_eq!(versions, decode(&encode(&versions)).unwrap());
+        assert!(decode(&encode(&versions)[..5]).is_err());
+        let snapshot = |xid, xmax, active: &[Xid]| Snapshot {
+            xid,
+            xmax,
+            active: active.iter().copied().collect(),
+        };
+        let data = |snapshot: &Snapshot| {
+            snapshot
+                .visible(&versions)
+                .map(|version| String::from_utf8(version.data.clone()).unwrap())
+        };
+        assert_eq!(Some("old".to_string()), data(&snapshot(None, 3, &[])));
+        assert_eq!(Some("a".to_string()), data(&snapshot(None, 6, &[5])));
+        assert_eq!(Some("b".to_string()), data(&snapshot(Some(6), 7, &[6])));
+        assert_eq!(None, data(&snapshot(None, 8, &[6])));
+        assert_eq!(None, data(&snapshot(Some(7), 8, &[6, 7])));
+
+        assert!(snapshot(Some(8), 9, &[6, 8]).can_write(&versions));
+        assert!(!snapshot(Some(6), 7, &[6]).can_write(&versions));
+        assert!(!snapshot(Some(8), 9, &[7, 8]).can_write(&versions));
+
+        assert_eq!(None, prune(&versions, 3));
+        assert_eq!(Some(versions[..2].to_vec()), prune(&versions, 5));
+        assert_eq!(Some(vec![]), prune(&versions, 8));
+        assert_eq!(None, latest(&versions));
+    }
+}
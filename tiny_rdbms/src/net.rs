@@ -0,0 +1,19 @@
+use std::io;
+
+pub mod client;
+pub mod postgres;
+pub mod protocol;
+pub mod server;
+
+pub const DEFAULT_ADDR: &str = "127.0.0.1:6543";
+pub const DEFAULT_POSTGRES_ADDR: &str = "127.0.0.1:5433";
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("protocol error: {0}")]
+    Protocol(String),
+    #[error("{0}")]
+    Server(String),
+}
@@ -0,0 +1,58 @@
+use std::io::{BufReader, BufWriter, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+
+use super::protocol::{self, Message};
+use super::Error;
+use crate::relly::value::Value;
+
+// `columns` is empty for statements that return no rows
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<Value>>,
+    pub tag: String,
+}
+
+pub struct Client {
+    reader: BufReader<TcpStream>,
+    writer: BufWriter<TcpStream>,
+}
+
+impl Client {
+    pub fn connect(addr: impl ToSocketAddrs) -> Result<Self, Error> {
+        let stream = TcpStream::connect(addr)?;
+        Ok(Self {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: BufWriter::new(stream),
+        })
+    }
+
+    // a statement that fails comes back as `Error::Server` and leaves the connection usable
+    pub fn query(&mut self, sql: &str) -> Result<Response, Error> {
+        protocol::write_message(&mut self.writer, &Message::Query(sql.to_string()))?;
+        self.writer.flush()?;
+        let mut response = Response {
+            columns: vec![],
+            rows: vec![],
+            tag: String::new(),
+        };
+        loop {
+            match protocol::read_message(&mut self.reader)? {
+                Some(Message::Columns(columns)) => response.columns = columns,
+                Some(Message::Row(row)) => response.rows.push(row),
+                Some(Message::Complete(tag)) => {
+                    response.tag = tag;
+                    return Ok(response);
+                }
+                Some(Message::Error(message)) => return Err(Error::Server(message)),
+                Some(message) => {
+                    return Err(Error::Protocol(format!(
+                        "unexpected message from server: {:?}",
+                        message
+                    )))
+                }
+                None => return Err(Error::Protocol("server closed the connection".to_string())),
+            }
+        }
+    }
+}
@@ -0,0 +1,361 @@
+// Enough of PostgreSQL's frontend/backend protocol 3.0 for psql and client libraries that use
+// the simple query flow: no TLS, every user is trusted, all values are sent in text format, and
+// the extended query messages are answered with an error.
+
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::net::TcpStream;
+
+use super::server::Session;
+use super::Error;
+use crate::catalog;
+use crate::lock_manager;
+use crate::relly::btree;
+use crate::relly::value::{DataType, Value};
+use crate::sql::{self, Output, QueryResult};
+use crate::transaction;
+
+const PROTOCOL_VERSION: u32 = 3 << 16;
+const SSL_REQUEST: u32 = 80877103;
+const GSSENC_REQUEST: u32 = 80877104;
+const CANCEL_REQUEST: u32 = 80877102;
+const MAX_MESSAGE_SIZE: usize = 1 << 24;
+
+const PARAMETERS: &[(&str, &str)] = &[
+    ("server_version", "14.0"),
+    ("server_encoding", "UTF8"),
+    ("client_encoding", "UTF8"),
+    ("DateStyle", "ISO, MDY"),
+    ("TimeZone", "UTC"),
+    ("integer_datetimes", "on"),
+    ("standard_conforming_strings", "on"),
+];
+
+pub fn serve_connection(stream: TcpStream, session: &Session) -> Result<(), Error> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+    if !startup(&mut reader, &mut writer)? {
+        return Ok(());
+    }
+    let mut in_transaction = false;
+    ready_for_query(&mut writer, in_transaction)?;
+    writer.flush()?;
+    // after an error in the extended flow everything up to the next Sync is ignored
+    let mut skipping = false;
+    while let Some((kind, body)) = read_message(&mut reader)? {
+        match kind {
+            b'Q' => {
+                let sql = cstring(&body)?;
+                in_transaction = simple_query(&mut writer, session, &sql, in_transaction)?;
+                ready_for_query(&mut writer, in_transaction)?;
+            }
+            b'X' => break,
+            b'S' => {
+                skipping = false;
+                ready_for_query(&mut writer, in_transaction)?;
+            }
+            b'P' | b'B' | b'D' | b'E' | b'C' | b'H' | b'F' => {
+                if !skipping {
+                    skipping = true;
+                    error_response(
+                        &mut writer,
+                        "0A000",
+                        "the extended query protocol is not supported",
+                    )?;
+                }
+            }
+            kind => {
+                let message = format!("unexpected message type {:?}", kind as char);
+                error_response(&mut writer, "08P01", &message)?;
+                writer.flush()?;
+                return Err(Error::Protocol(message));
+            }
+        }
+        writer.flush()?;
+    }
+    Ok(())
+}
+
+// answers encryption requests with 'N' until the startup message arrives; `false` when the
+// connection should be closed instead, such as for cancel requests, which are not supported
+fn startup(reader: &mut impl Read, writer: &mut impl Write) -> Result<bool, Error> {
+    loop {
+        let mut len = [0u8; 4];
+        match reader.read_exact(&mut len) {
+            Ok(()) => {}
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(false),
+            Err(err) => return Err(err.into()),
+        }
+        let len = u32::from_be_bytes(len) as usize;
+        if !(8..=MAX_MESSAGE_SIZE).contains(&len) {
+            return Err(Error::Protocol(format!(
+                "invalid startup message length {}",
+                len
+            )));
+        }
+        let mut body = vec![0u8; len - 4];
+        reader.read_exact(&mut body)?;
+        match u32::from_be_bytes(body[..4].try_into().unwrap()) {
+            SSL_REQUEST | GSSENC_REQUEST => {
+                writer.write_all(b"N")?;
+                writer.flush()?;
+            }
+            CANCEL_REQUEST => return Ok(false),
+            // the parameters, such as user and database, make no difference here
+            PROTOCOL_VERSION => break,
+            version => {
+                let message = format!(
+                    "unsupported frontend protocol {}.{}",
+                    version >> 16,
+                    version & 0xffff
+                );
+                error_response(writer, "0A000", &message)?;
+                writer.flush()?;
+                return Ok(false);
+            }
+        }
+    }
+    write_message(writer, b'R', &0i32.to_be_bytes())?;
+    for (name, value) in PARAMETERS {
+        let mut body = vec![];
+        put_cstring(&mut body, name);
+        put_cstring(&mut body, value);
+        write_message(writer, b'S', &body)?;
+    }
+    let mut key_data = std::process::id().to_be_bytes().to_vec();
+    key_data.extend(0u32.to_be_bytes());
+    write_message(writer, b'K', &key_data)?;
+    Ok(true)
+}
+
+// runs the statements of a query string in order, stopping at the first error; returns whether
+// the session is in a transaction afterwards
+fn simple_query(
+    writer: &mut impl Write,
+    session: &Session,
+    sql: &str,
+    mut in_transaction: bool,
+) -> Result<bool, Error> {
+    let statements = split_statements(sql);
+    if statements.is_empty() {
+        write_message(writer, b'I', &[])?;
+    }
+    for statement in statements {
+        let (result, now_in_transaction) = session.execute(statement, |output| {
+            match output {
+                Output::Columns { columns, types } => {
+                    write_message(writer, b'T', &row_description(&columns, &types))?
+                }
+                Output::Row(row) => write_message(writer, b'D', &data_row(&row))?,
+            }
+            Ok(())
+        })?;
+        in_transaction = now_in_transaction;
+        match result {
+            Ok(result) => {
+                let mut tag = vec![];
+                put_cstring(&mut tag, &command_tag(&result));
+                write_message(writer, b'C', &tag)?;
+            }
+            Err(err) => {
+                error_response(writer, sqlstate(&err), &format!("{:#}", err))?;
+                break;
+            }
+        }
+    }
+    Ok(in_transaction)
+}
+
+// splits at semicolons outside of quotes, dropping empty statements
+fn split_statements(sql: &str) -> Vec<&str> {
+    let mut statements = vec![];
+    let mut quote = None;
+    let mut start = 0;
+    for (i, c) in sql.char_indices() {
+        match (quote, c) {
+            (None, '\'' | '"') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            (None, ';') => {
+                statements.push(&sql[start..=i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    statements.push(&sql[start..]);
+    statements
+        .into_iter()
+        .filter(|statement| !statement.trim().trim_end_matches(';').trim().is_empty())
+        .collect()
+}
+
+fn command_tag(result: &QueryResult) -> String {
+    match result {
+        // the zero stands for the object id clients used to get back for single-row inserts
+        QueryResult::Insert(count) => format!("INSERT 0 {}", count),
+        result => result.tag(),
+    }
+}
+
+// the PostgreSQL types closest to the declared ones: bigint, numeric, float8, bool, text, bytea
+fn row_description(columns: &[String], types: &[DataType]) -> Vec<u8> {
+    let mut body = (columns.len() as i16).to_be_bytes().to_vec();
+    for (column, data_type) in columns.iter().zip(types) {
+        let (type_oid, type_len): (i32, i16) = match data_type {
+            DataType::Int => (20, 8),
+            DataType::UInt => (1700, -1),
+            DataType::Float => (701, 8),
+            DataType::Bool => (16, 1),
+            DataType::Text => (25, -1),
+            DataType::Bytes => (17, -1),
+        };
+        put_cstring(&mut body, column);
+        body.extend(0i32.to_be_bytes());
+        body.extend(0i16.to_be_bytes());
+        body.extend(type_oid.to_be_bytes());
+        body.extend(type_len.to_be_bytes());
+        body.extend((-1i32).to_be_bytes());
+        body.extend(0i16.to_be_bytes());
+    }
+    body
+}
+
+fn data_row(row: &[Value]) -> Vec<u8> {
+    let mut body = (row.len() as i16).to_be_bytes().to_vec();
+    for value in row {
+        match text_value(value) {
+            Some(text) => {
+                body.extend((text.len() as i32).to_be_bytes());
+                body.extend(text.as_bytes());
+            }
+            None => body.extend((-1i32).to_be_bytes()),
+        }
+    }
+    body
+}
+
+// the text format PostgreSQL uses for the matching type
+fn text_value(value: &Value) -> Option<String> {
+    let text = match value {
+        Value::Null => return None,
+        Value::Bool(v) => if *v { "t" } else { "f" }.to_string(),
+        Value::Float(v) if v.is_nan() => "NaN".to_string(),
+        Value::Float(v) if v.is_infinite() => {
+            if *v > 0.0 { "Infinity" } else { "-Infinity" }.to_string()
+        }
+        Value::Bytes(v) => {
+            let hex: String = v.iter().map(|byte| format!("{:02x}", byte)).collect();
+            format!("\\x{}", hex)
+        }
+        value => value.to_string(),
+    };
+    Some(text)
+}
+
+fn sqlstate(err: &anyhow::Error) -> &'static str {
+    for cause in err.chain() {
+        if let Some(err) = cause.downcast_ref::<sql::Error>() {
+            return match err {
+                sql::Error::Syntax(_) => "42601",
+                sql::Error::NoSuchTable(_) => "42P01",
+                sql::Error::NoSuchColumn(_) => "42703",
+                sql::Error::Invalid(_) => "22023",
+            };
+        }
+        if let Some(err) = cause.downcast_ref::<catalog::Error>() {
+            return match err {
+                catalog::Error::TableExists(_) => "42P07",
+                catalog::Error::NoSuchTable(_) => "42P01",
+                catalog::Error::TableNotEmpty(_) => "55000",
+                catalog::Error::NotEmpty => "XX000",
+            };
+        }
+        if let Some(btree::Error::DuplicateKey) = cause.downcast_ref() {
+            return "23505";
+        }
+        if let Some(transaction::Error::WriteConflict) = cause.downcast_ref() {
+            return "40001";
+        }
+        if let Some(err) = cause.downcast_ref::<lock_manager::Error>() {
+            return match err {
+                lock_manager::Error::Deadlock(_) => "40P01",
+                lock_manager::Error::Blocked(_) => "55P03",
+            };
+        }
+    }
+    "XX000"
+}
+
+fn error_response(writer: &mut impl Write, code: &str, message: &str) -> io::Result<()> {
+    let mut body = vec![];
+    for (field, value) in [
+        (b'S', "ERROR"),
+        (b'V', "ERROR"),
+        (b'C', code),
+        (b'M', message),
+    ] {
+        body.push(field);
+        put_cstring(&mut body, value);
+    }
+    body.push(0);
+    write_message(writer, b'E', &body)
+}
+
+fn ready_for_query(writer: &mut impl Write, in_transaction: bool) -> io::Result<()> {
+    write_message(writer, b'Z', if in_transaction { b"T" } else { b"I" })
+}
+
+fn read_message(reader: &mut impl Read) -> Result<Option<(u8, Vec<u8>)>, Error> {
+    let mut header = [0u8; 5];
+    match reader.read_exact(&mut header) {
+        Ok(()) => {}
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err.into()),
+    }
+    let len = u32::from_be_bytes(header[1..].try_into().unwrap()) as usize;
+    if !(4..=MAX_MESSAGE_SIZE).contains(&len) {
+        return Err(Error::Protocol(format!("invalid message length {}", len)));
+    }
+    let mut body = vec![0u8; len - 4];
+    reader.read_exact(&mut body)?;
+    Ok(Some((header[0], body)))
+}
+
+fn write_message(writer: &mut impl Write, kind: u8, body: &[u8]) -> io::Result<()> {
+    writer.write_all(&[kind])?;
+    writer.write_all(&(body.len() as u32 + 4).to_be_bytes())?;
+    writer.write_all(body)
+}
+
+fn cstring(body: &[u8]) -> Result<String, Error> {
+    let end = body
+        .iter()
+        .position(|&byte| byte == 0)
+        .ok_or_else(|| Error::Protocol("string is not terminated".to_string()))?;
+    String::from_utf8(body[..end].to_vec())
+        .map_err(|_| Error::Protocol("string is not valid UTF-8".to_string()))
+}
+
+fn put_cstring(body: &mut Vec<u8>, s: &str) {
+    body.extend(s.as_bytes());
+    body.push(0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_statements() {
+        assert_eq!(
+            vec!["BEGIN;", " INSERT INTO t VALUES ('a;b');", " COMMIT"],
+            split_statements("BEGIN; INSERT INTO t VALUES ('a;b'); COMMIT")
+        );
+        assert!(split_statements(" ; ;\n").is_empty());
+        assert_eq!(
+            Some("\\x00ff".to_string()),
+            text_value(&Value::Bytes(vec![0, 0xff]))
+        );
+        assert_eq!(Some("f".to_string()), text_value(&Value::Bool(false)));
+        assert_eq!(None, text_value(&Value::Null));
+    }
+}
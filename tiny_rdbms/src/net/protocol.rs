@@ -0,0 +1,244 @@
+// Every message travels in a frame: the payload length as a big-endian u32, then the payload,
+// whose first byte tells the kind of message. Integers are big-endian, and a string is its
+// UTF-8 length as a u32 followed by the bytes.
+//
+// client to server:
+//   'Q' string                 run one SQL statement
+// server to client, in reply to every query:
+//   'T' u16 n, n strings       the column names, for statements that return rows
+//   'D' u16 n, n values        one row; rows follow the column names one frame each, sent
+//                              as the engine produces them
+//   'C' string                 the statement succeeded, with its command tag ("INSERT 3")
+//   'E' string                 the statement failed and had no effect; nothing else follows,
+//                              but rows sent before the failure may precede it
+//
+// a value is a type byte followed by its payload:
+//   0 NULL, 1 i64, 2 u64, 3 f64, 4 bool as one byte, 5 string, 6 u32 length and raw bytes
+
+use std::io::{self, Read, Write};
+
+use super::Error;
+use crate::relly::value::Value;
+
+pub const MAX_FRAME_SIZE: usize = 1 << 24;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Message {
+    Query(String),
+    Columns(Vec<String>),
+    Row(Vec<Value>),
+    Complete(String),
+    Error(String),
+}
+
+pub fn write_message(writer: &mut impl Write, message: &Message) -> Result<(), Error> {
+    let mut payload = vec![];
+    match message {
+        Message::Query(sql) => {
+            payload.push(b'Q');
+            put_string(&mut payload, sql);
+        }
+        Message::Columns(columns) => {
+            payload.push(b'T');
+            payload.extend((columns.len() as u16).to_be_bytes());
+            for column in columns {
+                put_string(&mut payload, column);
+            }
+        }
+        Message::Row(values) => {
+            payload.push(b'D');
+            payload.extend((values.len() as u16).to_be_bytes());
+            for value in values {
+                put_value(&mut payload, value);
+            }
+        }
+        Message::Complete(tag) => {
+            payload.push(b'C');
+            put_string(&mut payload, tag);
+        }
+        Message::Error(message) => {
+            payload.push(b'E');
+            put_string(&mut payload, message);
+        }
+    }
+    if payload.len() > MAX_FRAME_SIZE {
+        return Err(Error::Protocol(format!(
+            "message of {} bytes is too large",
+            payload.len()
+        )));
+    }
+    writer.write_all(&(payload.len() as u32).to_be_bytes())?;
+    writer.write_all(&payload)?;
+    Ok(())
+}
+
+// `None` when the peer closed the connection between frames
+pub fn read_message(reader: &mut impl Read) -> Result<Option<Message>, Error> {
+    let mut len = [0u8; 4];
+    match reader.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err.into()),
+    }
+    let len = u32::from_be_bytes(len) as usize;
+    if len == 0 || len > MAX_FRAME_SIZE {
+        return Err(Error::Protocol(format!("invalid frame length {}", len)));
+    }
+    let mut payload = vec![0u8; len];
+    reader.read_exact(&mut payload)?;
+    let mut payload = Payload(&payload[1..], payload[0]);
+    let message = match payload.1 {
+        b'Q' => Message::Query(payload.string()?),
+        b'T' => Message::Columns(
+            (0..payload.u16()?)
+                .map(|_| payload.string())
+                .collect::<Result<_, _>>()?,
+        ),
+        b'D' => Message::Row(
+            (0..payload.u16()?)
+                .map(|_| payload.value())
+                .collect::<Result<_, _>>()?,
+        ),
+        b'C' => Message::Complete(payload.string()?),
+        b'E' => Message::Error(payload.string()?),
+        kind => {
+            return Err(Error::Protocol(format!(
+                "unknown message type {:?}",
+                kind as char
+            )))
+        }
+    };
+    if !payload.0.is_empty() {
+        return Err(Error::Protocol(format!(
+            "{} trailing bytes in message {:?}",
+            payload.0.len(),
+            payload.1 as char
+        )));
+    }
+    Ok(Some(message))
+}
+
+fn put_string(payload: &mut Vec<u8>, s: &str) {
+    put_bytes(payload, s.as_bytes());
+}
+
+fn put_bytes(payload: &mut Vec<u8>, bytes: &[u8]) {
+    payload.extend((bytes.len() as u32).to_be_bytes());
+    payload.extend(bytes);
+}
+
+fn put_value(payload: &mut Vec<u8>, value: &Value) {
+    match value {
+        Value::Null => payload.push(0),
+        Value::Int(v) => {
+            payload.push(1);
+            payload.extend(v.to_be_bytes());
+        }
+        Value::UInt(v) => {
+            payload.push(2);
+            payload.extend(v.to_be_bytes());
+        }
+        Value::Float(v) => {
+            payload.push(3);
+            payload.extend(v.to_bits().to_be_bytes());
+        }
+        Value::Bool(v) => payload.extend([4, *v as u8]),
+        Value::Text(v) => {
+            payload.push(5);
+            put_string(payload, v);
+        }
+        Value::Bytes(v) => {
+            payload.push(6);
+            put_bytes(payload, v);
+        }
+    }
+}
+
+// the unread rest of a payload, and its message type for error messages
+struct Payload<'a>(&'a [u8], u8);
+
+impl<'a> Payload<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if self.0.len() < len {
+            return Err(Error::Protocol(format!(
+                "message {:?} is truncated",
+                self.1 as char
+            )));
+        }
+        let (head, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(head)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn u16(&mut self) -> Result<u16, Error> {
+        Ok(u16::from_be_bytes(self.array()?))
+    }
+
+    fn bytes(&mut self) -> Result<&'a [u8], Error> {
+        let len = u32::from_be_bytes(self.array()?) as usize;
+        self.take(len)
+    }
+
+    fn string(&mut self) -> Result<String, Error> {
+        let bytes = self.bytes()?;
+        String::from_utf8(bytes.to_vec())
+            .map_err(|_| Error::Protocol("string is not valid UTF-8".to_string()))
+    }
+
+    fn value(&mut self) -> Result<Value, Error> {
+        let value = match self.array::<1>()?[0] {
+            0 => Value::Null,
+            1 => Value::Int(i64::from_be_bytes(self.array()?)),
+            2 => Value::UInt(u64::from_be_bytes(self.array()?)),
+            3 => Value::Float(f64::from_bits(u64::from_be_bytes(self.array()?))),
+            4 => Value::Bool(self.array::<1>()?[0] != 0),
+            5 => Value::Text(self.string()?),
+            6 => Value::Bytes(self.bytes()?.to_vec()),
+            tag => return Err(Error::Protocol(format!("unknown value type {}", tag))),
+        };
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let messages = vec![
+            Message::Query("SELECT * FROM t;".to_string()),
+            Message::Columns(vec!["id".to_string(), "名前".to_string()]),
+            Message::Row(vec![
+                Value::Null,
+                Value::Int(-3),
+                Value::UInt(u64::MAX),
+                Value::Float(0.5),
+                Value::Bool(true),
+                Value::Text("it's".to_string()),
+                Value::Bytes(vec![0, 0xff]),
+            ]),
+            Message::Complete("SELECT 1".to_string()),
+            Message::Error("no such table: t".to_string()),
+        ];
+        let mut stream = vec![];
+        for message in &messages {
+            write_message(&mut stream, message).unwrap();
+        }
+        assert_eq!([0, 0, 0, 21, b'Q', 0, 0, 0, 16, b'S'], stream[..10]);
+        let mut reader = &stream[..];
+        for message in &messages {
+            assert_eq!(Some(message), read_message(&mut reader).unwrap().as_ref());
+        }
+        assert_eq!(None, read_message(&mut reader).unwrap());
+
+        // a frame cut short, an unknown type, and one with bytes left over
+        assert!(read_message(&mut &stream[..12]).is_err());
+        assert!(read_message(&mut &[0, 0, 0, 1, b'?'][..]).is_err());
+        assert!(read_message(&mut &[0, 0, 0, 6, b'C', 0, 0, 0, 0, 0][..]).is_err());
+    }
+}
@@ -0,0 +1,253 @@
+use std::collections::HashMap;
+use std::io::{BufReader, BufWriter, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::mpsc;
+use std::thread;
+
+use anyhow::Result;
+
+use super::protocol::{self, Message};
+use super::{postgres, Error};
+use crate::buffer_pool_manager::BufferPoolManager;
+use crate::sql::{self, Database, Output, QueryResult};
+
+type SessionId = u64;
+
+// the statement's result, and whether the session is in a transaction afterwards
+type Reply = (Result<QueryResult>, bool);
+
+// rows are sent as the engine produces them, and the reply comes last
+enum Response {
+    Output(Output),
+    Done(Reply),
+}
+
+struct Execute {
+    session: SessionId,
+    sql: String,
+    responses: mpsc::Sender<Response>,
+}
+
+enum Request {
+    Execute(Execute),
+    Close(SessionId),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Protocol {
+    // the framing described in `protocol`
+    Native,
+    // the simple query flow of PostgreSQL's frontend/backend protocol 3.0
+    Postgres,
+}
+
+// a connection's handle on its session in the engine thread
+pub struct Session {
+    id: SessionId,
+    requests: mpsc::Sender<Request>,
+}
+
+impl Session {
+    // passes the statement's rows to `out` as they arrive; a SELECT completes as
+    // `QueryResult::Rows`
+    pub fn execute(
+        &self,
+        sql: &str,
+        mut out: impl FnMut(Output) -> Result<(), Error>,
+    ) -> Result<Reply, Error> {
+        let (responses, receiver) = mpsc::channel();
+        let stopped = || Error::Server("the database engine has stopped".to_string());
+        self.requests
+            .send(Request::Execute(Execute {
+                session: self.id,
+                sql: sql.to_string(),
+                responses,
+            }))
+            .map_err(|_| stopped())?;
+        loop {
+            match receiver.recv().map_err(|_| stopped())? {
+                Response::Output(output) => out(output)?,
+                Response::Done(reply) => return Ok(reply),
+            }
+        }
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        let _ = self.requests.send(Request::Close(self.id));
+    }
+}
+
+// each connection gets a thread that speaks the protocol, while statements run one at a time on
+// the engine thread, which owns the buffer pool manager and a session per connection; sessions
+// share one transaction manager, so they see each other's committed writes only. A statement
+// that has to wait for a lock is set aside, and its connection gets no reply until it has run
+// again and got the lock, or has been picked as a deadlock victim
+pub struct Server {
+    listener: TcpListener,
+    protocol: Protocol,
+}
+
+impl Server {
+    pub fn bind(addr: impl ToSocketAddrs, protocol: Protocol) -> Result<Self> {
+        Ok(Self {
+            listener: TcpListener::bind(addr)?,
+            protocol,
+        })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    // the buffer pool manager cannot move between threads, so `open` creates it on the engine
+    // thread; serves connections for good, as a failed accept only loses that connection
+    pub fn run(
+        self,
+        open: impl FnOnce() -> Result<BufferPoolManager> + Send + 'static,
+    ) -> Result<()> {
+        let (requests, receiver) = mpsc::channel();
+        let (started, start) = mpsc::sync_channel(1);
+        thread::spawn(move || {
+            let opened = open().and_then(|mut bufmgr| {
+                let db = Database::open(&mut bufmgr)?;
+                Ok((bufmgr, db))
+            });
+            match opened {
+                Ok((bufmgr, db)) => {
+                    started.send(Ok(())).unwrap();
+                    if let Err(err) = run_engine(bufmgr, db, receiver) {
+                        eprintln!("engine stopped: {:#}", err);
+                    }
+                }
+                Err(err) => started.send(Err(err)).unwrap(),
+            }
+        });
+        start.recv()??;
+
+        for (id, stream) in (1..).zip(self.listener.incoming()) {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(err) => {
+                    eprintln!("accept: {}", err);
+                    continue;
+                }
+            };
+            let session = Session {
+                id,
+                requests: requests.clone(),
+            };
+            let protocol = self.protocol;
+            thread::spawn(move || {
+                let peer = stream.peer_addr();
+                let result = match protocol {
+                    Protocol::Native => serve_connection(stream, &session),
+                    Protocol::Postgres => postgres::serve_connection(stream, &session),
+                };
+                if let Err(err) = result {
+                    eprintln!("connection {:?}: {}", peer, err);
+                }
+            });
+        }
+        Ok(())
+    }
+}
+
+fn run_engine(
+    mut bufmgr: BufferPoolManager,
+    db: Database,
+    requests: mpsc::Receiver<Request>,
+) -> Result<()> {
+    let mut sessions = HashMap::new();
+    // statements waiting for locks, oldest first
+    let mut blocked = vec![];
+    for request in requests {
+        match request {
+            Request::Execute(execute) => {
+                blocked.extend(run_statement(&mut bufmgr, &db, &mut sessions, execute));
+            }
+            Request::Close(session) => {
+                // a client that disconnects in a transaction loses it; a failed rollback is no
+                // reason to stop serving everyone else
+                if let Some(mut session) = sessions.remove(&session) {
+                    if session.in_transaction() {
+                        if let Err(err) = session.execute(&mut bufmgr, "ROLLBACK") {
+                            eprintln!("rollback on disconnect: {:#}", err);
+                        }
+                    }
+                }
+            }
+        }
+        // any request may have ended a transaction and released its locks, and a statement
+        // that gets through may end another one
+        loop {
+            let waiting = blocked.len();
+            blocked = blocked
+                .into_iter()
+                .filter_map(|execute| run_statement(&mut bufmgr, &db, &mut sessions, execute))
+                .collect();
+            if blocked.len() == waiting {
+                break;
+            }
+        }
+    }
+    bufmgr.flush()?;
+    Ok(())
+}
+
+// runs a statement and sends its reply, or hands it back while it is blocked on a lock
+fn run_statement(
+    bufmgr: &mut BufferPoolManager,
+    db: &Database,
+    sessions: &mut HashMap<SessionId, Database>,
+    execute: Execute,
+) -> Option<Execute> {
+    let session = sessions
+        .entry(execute.session)
+        .or_insert_with(|| db.session());
+    // the connection may have gone away meanwhile
+    let result = session.execute_streaming(bufmgr, &execute.sql, &mut |output| {
+        let _ = execute.responses.send(Response::Output(output));
+    });
+    if result.as_ref().is_err_and(sql::is_blocked) {
+        return Some(execute);
+    }
+    let _ = execute
+        .responses
+        .send(Response::Done((result, session.in_transaction())));
+    None
+}
+
+fn serve_connection(stream: TcpStream, session: &Session) -> Result<(), Error> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+    while let Some(message) = protocol::read_message(&mut reader)? {
+        let sql = match message {
+            Message::Query(sql) => sql,
+            message => {
+                return Err(Error::Protocol(format!(
+                    "unexpected message from client: {:?}",
+                    message
+                )))
+            }
+        };
+        let reply = session.execute(&sql, |output| {
+            let message = match output {
+                Output::Columns { columns, .. } => Message::Columns(columns),
+                Output::Row(row) => Message::Row(row),
+            };
+            protocol::write_message(&mut writer, &message)
+        })?;
+        match reply.0 {
+            Ok(result) => {
+                protocol::write_message(&mut writer, &Message::Complete(result.tag()))?;
+            }
+            Err(err) => {
+                protocol::write_message(&mut writer, &Message::Error(format!("{:#}", err)))?
+            }
+        }
+        writer.flush()?;
+    }
+    Ok(())
+}
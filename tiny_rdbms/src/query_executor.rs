@@ -1,93 +1,267 @@
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use std::ops::Bound;
+
 use crate::buffer_pool_manager::BufferPoolManager;
 use crate::disk_manager::PageId;
+use crate::mvcc::{self, Snapshot};
 use crate::relly::btree;
 use crate::relly::btree::{BTree, SearchMode};
 use crate::relly::tuple;
-use anyhow::Result;
+use crate::table::{SimpleTable, UniqueIndex};
+use anyhow::{anyhow, Result};
+
+mod aggregate;
+mod explain;
+mod spill;
+
+use aggregate::Accumulator;
+pub use aggregate::Aggregate;
+pub use explain::{explain, NodeStats, Profile};
 
 pub type Tuple = Vec<Vec<u8>>;
 pub type TupleSlice<'a> = &'a [Vec<u8>];
 pub type BoxExecutor<'a> = Box<dyn Executer + 'a>;
 
-pub enum TupleSearchMode<'a> {
-    #[allow(dead_code)]
-    Start,
-    Key(&'a [&'a [u8]]),
-}
-
-impl<'a> TupleSearchMode<'a> {
-    fn encode(&self) -> SearchMode {
-        match self {
-            TupleSearchMode::Start => SearchMode::Start,
-            TupleSearchMode::Key(tuple) => {
-                let mut key = vec![];
-                tuple::encode(tuple.iter(), &mut key);
-                SearchMode::Key(key)
-            }
-        }
-    }
-}
-
 pub trait Executer {
     fn next(&mut self, buffer_manager: &mut BufferPoolManager) -> Result<Option<Tuple>>;
+    // gives back the pages the executor still holds, such as those of spilled sort runs not
+    // read to the end; a consumer calls it once done, as a `None` from a limit above does not
+    // mean the nodes below ran dry
+    fn finish(&mut self, _buffer_manager: &mut BufferPoolManager) -> Result<()> {
+        Ok(())
+    }
 }
 
 pub trait PlanNode {
-    fn start(&self, buffer_manager: &mut BufferPoolManager) -> Result<BoxExecutor>;
+    fn start(&self, buffer_manager: &mut BufferPoolManager) -> Result<BoxExecutor<'_>>;
+    // one line for EXPLAIN: the node type and whatever drives it
+    fn explain(&self) -> String;
+    fn children(&self) -> Vec<&dyn PlanNode>;
+    // calls `f` with a copy of this node that reads from `children` instead of `children()`,
+    // one for one
+    fn with_children(&self, children: &[&dyn PlanNode], f: &mut dyn FnMut(&dyn PlanNode));
 }
 
 pub struct ExecSeqScan<'a> {
     table_iter: btree::Iter,
-    while_cond: &'a dyn Fn(TupleSlice) -> bool,
+    reverse: bool,
+    snapshot: Option<&'a Snapshot>,
 }
 
 impl<'a> Executer for ExecSeqScan<'a> {
     fn next(&mut self, buffer_manager: &mut BufferPoolManager) -> Result<Option<Tuple>> {
-        let (pkey_bytes, tuple_bytes) = match self.table_iter.next(buffer_manager)? {
-            Some(pair) => pair,
-            None => return Ok(None),
-        };
-        let mut pkey = vec![];
-        tuple::decode(&pkey_bytes, &mut pkey);
-        if !(self.while_cond)(&pkey) {
-            return Ok(None);
+        loop {
+            let pair = if self.reverse {
+                self.table_iter.prev(buffer_manager)?
+            } else {
+                self.table_iter.next(buffer_manager)?
+            };
+            let (pkey_bytes, versions_bytes) = match pair {
+                Some(pair) => pair,
+                None => return Ok(None),
+            };
+            let versions = mvcc::decode(&versions_bytes)?;
+            let version = match self.snapshot {
+                Some(snapshot) => snapshot.visible(&versions),
+                None => mvcc::latest(&versions),
+            };
+            if let Some(version) = version {
+                let mut tuple = vec![];
+                tuple::decode(&pkey_bytes, &mut tuple);
+                tuple::decode(&version.data, &mut tuple);
+                return Ok(Some(tuple));
+            }
         }
-        let mut tuple = pkey;
-        tuple::decode(&tuple_bytes, &mut tuple);
-        Ok(Some(tuple))
     }
 }
 
+// the bounds are primary key prefixes, each covering every record whose key it begins; without
+// a snapshot the scan returns the latest version of every record
 pub struct SeqScan<'a> {
     pub table_meta_page_id: PageId,
-    pub search_mode: TupleSearchMode<'a>,
-    pub while_cond: &'a dyn Fn(TupleSlice) -> bool,
+    pub lower: Bound<&'a [&'a [u8]]>,
+    pub upper: Bound<&'a [&'a [u8]]>,
+    pub reverse: bool,
+    pub snapshot: Option<&'a Snapshot>,
 }
 
 impl<'a> PlanNode for SeqScan<'a> {
-    fn start(&self, buffer_manager: &mut BufferPoolManager) -> Result<BoxExecutor> {
+    fn start(&self, buffer_manager: &mut BufferPoolManager) -> Result<BoxExecutor<'_>> {
+        let encode = |pkey: &&[&[u8]]| {
+            let mut key = vec![];
+            tuple::encode(pkey.iter(), &mut key);
+            key
+        };
+        let lower = self.lower.as_ref().map(encode);
+        let upper = self.upper.as_ref().map(encode);
         let btree = BTree::new(self.table_meta_page_id);
-        let table_iter = btree.search(buffer_manager, self.search_mode.encode())?;
+        let table_iter = btree.range(
+            buffer_manager,
+            lower.as_ref().map(Vec::as_slice),
+            upper.as_ref().map(Vec::as_slice),
+            self.reverse,
+        )?;
         Ok(Box::new(ExecSeqScan {
             table_iter,
-            while_cond: self.while_cond,
+            reverse: self.reverse,
+            snapshot: self.snapshot,
+        }))
+    }
+
+    fn explain(&self) -> String {
+        let mut details = vec![format!("table page {}", self.table_meta_page_id.to_u64())];
+        details.extend(explain::format_bound(self.lower, true));
+        details.extend(explain::format_bound(self.upper, false));
+        if self.reverse {
+            details.push("backward".to_string());
+        }
+        format!("SeqScan {}", details.join(", "))
+    }
+
+    fn children(&self) -> Vec<&dyn PlanNode> {
+        vec![]
+    }
+
+    fn with_children(&self, _children: &[&dyn PlanNode], f: &mut dyn FnMut(&dyn PlanNode)) {
+        f(self)
+    }
+}
+
+pub struct ExecIndexScan<'a> {
+    table_btree: BTree,
+    index: &'a UniqueIndex,
+    index_iter: btree::Iter,
+    reverse: bool,
+    snapshot: Option<&'a Snapshot>,
+}
+
+impl<'a> Executer for ExecIndexScan<'a> {
+    fn next(&mut self, buffer_manager: &mut BufferPoolManager) -> Result<Option<Tuple>> {
+        loop {
+            let pair = if self.reverse {
+                self.index_iter.prev(buffer_manager)?
+            } else {
+                self.index_iter.next(buffer_manager)?
+            };
+            let (entry_key, pkey_bytes) = match pair {
+                Some(pair) => pair,
+                None => return Ok(None),
+            };
+            let skey_bytes = &entry_key[..entry_key.len() - pkey_bytes.len()];
+            let versions_bytes = self
+                .table_btree
+                .get(buffer_manager, &pkey_bytes)?
+                .ok_or_else(|| anyhow!("index entry points to a missing record"))?;
+            let versions = mvcc::decode(&versions_bytes)?;
+            let version = match self.snapshot {
+                Some(snapshot) => snapshot.visible(&versions),
+                None => mvcc::latest(&versions),
+            };
+            // entries are kept for every version until vacuum, so the one read must hold the
+            // secondary key it was found under
+            let tuple = match version {
+                Some(version) => SimpleTable::decode(&pkey_bytes, &version.data),
+                None => continue,
+            };
+            if self.index.encode(&tuple) == skey_bytes {
+                return Ok(Some(tuple));
+            }
+        }
+    }
+}
+
+// the bounds are secondary key prefixes, as those of `SeqScan` are primary key ones; without a
+// snapshot the scan returns the latest version of every record it finds
+pub struct IndexScan<'a> {
+    pub table_meta_page_id: PageId,
+    pub index: &'a UniqueIndex,
+    pub lower: Bound<&'a [&'a [u8]]>,
+    pub upper: Bound<&'a [&'a [u8]]>,
+    pub reverse: bool,
+    pub snapshot: Option<&'a Snapshot>,
+}
+
+impl<'a> PlanNode for IndexScan<'a> {
+    fn start(&self, buffer_manager: &mut BufferPoolManager) -> Result<BoxExecutor<'_>> {
+        // an entry key starts with the encoded secondary key, so prefix bounds carry over
+        let encode = |skey: &&[&[u8]]| {
+            let mut key = vec![];
+            tuple::encode(skey.iter(), &mut key);
+            key
+        };
+        let lower = self.lower.as_ref().map(encode);
+        let upper = self.upper.as_ref().map(encode);
+        let table_btree = BTree::new(self.table_meta_page_id);
+        let index_btree = BTree::new(self.index.meta_page_id);
+        let index_iter = index_btree.range(
+            buffer_manager,
+            lower.as_ref().map(Vec::as_slice),
+            upper.as_ref().map(Vec::as_slice),
+            self.reverse,
+        )?;
+        Ok(Box::new(ExecIndexScan {
+            table_btree,
+            index: self.index,
+            index_iter,
+            reverse: self.reverse,
+            snapshot: self.snapshot,
         }))
     }
+
+    fn explain(&self) -> String {
+        let mut details = vec![
+            format!("index page {}", self.index.meta_page_id.to_u64()),
+            format!("table page {}", self.table_meta_page_id.to_u64()),
+        ];
+        details.extend(explain::format_bound(self.lower, true));
+        details.extend(explain::format_bound(self.upper, false));
+        if self.reverse {
+            details.push("backward".to_string());
+        }
+        format!("IndexScan {}", details.join(", "))
+    }
+
+    fn children(&self) -> Vec<&dyn PlanNode> {
+        vec![]
+    }
+
+    fn with_children(&self, _children: &[&dyn PlanNode], f: &mut dyn FnMut(&dyn PlanNode)) {
+        f(self)
+    }
 }
 
 pub struct Filter<'a> {
     pub inner_plan: &'a dyn PlanNode,
     pub cond: &'a dyn Fn(TupleSlice) -> bool,
+    // what `cond` checks, for EXPLAIN
+    pub description: &'a str,
 }
 
 impl<'a> PlanNode for Filter<'a> {
-    fn start(&self, buffer_manager: &mut BufferPoolManager) -> Result<BoxExecutor> {
+    fn start(&self, buffer_manager: &mut BufferPoolManager) -> Result<BoxExecutor<'_>> {
         let inner_iter = self.inner_plan.start(buffer_manager)?;
         Ok(Box::new(ExecFilter {
             inner_iter,
             cond: self.cond,
         }))
     }
+
+    fn explain(&self) -> String {
+        format!("Filter: {}", self.description)
+    }
+
+    fn children(&self) -> Vec<&dyn PlanNode> {
+        vec![self.inner_plan]
+    }
+
+    fn with_children(&self, children: &[&dyn PlanNode], f: &mut dyn FnMut(&dyn PlanNode)) {
+        f(&Filter {
+            inner_plan: children[0],
+            cond: self.cond,
+            description: self.description,
+        })
+    }
 }
 
 pub struct ExecFilter<'a> {
@@ -108,4 +282,1265 @@ impl<'a> Executer for ExecFilter<'a> {
             }
         }
     }
+
+    fn finish(&mut self, buffer_manager: &mut BufferPoolManager) -> Result<()> {
+        self.inner_iter.finish(buffer_manager)
+    }
+}
+
+pub struct Project<'a> {
+    pub inner_plan: &'a dyn PlanNode,
+    pub columns: &'a [usize],
+}
+
+impl<'a> PlanNode for Project<'a> {
+    fn start(&self, buffer_manager: &mut BufferPoolManager) -> Result<BoxExecutor<'_>> {
+        let inner_iter = self.inner_plan.start(buffer_manager)?;
+        Ok(Box::new(ExecProject {
+            inner_iter,
+            columns: self.columns,
+        }))
+    }
+
+    fn explain(&self) -> String {
+        format!("Project columns {:?}", self.columns)
+    }
+
+    fn children(&self) -> Vec<&dyn PlanNode> {
+        vec![self.inner_plan]
+    }
+
+    fn with_children(&self, children: &[&dyn PlanNode], f: &mut dyn FnMut(&dyn PlanNode)) {
+        f(&Project {
+            inner_plan: children[0],
+            columns: self.columns,
+        })
+    }
+}
+
+pub struct ExecProject<'a> {
+    inner_iter: BoxExecutor<'a>,
+    columns: &'a [usize],
+}
+
+impl<'a> Executer for ExecProject<'a> {
+    fn next(&mut self, buffer_manager: &mut BufferPoolManager) -> Result<Option<Tuple>> {
+        let tuple = match self.inner_iter.next(buffer_manager)? {
+            Some(tuple) => tuple,
+            None => return Ok(None),
+        };
+        Ok(Some(
+            self.columns.iter().map(|&i| tuple[i].clone()).collect(),
+        ))
+    }
+
+    fn finish(&mut self, buffer_manager: &mut BufferPoolManager) -> Result<()> {
+        self.inner_iter.finish(buffer_manager)
+    }
+}
+
+pub struct Limit<'a> {
+    pub inner_plan: &'a dyn PlanNode,
+    pub offset: usize,
+    pub limit: Option<usize>,
+}
+
+impl<'a> PlanNode for Limit<'a> {
+    fn start(&self, buffer_manager: &mut BufferPoolManager) -> Result<BoxExecutor<'_>> {
+        let inner_iter = self.inner_plan.start(buffer_manager)?;
+        Ok(Box::new(ExecLimit {
+            inner_iter,
+            to_skip: self.offset,
+            remaining: self.limit,
+        }))
+    }
+
+    fn explain(&self) -> String {
+        match self.limit {
+            Some(limit) => format!("Limit {}, offset {}", limit, self.offset),
+            None => format!("Limit offset {}", self.offset),
+        }
+    }
+
+    fn children(&self) -> Vec<&dyn PlanNode> {
+        vec![self.inner_plan]
+    }
+
+    fn with_children(&self, children: &[&dyn PlanNode], f: &mut dyn FnMut(&dyn PlanNode)) {
+        f(&Limit {
+            inner_plan: children[0],
+            offset: self.offset,
+            limit: self.limit,
+        })
+    }
+}
+
+pub struct ExecLimit<'a> {
+    inner_iter: BoxExecutor<'a>,
+    to_skip: usize,
+    remaining: Option<usize>,
+}
+
+impl<'a> Executer for ExecLimit<'a> {
+    fn next(&mut self, buffer_manager: &mut BufferPoolManager) -> Result<Option<Tuple>> {
+        while self.to_skip > 0 {
+            if self.inner_iter.next(buffer_manager)?.is_none() {
+                self.to_skip = 0;
+                self.remaining = Some(0);
+            } else {
+                self.to_skip -= 1;
+            }
+        }
+        match &mut self.remaining {
+            Some(0) => return Ok(None),
+            Some(remaining) => *remaining -= 1,
+            None => {}
+        }
+        self.inner_iter.next(buffer_manager)
+    }
+
+    fn finish(&mut self, buffer_manager: &mut BufferPoolManager) -> Result<()> {
+        self.inner_iter.finish(buffer_manager)
+    }
+}
+
+// sorts in memory while the input fits in `memory_limit` bytes; larger inputs are written out
+// as sorted runs in temporary pages and merged; the pages of runs not read to the end are freed
+// by `Executer::finish`
+pub struct Sort<'a> {
+    pub inner_plan: &'a dyn PlanNode,
+    pub compare: &'a dyn Fn(TupleSlice, TupleSlice) -> Ordering,
+    pub memory_limit: usize,
+}
+
+impl<'a> PlanNode for Sort<'a> {
+    fn start(&self, buffer_manager: &mut BufferPoolManager) -> Result<BoxExecutor<'_>> {
+        let mut exec = ExecSort {
+            compare: self.compare,
+            tuples: vec![].into_iter(),
+            runs: vec![],
+            heads: BinaryHeap::new(),
+        };
+        match self.fill(buffer_manager, &mut exec) {
+            Ok(()) => Ok(Box::new(exec)),
+            Err(err) => {
+                exec.finish(buffer_manager)?;
+                Err(err)
+            }
+        }
+    }
+
+    fn explain(&self) -> String {
+        format!("Sort in memory up to {} bytes", self.memory_limit)
+    }
+
+    fn children(&self) -> Vec<&dyn PlanNode> {
+        vec![self.inner_plan]
+    }
+
+    fn with_children(&self, children: &[&dyn PlanNode], f: &mut dyn FnMut(&dyn PlanNode)) {
+        f(&Sort {
+            inner_plan: children[0],
+            compare: self.compare,
+            memory_limit: self.memory_limit,
+        })
+    }
+}
+
+impl<'a> Sort<'a> {
+    // the runs go into `exec` as they are written, so that a failure can discard them
+    fn fill(&self, buffer_manager: &mut BufferPoolManager, exec: &mut ExecSort<'a>) -> Result<()> {
+        let mut inner_iter = self.inner_plan.start(buffer_manager)?;
+        let mut tuples = vec![];
+        let mut size = 0;
+        while let Some(tuple) = inner_iter.next(buffer_manager)? {
+            size += tuple.iter().map(Vec::len).sum::<usize>();
+            tuples.push(tuple);
+            if size > self.memory_limit {
+                tuples.sort_by(|a, b| (self.compare)(a, b));
+                exec.runs
+                    .push(spill::write_run(buffer_manager, tuples.drain(..))?);
+                size = 0;
+            }
+        }
+        inner_iter.finish(buffer_manager)?;
+        tuples.sort_by(|a, b| (self.compare)(a, b));
+        if exec.runs.is_empty() {
+            exec.tuples = tuples.into_iter();
+            return Ok(());
+        }
+        if !tuples.is_empty() {
+            exec.runs.push(spill::write_run(buffer_manager, tuples)?);
+        }
+        for run in 0..exec.runs.len() {
+            exec.push_head(buffer_manager, run)?;
+        }
+        Ok(())
+    }
+}
+
+pub struct ExecSort<'a> {
+    compare: &'a dyn Fn(TupleSlice, TupleSlice) -> Ordering,
+    tuples: std::vec::IntoIter<Tuple>,
+    runs: Vec<spill::Run>,
+    // the next tuple of every run that has one left
+    heads: BinaryHeap<Head<'a>>,
+}
+
+impl<'a> ExecSort<'a> {
+    fn push_head(&mut self, buffer_manager: &mut BufferPoolManager, run: usize) -> Result<()> {
+        if let Some(tuple) = self.runs[run].next(buffer_manager)? {
+            self.heads.push(Head {
+                tuple,
+                run,
+                compare: self.compare,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl<'a> Executer for ExecSort<'a> {
+    fn next(&mut self, buffer_manager: &mut BufferPoolManager) -> Result<Option<Tuple>> {
+        if self.runs.is_empty() {
+            return Ok(self.tuples.next());
+        }
+        let head = match self.heads.pop() {
+            Some(head) => head,
+            None => return Ok(None),
+        };
+        self.push_head(buffer_manager, head.run)?;
+        Ok(Some(head.tuple))
+    }
+
+    fn finish(&mut self, buffer_manager: &mut BufferPoolManager) -> Result<()> {
+        self.heads.clear();
+        for run in &mut self.runs {
+            run.discard(buffer_manager)?;
+        }
+        Ok(())
+    }
+}
+
+// ordered so that the max-heap pops the smallest tuple, and on ties the one from the earliest
+// run, which keeps the sort stable
+struct Head<'a> {
+    tuple: Tuple,
+    run: usize,
+    compare: &'a dyn Fn(TupleSlice, TupleSlice) -> Ordering,
+}
+
+impl Ord for Head<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.compare)(&other.tuple, &self.tuple).then_with(|| other.run.cmp(&self.run))
+    }
+}
+
+impl PartialOrd for Head<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Head<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for Head<'_> {}
+
+fn concat(outer: TupleSlice, inner: TupleSlice) -> Tuple {
+    outer.iter().chain(inner).cloned().collect()
+}
+
+pub struct NestedLoopJoin<'a> {
+    pub outer_plan: &'a dyn PlanNode,
+    pub inner_plan: &'a dyn PlanNode,
+    pub cond: &'a dyn Fn(TupleSlice, TupleSlice) -> bool,
+}
+
+impl<'a> PlanNode for NestedLoopJoin<'a> {
+    fn start(&self, buffer_manager: &mut BufferPoolManager) -> Result<BoxExecutor<'_>> {
+        let outer_iter = self.outer_plan.start(buffer_manager)?;
+        Ok(Box::new(ExecNestedLoopJoin {
+            outer_iter,
+            inner_plan: self.inner_plan,
+            cond: self.cond,
+            current: None,
+        }))
+    }
+
+    fn explain(&self) -> String {
+        "NestedLoopJoin".to_string()
+    }
+
+    fn children(&self) -> Vec<&dyn PlanNode> {
+        vec![self.outer_plan, self.inner_plan]
+    }
+
+    fn with_children(&self, children: &[&dyn PlanNode], f: &mut dyn FnMut(&dyn PlanNode)) {
+        f(&NestedLoopJoin {
+            outer_plan: children[0],
+            inner_plan: children[1],
+            cond: self.cond,
+        })
+    }
+}
+
+pub struct ExecNestedLoopJoin<'a> {
+    outer_iter: BoxExecutor<'a>,
+    inner_plan: &'a dyn PlanNode,
+    cond: &'a dyn Fn(TupleSlice, TupleSlice) -> bool,
+    current: Option<(Tuple, BoxExecutor<'a>)>,
+}
+
+impl<'a> Executer for ExecNestedLoopJoin<'a> {
+    fn next(&mut self, buffer_manager: &mut BufferPoolManager) -> Result<Option<Tuple>> {
+        loop {
+            let (outer, inner_iter) = match &mut self.current {
+                Some(current) => current,
+                None => match self.outer_iter.next(buffer_manager)? {
+                    Some(outer) => {
+                        let inner_iter = self.inner_plan.start(buffer_manager)?;
+                        self.current.insert((outer, inner_iter))
+                    }
+                    None => return Ok(None),
+                },
+            };
+            match inner_iter.next(buffer_manager)? {
+                Some(inner) => {
+                    if (self.cond)(outer, &inner) {
+                        return Ok(Some(concat(outer, &inner)));
+                    }
+                }
+                None => {
+                    inner_iter.finish(buffer_manager)?;
+                    self.current = None;
+                }
+            }
+        }
+    }
+
+    fn finish(&mut self, buffer_manager: &mut BufferPoolManager) -> Result<()> {
+        if let Some((_, inner_iter)) = &mut self.current {
+            inner_iter.finish(buffer_manager)?;
+        }
+        self.outer_iter.finish(buffer_manager)
+    }
+}
+
+// looks up the inner table by primary key, or by a prefix of it, for each outer tuple; without a
+// snapshot the latest version of every inner record is joined
+pub struct IndexNestedLoopJoin<'a> {
+    pub outer_plan: &'a dyn PlanNode,
+    pub inner_table_meta_page_id: PageId,
+    pub outer_key: &'a dyn Fn(TupleSlice) -> Tuple,
+    pub snapshot: Option<&'a Snapshot>,
+}
+
+impl<'a> PlanNode for IndexNestedLoopJoin<'a> {
+    fn start(&self, buffer_manager: &mut BufferPoolManager) -> Result<BoxExecutor<'_>> {
+        let outer_iter = self.outer_plan.start(buffer_manager)?;
+        Ok(Box::new(ExecIndexNestedLoopJoin {
+            outer_iter,
+            inner_btree: BTree::new(self.inner_table_meta_page_id),
+            outer_key: self.outer_key,
+            snapshot: self.snapshot,
+            current: None,
+        }))
+    }
+
+    fn explain(&self) -> String {
+        format!(
+            "IndexNestedLoopJoin inner table page {}",
+            self.inner_table_meta_page_id.to_u64()
+        )
+    }
+
+    fn children(&self) -> Vec<&dyn PlanNode> {
+        vec![self.outer_plan]
+    }
+
+    fn with_children(&self, children: &[&dyn PlanNode], f: &mut dyn FnMut(&dyn PlanNode)) {
+        f(&IndexNestedLoopJoin {
+            outer_plan: children[0],
+            inner_table_meta_page_id: self.inner_table_meta_page_id,
+            outer_key: self.outer_key,
+            snapshot: self.snapshot,
+        })
+    }
+}
+
+pub struct ExecIndexNestedLoopJoin<'a> {
+    outer_iter: BoxExecutor<'a>,
+    inner_btree: BTree,
+    outer_key: &'a dyn Fn(TupleSlice) -> Tuple,
+    snapshot: Option<&'a Snapshot>,
+    current: Option<(Tuple, Tuple, btree::Iter)>,
+}
+
+impl<'a> Executer for ExecIndexNestedLoopJoin<'a> {
+    fn next(&mut self, buffer_manager: &mut BufferPoolManager) -> Result<Option<Tuple>> {
+        loop {
+            let (outer, key, inner_iter) = match &mut self.current {
+                Some(current) => current,
+                None => match self.outer_iter.next(buffer_manager)? {
+                    Some(outer) => {
+                        let key = (self.outer_key)(&outer);
+                        let mut key_bytes = vec![];
+                        tuple::encode(key.iter(), &mut key_bytes);
+                        let inner_iter = self
+                            .inner_btree
+                            .search(buffer_manager, SearchMode::Key(key_bytes))?;
+                        self.current.insert((outer, key, inner_iter))
+                    }
+                    None => return Ok(None),
+                },
+            };
+            if let Some((pkey_bytes, versions_bytes)) = inner_iter.next(buffer_manager)? {
+                let mut inner = vec![];
+                tuple::decode(&pkey_bytes, &mut inner);
+                if inner.starts_with(key) {
+                    // deleted records, and those the snapshot does not see, are skipped
+                    let versions = mvcc::decode(&versions_bytes)?;
+                    let version = match self.snapshot {
+                        Some(snapshot) => snapshot.visible(&versions),
+                        None => mvcc::latest(&versions),
+                    };
+                    if let Some(version) = version {
+                        tuple::decode(&version.data, &mut inner);
+                        return Ok(Some(concat(outer, &inner)));
+                    }
+                    continue;
+                }
+            }
+            self.current = None;
+        }
+    }
+
+    fn finish(&mut self, buffer_manager: &mut BufferPoolManager) -> Result<()> {
+        self.outer_iter.finish(buffer_manager)
+    }
+}
+
+// builds a hash table over the inner input and probes it with each outer tuple
+pub struct HashJoin<'a> {
+    pub outer_plan: &'a dyn PlanNode,
+    pub inner_plan: &'a dyn PlanNode,
+    pub outer_key: &'a dyn Fn(TupleSlice) -> Tuple,
+    pub inner_key: &'a dyn Fn(TupleSlice) -> Tuple,
+}
+
+impl<'a> PlanNode for HashJoin<'a> {
+    fn start(&self, buffer_manager: &mut BufferPoolManager) -> Result<BoxExecutor<'_>> {
+        let mut table: HashMap<Tuple, Vec<Tuple>> = HashMap::new();
+        let mut inner_iter = self.inner_plan.start(buffer_manager)?;
+        while let Some(inner) = inner_iter.next(buffer_manager)? {
+            table
+                .entry((self.inner_key)(&inner))
+                .or_default()
+                .push(inner);
+        }
+        inner_iter.finish(buffer_manager)?;
+        let outer_iter = self.outer_plan.start(buffer_manager)?;
+        Ok(Box::new(ExecHashJoin {
+            outer_iter,
+            outer_key: self.outer_key,
+            table,
+            output: vec![],
+        }))
+    }
+
+    fn explain(&self) -> String {
+        "HashJoin".to_string()
+    }
+
+    fn children(&self) -> Vec<&dyn PlanNode> {
+        vec![self.outer_plan, self.inner_plan]
+    }
+
+    fn with_children(&self, children: &[&dyn PlanNode], f: &mut dyn FnMut(&dyn PlanNode)) {
+        f(&HashJoin {
+            outer_plan: children[0],
+            inner_plan: children[1],
+            outer_key: self.outer_key,
+            inner_key: self.inner_key,
+        })
+    }
+}
+
+pub struct ExecHashJoin<'a> {
+    outer_iter: BoxExecutor<'a>,
+    outer_key: &'a dyn Fn(TupleSlice) -> Tuple,
+    table: HashMap<Tuple, Vec<Tuple>>,
+    output: Vec<Tuple>,
+}
+
+impl<'a> Executer for ExecHashJoin<'a> {
+    fn next(&mut self, buffer_manager: &mut BufferPoolManager) -> Result<Option<Tuple>> {
+        while self.output.is_empty() {
+            let outer = match self.outer_iter.next(buffer_manager)? {
+                Some(outer) => outer,
+                None => return Ok(None),
+            };
+            if let Some(inners) = self.table.get(&(self.outer_key)(&outer)) {
+                self.output = inners
+                    .iter()
+                    .rev()
+                    .map(|inner| concat(&outer, inner))
+                    .collect();
+            }
+        }
+        Ok(self.output.pop())
+    }
+
+    fn finish(&mut self, buffer_manager: &mut BufferPoolManager) -> Result<()> {
+        self.outer_iter.finish(buffer_manager)
+    }
+}
+
+fn accumulate(accumulators: &mut [Accumulator], tuple: TupleSlice) -> Result<()> {
+    for accumulator in accumulators {
+        accumulator.update(tuple)?;
+    }
+    Ok(())
+}
+
+fn finish(group: Tuple, accumulators: &[Accumulator]) -> Tuple {
+    let mut tuple = group;
+    tuple.extend(accumulators.iter().map(Accumulator::finish));
+    tuple
+}
+
+// emits the group-by columns followed by one column per aggregate, in group order; without
+// group-by columns there is exactly one output tuple, even for an empty input
+pub struct HashAggregate<'a> {
+    pub inner_plan: &'a dyn PlanNode,
+    pub group_by: &'a [usize],
+    pub aggregates: &'a [Aggregate],
+}
+
+impl<'a> PlanNode for HashAggregate<'a> {
+    fn start(&self, buffer_manager: &mut BufferPoolManager) -> Result<BoxExecutor<'_>> {
+        let new_accumulators = || -> Vec<_> {
+            self.aggregates
+                .iter()
+                .copied()
+                .map(Accumulator::new)
+                .collect()
+        };
+        let mut groups: HashMap<Tuple, Vec<Accumulator>> = HashMap::new();
+        if self.group_by.is_empty() {
+            groups.insert(vec![], new_accumulators());
+        }
+        let mut inner_iter = self.inner_plan.start(buffer_manager)?;
+        while let Some(tuple) = inner_iter.next(buffer_manager)? {
+            let group = self.group_by.iter().map(|&i| tuple[i].clone()).collect();
+            let accumulators = groups.entry(group).or_insert_with(new_accumulators);
+            accumulate(accumulators, &tuple)?;
+        }
+        inner_iter.finish(buffer_manager)?;
+        let mut groups: Vec<_> = groups.into_iter().collect();
+        groups.sort_by(|(a, _), (b, _)| a.cmp(b));
+        let tuples: Vec<_> = groups
+            .into_iter()
+            .map(|(group, accumulators)| finish(group, &accumulators))
+            .collect();
+        Ok(Box::new(ExecHashAggregate {
+            tuples: tuples.into_iter(),
+        }))
+    }
+
+    fn explain(&self) -> String {
+        format!(
+            "HashAggregate group by {:?}, {:?}",
+            self.group_by, self.aggregates
+        )
+    }
+
+    fn children(&self) -> Vec<&dyn PlanNode> {
+        vec![self.inner_plan]
+    }
+
+    fn with_children(&self, children: &[&dyn PlanNode], f: &mut dyn FnMut(&dyn PlanNode)) {
+        f(&HashAggregate {
+            inner_plan: children[0],
+            group_by: self.group_by,
+            aggregates: self.aggregates,
+        })
+    }
+}
+
+pub struct ExecHashAggregate {
+    tuples: std::vec::IntoIter<Tuple>,
+}
+
+impl Executer for ExecHashAggregate {
+    fn next(&mut self, _buffer_manager: &mut BufferPoolManager) -> Result<Option<Tuple>> {
+        Ok(self.tuples.next())
+    }
+}
+
+// same output as `HashAggregate`, computed on the fly from an input that is already ordered by
+// the group-by columns, such as a `SeqScan` grouped by a primary key prefix
+pub struct SortedAggregate<'a> {
+    pub inner_plan: &'a dyn PlanNode,
+    pub group_by: &'a [usize],
+    pub aggregates: &'a [Aggregate],
+}
+
+impl<'a> PlanNode for SortedAggregate<'a> {
+    fn start(&self, buffer_manager: &mut BufferPoolManager) -> Result<BoxExecutor<'_>> {
+        let inner_iter = self.inner_plan.start(buffer_manager)?;
+        let current = if self.group_by.is_empty() {
+            Some((vec![], self.new_accumulators()))
+        } else {
+            None
+        };
+        Ok(Box::new(ExecSortedAggregate {
+            plan: self,
+            inner_iter,
+            current,
+            done: false,
+        }))
+    }
+
+    fn explain(&self) -> String {
+        format!(
+            "SortedAggregate group by {:?}, {:?}",
+            self.group_by, self.aggregates
+        )
+    }
+
+    fn children(&self) -> Vec<&dyn PlanNode> {
+        vec![self.inner_plan]
+    }
+
+    fn with_children(&self, children: &[&dyn PlanNode], f: &mut dyn FnMut(&dyn PlanNode)) {
+        f(&SortedAggregate {
+            inner_plan: children[0],
+            group_by: self.group_by,
+            aggregates: self.aggregates,
+        })
+    }
+}
+
+impl<'a> SortedAggregate<'a> {
+    fn new_accumulators(&self) -> Vec<Accumulator> {
+        self.aggregates
+            .iter()
+            .copied()
+            .map(Accumulator::new)
+            .collect()
+    }
+}
+
+pub struct ExecSortedAggregate<'a> {
+    plan: &'a SortedAggregate<'a>,
+    inner_iter: BoxExecutor<'a>,
+    current: Option<(Tuple, Vec<Accumulator>)>,
+    done: bool,
+}
+
+impl<'a> Executer for ExecSortedAggregate<'a> {
+    fn next(&mut self, buffer_manager: &mut BufferPoolManager) -> Result<Option<Tuple>> {
+        if self.done {
+            return Ok(None);
+        }
+        while let Some(tuple) = self.inner_iter.next(buffer_manager)? {
+            let group: Tuple = self
+                .plan
+                .group_by
+                .iter()
+                .map(|&i| tuple[i].clone())
+                .collect();
+            let finished = match &self.current {
+                Some((current, _)) if *current == group => None,
+                _ => self.current.replace((group, self.plan.new_accumulators())),
+            };
+            accumulate(&mut self.current.as_mut().unwrap().1, &tuple)?;
+            if let Some((group, accumulators)) = finished {
+                return Ok(Some(finish(group, &accumulators)));
+            }
+        }
+        self.done = true;
+        Ok(self
+            .current
+            .take()
+            .map(|(group, accumulators)| finish(group, &accumulators)))
+    }
+
+    fn finish(&mut self, buffer_manager: &mut BufferPoolManager) -> Result<()> {
+        self.inner_iter.finish(buffer_manager)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disk_manager;
+    use crate::transaction::TransactionManager;
+
+    fn collect(bufmgr: &mut BufferPoolManager, plan: &dyn PlanNode) -> Vec<Tuple> {
+        let mut exec = plan.start(bufmgr).unwrap();
+        let mut tuples = vec![];
+        while let Some(tuple) = exec.next(bufmgr).unwrap() {
+            tuples.push(tuple);
+        }
+        exec.finish(bufmgr).unwrap();
+        tuples
+    }
+
+    #[test]
+    fn test_index_scan() {
+        let mut bufmgr = disk_manager::temp_bufmgr();
+        let mut table = SimpleTable {
+            meta_page_id: PageId::INVALID_PAGE_ID,
+            num_key_elems: 1,
+            unique_indices: vec![UniqueIndex {
+                meta_page_id: PageId::INVALID_PAGE_ID,
+                skey: vec![2],
+            }],
+        };
+        table.create(&mut bufmgr).unwrap();
+        table
+            .insert(&mut bufmgr, &[b"z", b"Alice", b"Smith"])
+            .unwrap();
+        table
+            .insert(&mut bufmgr, &[b"x", b"Bob", b"Johnson"])
+            .unwrap();
+        table
+            .insert(&mut bufmgr, &[b"y", b"Charlie", b"Williams"])
+            .unwrap();
+        table
+            .insert(&mut bufmgr, &[b"w", b"Dave", b"Miller"])
+            .unwrap();
+        assert!(table
+            .insert(&mut bufmgr, &[b"v", b"Eve", b"Smith"])
+            .is_err());
+        table
+            .update(&mut bufmgr, &[b"y", b"Charlie", b"Brown"])
+            .unwrap();
+        table.delete(&mut bufmgr, &[b"w"]).unwrap();
+
+        let plan = IndexScan {
+            table_meta_page_id: table.meta_page_id,
+            index: &table.unique_indices[0],
+            lower: Bound::Unbounded,
+            upper: Bound::Unbounded,
+            reverse: false,
+            snapshot: None,
+        };
+        let names: Vec<_> = collect(&mut bufmgr, &plan)
+            .into_iter()
+            .map(|tuple| tuple[1].clone())
+            .collect();
+        assert_eq!(
+            vec![b"Charlie".to_vec(), b"Bob".to_vec(), b"Alice".to_vec()],
+            names
+        );
+
+        let plan = IndexScan {
+            table_meta_page_id: table.meta_page_id,
+            index: &table.unique_indices[0],
+            lower: Bound::Included(&[b"Miller"]),
+            upper: Bound::Excluded(&[b"T"]),
+            reverse: false,
+            snapshot: None,
+        };
+        let tuples = collect(&mut bufmgr, &plan);
+        assert_eq!(1, tuples.len());
+        assert_eq!(
+            vec![b"z".to_vec(), b"Alice".to_vec(), b"Smith".to_vec()],
+            tuples[0]
+        );
+        let plan = IndexScan {
+            table_meta_page_id: table.meta_page_id,
+            index: &table.unique_indices[0],
+            lower: Bound::Excluded(&[b"Brown"]),
+            upper: Bound::Unbounded,
+            reverse: true,
+            snapshot: None,
+        };
+        let names: Vec<_> = collect(&mut bufmgr, &plan)
+            .into_iter()
+            .map(|tuple| tuple[1].clone())
+            .collect();
+        assert_eq!(vec![b"Alice".to_vec(), b"Bob".to_vec()], names);
+
+        // a running transaction's insert is indexed, but only its own snapshot sees the record
+        let manager = TransactionManager::open(&mut bufmgr).unwrap();
+        let mut txn = manager.begin(&mut bufmgr).unwrap();
+        txn.insert(&mut bufmgr, &table, &[b"u", b"Frank", b"Davis"])
+            .unwrap();
+        let mut scan = |snapshot: &Snapshot| {
+            let plan = IndexScan {
+                table_meta_page_id: table.meta_page_id,
+                index: &table.unique_indices[0],
+                lower: Bound::Unbounded,
+                upper: Bound::Unbounded,
+                reverse: false,
+                snapshot: Some(snapshot),
+            };
+            collect(&mut bufmgr, &plan).len()
+        };
+        assert_eq!(3, scan(&manager.snapshot()));
+        assert_eq!(4, scan(txn.snapshot()));
+        txn.commit(&mut bufmgr).unwrap();
+
+        // a value moves to another record after a snapshot is taken, and the snapshot still finds
+        // each record under the value it sees
+        let reader = manager.begin(&mut bufmgr).unwrap();
+        let mut writer = manager.begin(&mut bufmgr).unwrap();
+        writer
+            .update(&mut bufmgr, &table, &[b"x", b"Bob", b"Jones"])
+            .unwrap();
+        writer
+            .insert(&mut bufmgr, &table, &[b"v", b"Eve", b"Johnson"])
+            .unwrap();
+        writer.commit(&mut bufmgr).unwrap();
+        let mut lookup = |snapshot: Option<&Snapshot>, last_name: &[u8]| {
+            let plan = IndexScan {
+                table_meta_page_id: table.meta_page_id,
+                index: &table.unique_indices[0],
+                lower: Bound::Included(&[last_name]),
+                upper: Bound::Included(&[last_name]),
+                reverse: false,
+                snapshot,
+            };
+            collect(&mut bufmgr, &plan)
+                .into_iter()
+                .map(|tuple| tuple[1].clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            vec![b"Bob".to_vec()],
+            lookup(Some(reader.snapshot()), b"Johnson")
+        );
+        assert!(lookup(Some(reader.snapshot()), b"Jones").is_empty());
+        let snapshot = manager.snapshot();
+        assert_eq!(vec![b"Eve".to_vec()], lookup(Some(&snapshot), b"Johnson"));
+        assert_eq!(vec![b"Bob".to_vec()], lookup(Some(&snapshot), b"Jones"));
+        assert_eq!(vec![b"Eve".to_vec()], lookup(None, b"Johnson"));
+
+        // the entry for the old value goes once no snapshot needs the version
+        reader.rollback(&mut bufmgr).unwrap();
+        assert_eq!(1, table.vacuum(&mut bufmgr, manager.horizon()).unwrap());
+        let mut iter = BTree::new(table.unique_indices[0].meta_page_id)
+            .search(&mut bufmgr, SearchMode::Start)
+            .unwrap();
+        let mut num_entries = 0;
+        while iter.next(&mut bufmgr).unwrap().is_some() {
+            num_entries += 1;
+        }
+        assert_eq!(5, num_entries);
+    }
+
+    fn table(bufmgr: &mut BufferPoolManager, len: u64) -> SimpleTable {
+        let mut table = SimpleTable {
+            meta_page_id: PageId::INVALID_PAGE_ID,
+            num_key_elems: 1,
+            unique_indices: vec![],
+        };
+        table.create(bufmgr).unwrap();
+        for i in 0..len {
+            let id = i.to_be_bytes();
+            let group = (i * 7919 % 10).to_be_bytes();
+            table.insert(bufmgr, &[&id, &group, &[b'x'; 50]]).unwrap();
+        }
+        table
+    }
+
+    #[test]
+    fn test_seq_scan_range() {
+        let mut bufmgr = disk_manager::temp_bufmgr();
+        let mut table = SimpleTable {
+            meta_page_id: PageId::INVALID_PAGE_ID,
+            num_key_elems: 2,
+            unique_indices: vec![],
+        };
+        table.create(&mut bufmgr).unwrap();
+        for record in [["a", "1"], ["a", "2"], ["b", "1"], ["b", "2"], ["c", "1"]] {
+            table
+                .insert(&mut bufmgr, &record.map(str::as_bytes))
+                .unwrap();
+        }
+        let scan = |bufmgr: &mut BufferPoolManager, lower, upper, reverse| -> Vec<String> {
+            let plan = SeqScan {
+                table_meta_page_id: table.meta_page_id,
+                lower,
+                upper,
+                reverse,
+                snapshot: None,
+            };
+            collect(bufmgr, &plan)
+                .iter()
+                .map(|tuple| String::from_utf8(tuple.concat()).unwrap())
+                .collect()
+        };
+        let (a, b): (&[&[u8]], &[&[u8]]) = (&[b"a"], &[b"b"]);
+        assert_eq!(
+            vec!["b1", "b2"],
+            scan(&mut bufmgr, Bound::Excluded(a), Bound::Included(b), false)
+        );
+        assert_eq!(
+            vec!["b2", "b1"],
+            scan(&mut bufmgr, Bound::Excluded(a), Bound::Included(b), true)
+        );
+        assert_eq!(
+            vec!["c1", "b2", "b1", "a2", "a1"],
+            scan(&mut bufmgr, Bound::Unbounded, Bound::Unbounded, true)
+        );
+        assert_eq!(
+            vec!["a2", "b1", "b2", "c1"],
+            scan(
+                &mut bufmgr,
+                Bound::Included(&[b"a", b"2"]),
+                Bound::Unbounded,
+                false
+            )
+        );
+        assert_eq!(
+            vec!["a2", "a1"],
+            scan(&mut bufmgr, Bound::Unbounded, Bound::Excluded(b), true)
+        );
+    }
+
+    #[test]
+    fn test_project_limit() {
+        let mut bufmgr = disk_manager::temp_bufmgr();
+        let table = table(&mut bufmgr, 10);
+        let scan = SeqScan {
+            table_meta_page_id: table.meta_page_id,
+            lower: Bound::Unbounded,
+            upper: Bound::Unbounded,
+            reverse: false,
+            snapshot: None,
+        };
+        let project = Project {
+            inner_plan: &scan,
+            columns: &[1, 0],
+        };
+        let plan = Limit {
+            inner_plan: &project,
+            offset: 3,
+            limit: Some(2),
+        };
+        assert_eq!(
+            vec![
+                vec![
+                    (3u64 * 7919 % 10).to_be_bytes().to_vec(),
+                    3u64.to_be_bytes().to_vec()
+                ],
+                vec![
+                    (4u64 * 7919 % 10).to_be_bytes().to_vec(),
+                    4u64.to_be_bytes().to_vec()
+                ],
+            ],
+            collect(&mut bufmgr, &plan)
+        );
+        let plan = Limit {
+            inner_plan: &scan,
+            offset: 8,
+            limit: None,
+        };
+        assert_eq!(2, collect(&mut bufmgr, &plan).len());
+        let plan = Limit {
+            inner_plan: &scan,
+            offset: 20,
+            limit: Some(1),
+        };
+        assert!(collect(&mut bufmgr, &plan).is_empty());
+    }
+
+    #[test]
+    fn test_sort() {
+        let mut bufmgr = disk_manager::temp_bufmgr();
+        let table = table(&mut bufmgr, 1000);
+        let scan = SeqScan {
+            table_meta_page_id: table.meta_page_id,
+            lower: Bound::Unbounded,
+            upper: Bound::Unbounded,
+            reverse: false,
+            snapshot: None,
+        };
+        let compare = |a: TupleSlice, b: TupleSlice| b[1].cmp(&a[1]);
+        let mut expected = collect(&mut bufmgr, &scan);
+        expected.sort_by(|a, b| compare(a, b));
+
+        let in_use = bufmgr.num_pages() - bufmgr.num_free_pages();
+        for memory_limit in [usize::MAX, 10000, 0] {
+            let plan = Sort {
+                inner_plan: &scan,
+                compare: &compare,
+                memory_limit,
+            };
+            assert_eq!(expected, collect(&mut bufmgr, &plan));
+            assert_eq!(in_use, bufmgr.num_pages() - bufmgr.num_free_pages());
+        }
+
+        // runs that are never read to the end, as under a LIMIT, leave no pages behind once the
+        // executor is finished
+        let plan = Sort {
+            inner_plan: &scan,
+            compare: &compare,
+            memory_limit: 10000,
+        };
+        let mut exec = plan.start(&mut bufmgr).unwrap();
+        assert_eq!(Some(&expected[0]), exec.next(&mut bufmgr).unwrap().as_ref());
+        assert!(bufmgr.num_pages() - bufmgr.num_free_pages() > in_use);
+        exec.finish(&mut bufmgr).unwrap();
+        assert_eq!(in_use, bufmgr.num_pages() - bufmgr.num_free_pages());
+    }
+
+    #[test]
+    fn test_join() {
+        let mut bufmgr = disk_manager::temp_bufmgr();
+        let mut users = SimpleTable {
+            meta_page_id: PageId::INVALID_PAGE_ID,
+            num_key_elems: 1,
+            unique_indices: vec![],
+        };
+        users.create(&mut bufmgr).unwrap();
+        let mut orders = SimpleTable {
+            meta_page_id: PageId::INVALID_PAGE_ID,
+            num_key_elems: 2,
+            unique_indices: vec![],
+        };
+        orders.create(&mut bufmgr).unwrap();
+        for (id, name) in [("1", "Alice"), ("2", "Bob"), ("3", "Carol")] {
+            users
+                .insert(&mut bufmgr, &[id.as_bytes(), name.as_bytes()])
+                .unwrap();
+        }
+        for record in [
+            ["1", "10", "apple"],
+            ["3", "11", "melon"],
+            ["1", "12", "grape"],
+            ["4", "13", "lemon"],
+        ] {
+            let record = record.map(str::as_bytes);
+            orders.insert(&mut bufmgr, &record).unwrap();
+        }
+
+        let users_scan = SeqScan {
+            table_meta_page_id: users.meta_page_id,
+            lower: Bound::Unbounded,
+            upper: Bound::Unbounded,
+            reverse: false,
+            snapshot: None,
+        };
+        let orders_scan = SeqScan {
+            table_meta_page_id: orders.meta_page_id,
+            lower: Bound::Unbounded,
+            upper: Bound::Unbounded,
+            reverse: false,
+            snapshot: None,
+        };
+        let names_and_items = |tuples: Vec<Tuple>| {
+            tuples
+                .into_iter()
+                .map(|tuple| {
+                    assert_eq!(tuple[0], tuple[2]);
+                    (
+                        String::from_utf8(tuple[1].clone()).unwrap(),
+                        String::from_utf8(tuple[4].clone()).unwrap(),
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        let expected = vec![
+            ("Alice".to_string(), "apple".to_string()),
+            ("Alice".to_string(), "grape".to_string()),
+            ("Carol".to_string(), "melon".to_string()),
+        ];
+
+        let plan = NestedLoopJoin {
+            outer_plan: &users_scan,
+            inner_plan: &orders_scan,
+            cond: &|user, order| user[0] == order[0],
+        };
+        assert_eq!(expected, names_and_items(collect(&mut bufmgr, &plan)));
+
+        // the inner side restarts for every outer tuple
+        assert_eq!(
+            format!(
+                "NestedLoopJoin\n-> SeqScan table page {}\n-> SeqScan table page {}\n",
+                users.meta_page_id.to_u64(),
+                orders.meta_page_id.to_u64()
+            ),
+            explain(&plan)
+        );
+        let profile = Profile::new(&plan);
+        let mut tuples = vec![];
+        profile.instrument(&plan, &mut |plan| tuples = collect(&mut bufmgr, plan));
+        assert_eq!(expected, names_and_items(tuples));
+        let stats: Vec<_> = profile
+            .stats()
+            .iter()
+            .map(|stats| (stats.loops, stats.rows, stats.next_calls))
+            .collect();
+        assert_eq!(vec![(1, 3, 4), (1, 3, 4), (3, 12, 15)], stats);
+
+        let plan = IndexNestedLoopJoin {
+            outer_plan: &users_scan,
+            inner_table_meta_page_id: orders.meta_page_id,
+            outer_key: &|user| vec![user[0].clone()],
+            snapshot: None,
+        };
+        assert_eq!(expected, names_and_items(collect(&mut bufmgr, &plan)));
+
+        let plan = HashJoin {
+            outer_plan: &users_scan,
+            inner_plan: &orders_scan,
+            outer_key: &|user| vec![user[0].clone()],
+            inner_key: &|order| vec![order[0].clone()],
+        };
+        assert_eq!(expected, names_and_items(collect(&mut bufmgr, &plan)));
+    }
+
+    #[test]
+    fn test_aggregate() {
+        use crate::relly::value::{self, DataType, Value};
+
+        let mut bufmgr = disk_manager::temp_bufmgr();
+        let mut sales = SimpleTable {
+            meta_page_id: PageId::INVALID_PAGE_ID,
+            num_key_elems: 2,
+            unique_indices: vec![],
+        };
+        sales.create(&mut bufmgr).unwrap();
+        for (region, id, amount) in [
+            ("east", 1, Value::Int(10)),
+            ("west", 2, Value::Int(5)),
+            ("east", 3, Value::Int(-4)),
+            ("north", 4, Value::Null),
+            ("west", 5, Value::Int(7)),
+            ("east", 6, Value::Null),
+        ] {
+            let record = [Value::from(region), Value::Int(id), amount].map(|v| v.encode());
+            let record: Vec<&[u8]> = record.iter().map(Vec::as_slice).collect();
+            sales.insert(&mut bufmgr, &record).unwrap();
+        }
+        let scan = SeqScan {
+            table_meta_page_id: sales.meta_page_id,
+            lower: Bound::Unbounded,
+            upper: Bound::Unbounded,
+            reverse: false,
+            snapshot: None,
+        };
+        let aggregates = [
+            Aggregate::Count(None),
+            Aggregate::Count(Some(2)),
+            Aggregate::Sum(2, DataType::Int),
+            Aggregate::Min(2),
+            Aggregate::Max(1),
+            Aggregate::Avg(2, DataType::Int),
+        ];
+        let types = [
+            DataType::Text,
+            DataType::Int,
+            DataType::Int,
+            DataType::Int,
+            DataType::Int,
+            DataType::Int,
+            DataType::Float,
+        ];
+        let decode = |tuples: Vec<Tuple>, types: &[DataType]| -> Vec<Vec<Value>> {
+            tuples
+                .iter()
+                .map(|tuple| value::decode_tuple(tuple, types).unwrap())
+                .collect()
+        };
+        let expected = vec![
+            vec![
+                Value::from("east"),
+                Value::Int(3),
+                Value::Int(2),
+                Value::Int(6),
+                Value::Int(-4),
+                Value::Int(6),
+                Value::Float(3.0),
+            ],
+            vec![
+                Value::from("north"),
+                Value::Int(1),
+                Value::Int(0),
+                Value::Null,
+                Value::Null,
+                Value::Int(4),
+                Value::Null,
+            ],
+            vec![
+                Value::from("west"),
+                Value::Int(2),
+                Value::Int(2),
+                Value::Int(12),
+                Value::Int(5),
+                Value::Int(5),
+                Value::Float(6.0),
+            ],
+        ];
+        let plan = HashAggregate {
+            inner_plan: &scan,
+            group_by: &[0],
+            aggregates: &aggregates,
+        };
+        assert_eq!(expected, decode(collect(&mut bufmgr, &plan), &types));
+        let plan = SortedAggregate {
+            inner_plan: &scan,
+            group_by: &[0],
+            aggregates: &aggregates,
+        };
+        assert_eq!(expected, decode(collect(&mut bufmgr, &plan), &types));
+
+        let positive = Value::Int(0).encode();
+        let filter = Filter {
+            inner_plan: &scan,
+            cond: &|tuple| tuple[2] > positive,
+            description: "total > 0",
+        };
+        let totals = [Aggregate::Count(None), Aggregate::Sum(2, DataType::Int)];
+        let expected = vec![vec![Value::Int(3), Value::Int(22)]];
+        let plan = HashAggregate {
+            inner_plan: &filter,
+            group_by: &[],
+            aggregates: &totals,
+        };
+        assert_eq!(
+            expected,
+            decode(collect(&mut bufmgr, &plan), &[DataType::Int; 2])
+        );
+        let plan = SortedAggregate {
+            inner_plan: &filter,
+            group_by: &[],
+            aggregates: &totals,
+        };
+        assert_eq!(
+            expected,
+            decode(collect(&mut bufmgr, &plan), &[DataType::Int; 2])
+        );
+
+        let nothing = Filter {
+            inner_plan: &scan,
+            cond: &|_| false,
+            description: "false",
+        };
+        let plan = SortedAggregate {
+            inner_plan: &nothing,
+            group_by: &[],
+            aggregates: &totals,
+        };
+        assert_eq!(
+            vec![vec![Value::Int(0), Value::Null]],
+            decode(collect(&mut bufmgr, &plan), &[DataType::Int; 2])
+        );
+        let plan = HashAggregate {
+            inner_plan: &nothing,
+            group_by: &[0],
+            aggregates: &totals,
+        };
+        assert!(collect(&mut bufmgr, &plan).is_empty());
+    }
 }
@@ -0,0 +1,103 @@
+use anyhow::{anyhow, Result};
+
+use super::TupleSlice;
+use crate::relly::value::{self, DataType, Value};
+
+// inputs and outputs use the typed encoding of `relly::value`; NULLs are ignored except by
+// `Count(None)`, which counts rows
+#[derive(Debug, Clone, Copy)]
+pub enum Aggregate {
+    Count(Option<usize>),
+    Sum(usize, DataType),
+    Min(usize),
+    Max(usize),
+    Avg(usize, DataType),
+}
+
+enum State {
+    Count(i64),
+    Sum(Option<Value>),
+    Extreme(Option<Vec<u8>>),
+    Avg(f64, u64),
+}
+
+pub struct Accumulator {
+    aggregate: Aggregate,
+    state: State,
+}
+
+impl Accumulator {
+    pub fn new(aggregate: Aggregate) -> Self {
+        let state = match aggregate {
+            Aggregate::Count(_) => State::Count(0),
+            Aggregate::Sum(..) => State::Sum(None),
+            Aggregate::Min(_) | Aggregate::Max(_) => State::Extreme(None),
+            Aggregate::Avg(..) => State::Avg(0.0, 0),
+        };
+        Self { aggregate, state }
+    }
+
+    pub fn update(&mut self, tuple: TupleSlice) -> Result<()> {
+        match (self.aggregate, &mut self.state) {
+            (Aggregate::Count(None), State::Count(count)) => *count += 1,
+            (Aggregate::Count(Some(column)), State::Count(count)) => {
+                if !value::is_null(&tuple[column]) {
+                    *count += 1;
+                }
+            }
+            (Aggregate::Sum(column, data_type), State::Sum(sum)) => {
+                let value = Value::decode(&tuple[column], data_type)?;
+                *sum = match (sum.take(), value) {
+                    (sum, Value::Null) => sum,
+                    (None, value @ (Value::Int(_) | Value::UInt(_) | Value::Float(_))) => {
+                        Some(value)
+                    }
+                    (Some(Value::Int(a)), Value::Int(b)) => Some(Value::Int(
+                        a.checked_add(b)
+                            .ok_or_else(|| anyhow!("integer overflow"))?,
+                    )),
+                    (Some(Value::UInt(a)), Value::UInt(b)) => Some(Value::UInt(
+                        a.checked_add(b)
+                            .ok_or_else(|| anyhow!("integer overflow"))?,
+                    )),
+                    (Some(Value::Float(a)), Value::Float(b)) => Some(Value::Float(a + b)),
+                    _ => return Err(anyhow!("cannot sum {}", data_type)),
+                };
+            }
+            (Aggregate::Min(column), State::Extreme(min)) => {
+                let value = &tuple[column];
+                if !value::is_null(value) && min.as_ref().is_none_or(|min| value < min) {
+                    *min = Some(value.clone());
+                }
+            }
+            (Aggregate::Max(column), State::Extreme(max)) => {
+                let value = &tuple[column];
+                if !value::is_null(value) && max.as_ref().is_none_or(|max| value > max) {
+                    *max = Some(value.clone());
+                }
+            }
+            (Aggregate::Avg(column, data_type), State::Avg(sum, count)) => {
+                *sum += match Value::decode(&tuple[column], data_type)? {
+                    Value::Null => return Ok(()),
+                    Value::Int(v) => v as f64,
+                    Value::UInt(v) => v as f64,
+                    Value::Float(v) => v,
+                    _ => return Err(anyhow!("cannot average {}", data_type)),
+                };
+                *count += 1;
+            }
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    pub fn finish(&self) -> Vec<u8> {
+        match &self.state {
+            State::Count(count) => Value::Int(*count).encode(),
+            State::Sum(sum) => sum.as_ref().unwrap_or(&Value::Null).encode(),
+            State::Extreme(extreme) => extreme.clone().unwrap_or_else(|| Value::Null.encode()),
+            State::Avg(_, 0) => Value::Null.encode(),
+            State::Avg(sum, count) => Value::Float(sum / *count as f64).encode(),
+        }
+    }
+}
@@ -0,0 +1,220 @@
+use std::cell::Cell;
+use std::fmt::{self, Display};
+use std::ops::Bound;
+
+use anyhow::Result;
+
+use super::{BoxExecutor, Executer, PlanNode, Tuple};
+use crate::buffer_pool_manager::BufferPoolManager;
+
+// renders a plan as an indented tree, one node per line
+pub fn explain(plan: &dyn PlanNode) -> String {
+    let mut out = String::new();
+    walk(plan, 0, &mut |plan, depth| {
+        out.push_str(&format!("{}{}\n", indent(depth), plan.explain()))
+    });
+    out
+}
+
+fn walk(plan: &dyn PlanNode, depth: usize, f: &mut dyn FnMut(&dyn PlanNode, usize)) {
+    f(plan, depth);
+    for child in plan.children() {
+        walk(child, depth + 1, f);
+    }
+}
+
+fn indent(depth: usize) -> String {
+    match depth {
+        0 => String::new(),
+        depth => format!("{}-> ", "   ".repeat(depth - 1)),
+    }
+}
+
+// elements are shown as strings when they are printable UTF-8 and as hex otherwise
+pub fn format_tuple(tuple: &[impl AsRef<[u8]>]) -> String {
+    let elems: Vec<_> = tuple
+        .iter()
+        .map(|elem| match std::str::from_utf8(elem.as_ref()) {
+            Ok(s) if !s.chars().any(char::is_control) => format!("'{}'", s.replace('\'', "''")),
+            _ => {
+                let hex: String = elem
+                    .as_ref()
+                    .iter()
+                    .map(|byte| format!("{:02x}", byte))
+                    .collect();
+                format!("x'{}'", hex)
+            }
+        })
+        .collect();
+    format!("({})", elems.join(", "))
+}
+
+pub fn format_bound(bound: Bound<&[&[u8]]>, is_lower: bool) -> Option<String> {
+    let (op, key) = match (bound, is_lower) {
+        (Bound::Included(key), true) => (">=", key),
+        (Bound::Excluded(key), true) => (">", key),
+        (Bound::Included(key), false) => ("<=", key),
+        (Bound::Excluded(key), false) => ("<", key),
+        (Bound::Unbounded, _) => return None,
+    };
+    Some(format!("key {} {}", op, format_tuple(key)))
+}
+
+// what one node did over a run; `fetches` counts buffer pool fetches made while the node was
+// starting or producing rows, so it includes those of the nodes below it
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct NodeStats {
+    pub loops: u64,
+    pub rows: u64,
+    pub next_calls: u64,
+    pub fetches: u64,
+}
+
+// EXPLAIN ANALYZE: every node of a plan gets counters, in the order `explain` lists them
+pub struct Profile {
+    nodes: Vec<(usize, String)>,
+    stats: Vec<Cell<NodeStats>>,
+}
+
+impl Profile {
+    pub fn new(plan: &dyn PlanNode) -> Self {
+        let mut nodes = vec![];
+        walk(plan, 0, &mut |plan, depth| {
+            nodes.push((depth, plan.explain()))
+        });
+        let stats = vec![Cell::default(); nodes.len()];
+        Self { nodes, stats }
+    }
+
+    pub fn stats(&self) -> Vec<NodeStats> {
+        self.stats.iter().map(Cell::get).collect()
+    }
+
+    // calls `f` with a copy of `plan` whose nodes count into this profile
+    pub fn instrument(&self, plan: &dyn PlanNode, f: &mut dyn FnMut(&dyn PlanNode)) {
+        self.instrument_node(plan, &Cell::new(0), f);
+    }
+
+    fn instrument_node(
+        &self,
+        plan: &dyn PlanNode,
+        next_idx: &Cell<usize>,
+        f: &mut dyn FnMut(&dyn PlanNode),
+    ) {
+        let stats = &self.stats[next_idx.get()];
+        next_idx.set(next_idx.get() + 1);
+        self.instrument_children(&plan.children(), &[], next_idx, &mut |children| {
+            plan.with_children(children, &mut |plan| {
+                f(&Instrumented {
+                    inner_plan: plan,
+                    stats,
+                })
+            })
+        });
+    }
+
+    // children are wrapped one at a time, each inside the callback of the previous one, since
+    // a wrapped node only lives as long as that callback
+    fn instrument_children(
+        &self,
+        children: &[&dyn PlanNode],
+        instrumented: &[&dyn PlanNode],
+        next_idx: &Cell<usize>,
+        f: &mut dyn FnMut(&[&dyn PlanNode]),
+    ) {
+        match children.split_first() {
+            None => f(instrumented),
+            Some((child, rest)) => self.instrument_node(*child, next_idx, &mut |child| {
+                let mut instrumented = instrumented.to_vec();
+                instrumented.push(child);
+                self.instrument_children(rest, &instrumented, next_idx, f)
+            }),
+        }
+    }
+}
+
+impl Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for ((depth, explain), stats) in self.nodes.iter().zip(&self.stats) {
+            let stats = stats.get();
+            writeln!(
+                f,
+                "{}{} (loops={} rows={} next={} fetches={})",
+                indent(*depth),
+                explain,
+                stats.loops,
+                stats.rows,
+                stats.next_calls,
+                stats.fetches
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn num_fetches(buffer_manager: &BufferPoolManager) -> u64 {
+    let stats = buffer_manager.stats();
+    stats.hits + stats.misses
+}
+
+fn update(stats: &Cell<NodeStats>, f: impl FnOnce(&mut NodeStats)) {
+    let mut value = stats.get();
+    f(&mut value);
+    stats.set(value);
+}
+
+struct Instrumented<'a> {
+    inner_plan: &'a dyn PlanNode,
+    stats: &'a Cell<NodeStats>,
+}
+
+impl<'a> PlanNode for Instrumented<'a> {
+    fn start(&self, buffer_manager: &mut BufferPoolManager) -> Result<BoxExecutor<'_>> {
+        let before = num_fetches(buffer_manager);
+        let inner_iter = self.inner_plan.start(buffer_manager)?;
+        let fetches = num_fetches(buffer_manager) - before;
+        update(self.stats, |stats| {
+            stats.loops += 1;
+            stats.fetches += fetches;
+        });
+        Ok(Box::new(ExecInstrumented {
+            inner_iter,
+            stats: self.stats,
+        }))
+    }
+
+    fn explain(&self) -> String {
+        self.inner_plan.explain()
+    }
+
+    fn children(&self) -> Vec<&dyn PlanNode> {
+        self.inner_plan.children()
+    }
+
+    fn with_children(&self, children: &[&dyn PlanNode], f: &mut dyn FnMut(&dyn PlanNode)) {
+        self.inner_plan.with_children(children, f)
+    }
+}
+
+struct ExecInstrumented<'a> {
+    inner_iter: BoxExecutor<'a>,
+    stats: &'a Cell<NodeStats>,
+}
+
+impl<'a> Executer for ExecInstrumented<'a> {
+    fn next(&mut self, buffer_manager: &mut BufferPoolManager) -> Result<Option<Tuple>> {
+        let before = num_fetches(buffer_manager);
+        let tuple = self.inner_iter.next(buffer_manager)?;
+        let fetches = num_fetches(buffer_manager) - before;
+        update(self.stats, |stats| {
+            stats.next_calls += 1;
+            stats.rows += tuple.is_some() as u64;
+            stats.fetches += fetches;
+        });
+        Ok(tuple)
+    }
+
+    fn finish(&mut self, buffer_manager: &mut BufferPoolManager) -> Result<()> {
+        self.inner_iter.finish(buffer_manager)
+    }
+}
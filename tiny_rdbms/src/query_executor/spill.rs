@@ -0,0 +1,137 @@
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+use anyhow::{anyhow, Result};
+
+use super::Tuple;
+use crate::buffer_pool_manager::{Buffer, BufferPoolManager, Error};
+use crate::disk_manager::{PageId, PAGE_SIZE};
+use crate::relly::tuple;
+
+// a run is a byte stream of length-prefixed tuples spread over temporary pages, each of which
+// starts with the number of bytes used in it
+const HEADER_SIZE: usize = 4;
+const CAPACITY: usize = PAGE_SIZE - HEADER_SIZE;
+
+fn used(page: &[u8]) -> usize {
+    u32::from_ne_bytes(page[..HEADER_SIZE].try_into().unwrap()) as usize
+}
+
+pub fn write_run(
+    bufmgr: &mut BufferPoolManager,
+    tuples: impl IntoIterator<Item = Tuple>,
+) -> Result<Run, Error> {
+    let buffer = bufmgr.create_page()?;
+    let mut writer = Writer {
+        run: Run {
+            page_ids: VecDeque::from([buffer.page_id]),
+            offset: 0,
+        },
+        buffer,
+    };
+    let mut bytes = vec![];
+    let result = tuples.into_iter().try_for_each(|tuple| {
+        bytes.clear();
+        tuple::encode(tuple.iter(), &mut bytes);
+        writer.write(bufmgr, &(bytes.len() as u32).to_ne_bytes())?;
+        writer.write(bufmgr, &bytes)
+    });
+    let Writer { mut run, buffer } = writer;
+    drop(buffer);
+    if let Err(err) = result {
+        run.discard(bufmgr)?;
+        return Err(err);
+    }
+    Ok(run)
+}
+
+// the run is built up as its pages are added, so a failed write can give them all back
+struct Writer {
+    run: Run,
+    buffer: Rc<Buffer>,
+}
+
+impl Writer {
+    fn write(&mut self, bufmgr: &mut BufferPoolManager, mut bytes: &[u8]) -> Result<(), Error> {
+        while !bytes.is_empty() {
+            let written = {
+                let mut page = self.buffer.page.borrow_mut();
+                let used = used(&page[..]);
+                let n = bytes.len().min(CAPACITY - used);
+                page[HEADER_SIZE + used..][..n].copy_from_slice(&bytes[..n]);
+                page[..HEADER_SIZE].copy_from_slice(&((used + n) as u32).to_ne_bytes());
+                n
+            };
+            self.buffer.is_dirty.set(true);
+            bytes = &bytes[written..];
+            if !bytes.is_empty() {
+                self.buffer = bufmgr.create_page()?;
+                self.run.page_ids.push_back(self.buffer.page_id);
+            }
+        }
+        Ok(())
+    }
+}
+
+// pages are freed as soon as they have been read; a run left halfway has to be discarded, or
+// its remaining pages stay allocated
+pub struct Run {
+    // the pages not yet read to the end, in order
+    page_ids: VecDeque<PageId>,
+    offset: usize,
+}
+
+impl Run {
+    pub fn next(&mut self, bufmgr: &mut BufferPoolManager) -> Result<Option<Tuple>> {
+        let mut len = [0u8; 4];
+        if !self.read(bufmgr, &mut len)? {
+            return Ok(None);
+        }
+        let mut bytes = vec![0u8; u32::from_ne_bytes(len) as usize];
+        if !self.read(bufmgr, &mut bytes)? {
+            return Err(anyhow!("spill run ends in the middle of a tuple"));
+        }
+        let mut tuple = vec![];
+        tuple::decode(&bytes, &mut tuple);
+        Ok(Some(tuple))
+    }
+
+    // frees the pages not read yet; a page leaves the run only once it has been freed
+    pub fn discard(&mut self, bufmgr: &mut BufferPoolManager) -> Result<(), Error> {
+        while let Some(&page_id) = self.page_ids.front() {
+            bufmgr.deallocate_page(page_id)?;
+            self.page_ids.pop_front();
+        }
+        self.offset = 0;
+        Ok(())
+    }
+
+    // `false` when the run ends before `buf` is filled
+    fn read(&mut self, bufmgr: &mut BufferPoolManager, mut buf: &mut [u8]) -> Result<bool, Error> {
+        loop {
+            let page_id = match self.page_ids.front() {
+                Some(&page_id) => page_id,
+                None => return Ok(buf.is_empty()),
+            };
+            let buffer = bufmgr.fetch_page(page_id)?;
+            let used = {
+                let page = buffer.page.borrow();
+                let used = used(&page[..]);
+                let n = buf.len().min(used - self.offset);
+                buf[..n].copy_from_slice(&page[HEADER_SIZE + self.offset..][..n]);
+                self.offset += n;
+                buf = &mut std::mem::take(&mut buf)[n..];
+                used
+            };
+            if self.offset == used {
+                drop(buffer);
+                bufmgr.deallocate_page(page_id)?;
+                self.page_ids.pop_front();
+                self.offset = 0;
+            }
+            if buf.is_empty() {
+                return Ok(true);
+            }
+        }
+    }
+}
@@ -1,19 +1,27 @@
 use std::cell::{Ref, RefMut};
+use std::cmp::Ordering;
 use std::convert::identity;
+use std::ops::Bound;
 use std::rc::Rc;
 
 use bincode::Options;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
-use zerocopy::{AsBytes, ByteSlice};
+use zerocopy::{ByteSlice, ByteSliceMut};
 
-use crate::buffer_pool_manager::{self, Buffer, BufferPoolManager};
+use crate::buffer_pool_manager::{self, Buffer, BufferPoolManager, FetchPage, ReadPage};
 use crate::disk_manager::PageId;
 
 mod branch;
+mod bulk;
+mod check;
 mod leaf;
 mod meta;
-mod node;
+pub mod node;
+mod overflow;
+
+pub use bulk::DEFAULT_FILL_FACTOR;
+pub use check::Corruption;
 
 #[derive(Serialize, Deserialize)]
 pub struct Pair<'a> {
@@ -35,13 +43,27 @@ impl<'a> Pair<'a> {
 pub enum Error {
     #[error("duplicate key")]
     DuplicateKey,
+    #[error("key not found")]
+    KeyNotFound,
+    #[error("keys are not in ascending order")]
+    Unsorted,
+    #[error("fill factor {0} is not in (0, 1]")]
+    FillFactor(f64),
     #[error(transparent)]
     Buffer(#[from] buffer_pool_manager::Error),
 }
 
+#[derive(Debug, Clone, Copy)]
+enum WriteMode {
+    Insert,
+    Update,
+    Upsert,
+}
+
 #[derive(Debug, Clone)]
 pub enum SearchMode {
     Start,
+    End,
     Key(Vec<u8>),
 }
 
@@ -49,6 +71,7 @@ impl SearchMode {
     fn child_page_id(&self, branch: &branch::Branch<impl ByteSlice>) -> PageId {
         match self {
             SearchMode::Start => branch.child_at(0),
+            SearchMode::End => branch.right_child(),
             SearchMode::Key(key) => branch.search_child(key),
         }
     }
@@ -56,25 +79,67 @@ impl SearchMode {
     fn tuple_slot_id(&self, leaf: &leaf::Leaf<impl ByteSlice>) -> Result<usize, usize> {
         match self {
             SearchMode::Start => Err(0),
+            SearchMode::End => Err(leaf.num_pairs()),
             SearchMode::Key(key) => leaf.search_slot_id(key),
         }
     }
 }
 
+// how a tree lays out its pages, fixed when the tree is created
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Layout {
+    // a separator keeps only as much of the first key of its right child as tells it apart from
+    // the last key of its left child
+    pub suffix_truncation: bool,
+    // leaves store the prefix their keys share once, which suits the 9-byte blocks of composite
+    // memcmpable keys
+    pub prefix_compression: bool,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Self {
+            suffix_truncation: true,
+            prefix_compression: false,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Shape {
+    pub height: usize,
+    pub branch_pages: u64,
+    pub leaf_pages: u64,
+}
+
+#[derive(Clone)]
 pub struct BTree {
     pub meta_page_id: PageId,
 }
 
 impl BTree {
     pub fn create(bufmgr: &mut BufferPoolManager) -> Result<Self, Error> {
+        Self::create_with_layout(bufmgr, Layout::default())
+    }
+
+    pub fn create_with_layout(
+        bufmgr: &mut BufferPoolManager,
+        layout: Layout,
+    ) -> Result<Self, Error> {
         let meta_buffer = bufmgr.create_page()?;
         let mut meta = meta::Meta::new(meta_buffer.page.borrow_mut() as RefMut<[_]>);
         let root_buffer = bufmgr.create_page()?;
         let mut root = node::Node::new(root_buffer.page.borrow_mut() as RefMut<[_]>);
         root.initialize_as_leaf();
         let mut leaf = leaf::Leaf::new(root.body);
-        leaf.initialize();
+        // splits pass the compression of a leaf on to the new one
+        if layout.prefix_compression {
+            leaf.initialize_compressed();
+        } else {
+            leaf.initialize();
+        }
         meta.header.root_page_id = root_buffer.page_id;
+        meta.set_layout(layout);
         Ok(Self::new(meta_buffer.page_id))
     }
 
@@ -82,68 +147,152 @@ impl BTree {
         Self { meta_page_id }
     }
 
-    fn fetch_root_page(&self, bufmgr: &mut BufferPoolManager) -> Result<Rc<Buffer>, Error> {
-        let root_page_id = {
-            let meta_buffer = bufmgr.fetch_page(self.meta_page_id)?;
-            let meta = meta::Meta::new(meta_buffer.page.borrow() as Ref<[_]>);
-            meta.header.root_page_id
-        };
+    pub fn layout<S: FetchPage>(&self, bufmgr: &mut S) -> Result<Layout, Error> {
+        Ok(bufmgr
+            .fetch_page(self.meta_page_id)?
+            .read_page(|page| meta::Meta::new(page).layout()))
+    }
+
+    fn fetch_root_page<S: FetchPage>(&self, bufmgr: &mut S) -> Result<S::Buffer, Error> {
+        let root_page_id = bufmgr
+            .fetch_page(self.meta_page_id)?
+            .read_page(|page| meta::Meta::new(page).header.root_page_id);
         Ok(bufmgr.fetch_page(root_page_id)?)
     }
 
-    fn search_internal(
+    fn search_internal<S: FetchPage>(
         &self,
-        bufmgr: &mut BufferPoolManager,
-        node_buffer: Rc<Buffer>,
+        bufmgr: &mut S,
+        node_buffer: S::Buffer,
         search_mode: SearchMode,
-    ) -> Result<Iter, Error> {
-        let node = node::Node::new(node_buffer.page.borrow() as Ref<[_]>);
-        match node::Body::new(node.header.node_type, node.body.as_bytes()) {
-            node::Body::Leaf(leaf) => {
-                let slot_id = search_mode.tuple_slot_id(&leaf).unwrap_or_else(identity);
-                let is_right_most = leaf.num_pairs() == slot_id;
-                drop(node);
-
-                let mut iter = Iter {
-                    buffer: node_buffer,
-                    slot_id,
-                };
-                if is_right_most {
-                    iter.advance(bufmgr)?;
+    ) -> Result<Iter<S::Buffer>, Error> {
+        // descend into a child page, or start at a slot once a leaf is reached
+        let descent = node_buffer.read_page(|page| {
+            let node = node::Node::new(page);
+            match node::Body::new(node.header.node_type, node.body) {
+                node::Body::Leaf(leaf) => {
+                    Err(search_mode.tuple_slot_id(&leaf).unwrap_or_else(identity))
                 }
-                Ok(iter)
+                node::Body::Branch(branch) => Ok(search_mode.child_page_id(&branch)),
             }
-            node::Body::Branch(branch) => {
-                let child_page_id = search_mode.child_page_id(&branch);
-                drop(node);
+        });
+        match descent {
+            Ok(child_page_id) => {
                 drop(node_buffer);
                 let child_node_page = bufmgr.fetch_page(child_page_id)?;
                 self.search_internal(bufmgr, child_node_page, search_mode)
             }
+            Err(slot_id) => {
+                let mut iter = Iter {
+                    buffer: node_buffer,
+                    slot_id,
+                    lower: Bound::Unbounded,
+                    upper: Bound::Unbounded,
+                };
+                iter.skip_exhausted_leaves(bufmgr)?;
+                Ok(iter)
+            }
         }
     }
 
-    pub fn search(
+    pub fn search<S: FetchPage>(
         &self,
-        bufmgr: &mut BufferPoolManager,
+        bufmgr: &mut S,
         search_mode: SearchMode,
-    ) -> Result<Iter, Error> {
+    ) -> Result<Iter<S::Buffer>, Error> {
         let root_page = self.fetch_root_page(bufmgr)?;
         self.search_internal(bufmgr, root_page, search_mode)
     }
 
+    // bounds match keys by prefix: `Included(b)` admits and `Excluded(b)` rejects every key that
+    // starts with `b`, so a tuple prefix bounds all the tuples it begins. The cursor is placed at
+    // the upper end of the range when `reverse` is set, for walking it with `Iter::prev`
+    pub fn range<S: FetchPage>(
+        &self,
+        bufmgr: &mut S,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+        reverse: bool,
+    ) -> Result<Iter<S::Buffer>, Error> {
+        let search_mode = match (if reverse { upper } else { lower }, reverse) {
+            (Bound::Unbounded, false) => SearchMode::Start,
+            (Bound::Unbounded, true) => SearchMode::End,
+            (Bound::Included(key), false) | (Bound::Excluded(key), true) => {
+                SearchMode::Key(key.to_vec())
+            }
+            (Bound::Excluded(key), false) | (Bound::Included(key), true) => {
+                prefix_successor(key).map_or(SearchMode::End, SearchMode::Key)
+            }
+        };
+        let mut iter = self.search(bufmgr, search_mode)?;
+        iter.lower = lower.map(<[u8]>::to_vec);
+        iter.upper = upper.map(<[u8]>::to_vec);
+        Ok(iter)
+    }
+
+    pub fn get<S: FetchPage>(&self, bufmgr: &mut S, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        let mut iter = self.search(bufmgr, SearchMode::Key(key.to_vec()))?;
+        match iter.next(bufmgr)? {
+            Some((found_key, value)) if found_key == key => Ok(Some(value)),
+            _ => Ok(None),
+        }
+    }
+
+    // the number of levels and of pages on them
+    pub fn shape<S: FetchPage>(&self, bufmgr: &mut S) -> Result<Shape, Error> {
+        let root_page_id = bufmgr
+            .fetch_page(self.meta_page_id)?
+            .read_page(|page| meta::Meta::new(page).header.root_page_id);
+        let mut shape = Shape::default();
+        let mut level = vec![root_page_id];
+        while !level.is_empty() {
+            shape.height += 1;
+            let mut children = vec![];
+            for page_id in level {
+                bufmgr.fetch_page(page_id)?.read_page(|page| {
+                    let node = node::Node::new(page);
+                    match node::Body::new(node.header.node_type, node.body) {
+                        node::Body::Leaf(_) => shape.leaf_pages += 1,
+                        node::Body::Branch(branch) => {
+                            shape.branch_pages += 1;
+                            children
+                                .extend((0..=branch.num_pairs()).map(|idx| branch.child_at(idx)));
+                        }
+                    }
+                });
+            }
+            level = children;
+        }
+        Ok(shape)
+    }
+
+    #[allow(clippy::too_many_arguments)]
     fn insert_internal(
         &self,
         bufmgr: &mut BufferPoolManager,
         buffer: Rc<Buffer>,
         key: &[u8],
         value: &[u8],
+        mode: WriteMode,
+        layout: Layout,
+        replaced: &mut Option<Vec<u8>>,
     ) -> Result<Option<(Vec<u8>, PageId)>, Error> {
         let node = node::Node::new(buffer.page.borrow_mut() as RefMut<[_]>);
         match node::Body::new(node.header.node_type, node.body) {
             node::Body::Leaf(mut leaf) => {
                 let slot_id = match leaf.search_slot_id(key) {
-                    Ok(_) => return Err(Error::DuplicateKey),
+                    Ok(_) if matches!(mode, WriteMode::Insert) => return Err(Error::DuplicateKey),
+                    Ok(slot_id) => {
+                        *replaced = Some(leaf.pair_at(slot_id).value.to_vec());
+                        if leaf.update(slot_id, value).is_some() {
+                            buffer.is_dirty.set(true);
+                            return Ok(None);
+                        }
+                        // the new value does not fit in place, so relocate it through a split
+                        leaf.remove(slot_id);
+                        slot_id
+                    }
+                    Err(_) if matches!(mode, WriteMode::Update) => return Err(Error::KeyNotFound),
                     Err(slot_id) => slot_id,
                 };
                 if leaf.insert(slot_id, key, value).is_some() {
@@ -171,7 +320,8 @@ impl BTree {
                     new_leaf_node.initialize_as_leaf();
                     let mut new_leaf = leaf::Leaf::new(new_leaf_node.body);
                     new_leaf.initialize();
-                    let overflow_key = leaf.split_insert(&mut new_leaf, key, value);
+                    leaf.split_insert(&mut new_leaf, key, value);
+                    let overflow_key = leaf_separator(&new_leaf, &leaf, layout);
                     new_leaf.set_next_page_id(Some(buffer.page_id));
                     new_leaf.set_prev_page_id(prev_leaf_page_id);
                     buffer.is_dirty.set(true);
@@ -182,8 +332,16 @@ impl BTree {
                 let child_idx = branch.search_child_idx(key);
                 let child_page_id = branch.child_at(child_idx);
                 let child_node_buffer = bufmgr.fetch_page(child_page_id)?;
-                if let Some((overflow_key_from_child, overflow_child_page_id)) =
-                    self.insert_internal(bufmgr, child_node_buffer, key, value)?
+                if let Some((overflow_key_from_child, overflow_child_page_id)) = self
+                    .insert_internal(
+                        bufmgr,
+                        child_node_buffer,
+                        key,
+                        value,
+                        mode,
+                        layout,
+                        replaced,
+                    )?
                 {
                     if branch
                         .insert(child_idx, &overflow_key_from_child, overflow_child_page_id)
@@ -213,17 +371,41 @@ impl BTree {
         }
     }
 
-    pub fn insert(
+    fn write(
         &self,
         bufmgr: &mut BufferPoolManager,
         key: &[u8],
         value: &[u8],
+        mode: WriteMode,
     ) -> Result<(), Error> {
+        let stored = overflow::store(bufmgr, value)?;
+        match self.write_stored(bufmgr, key, &stored, mode) {
+            // the value replaced by an update is only freed once the new one is in place
+            Ok(Some(replaced)) => Ok(overflow::free(bufmgr, &replaced)?),
+            Ok(None) => Ok(()),
+            Err(err) => {
+                overflow::free(bufmgr, &stored)?;
+                Err(err)
+            }
+        }
+    }
+
+    fn write_stored(
+        &self,
+        bufmgr: &mut BufferPoolManager,
+        key: &[u8],
+        value: &[u8],
+        mode: WriteMode,
+    ) -> Result<Option<Vec<u8>>, Error> {
         let meta_buffer = bufmgr.fetch_page(self.meta_page_id)?;
         let mut meta = meta::Meta::new(meta_buffer.page.borrow_mut() as RefMut<[_]>);
         let root_page_id = meta.header.root_page_id;
         let root_buffer = bufmgr.fetch_page(root_page_id)?;
-        if let Some((key, child_page_id)) = self.insert_internal(bufmgr, root_buffer, key, value)? {
+        let layout = meta.layout();
+        let mut replaced = None;
+        if let Some((key, child_page_id)) =
+            self.insert_internal(bufmgr, root_buffer, key, value, mode, layout, &mut replaced)?
+        {
             let new_root_buffer = bufmgr.create_page()?;
             let mut node = node::Node::new(new_root_buffer.page.borrow_mut() as RefMut<[_]>);
             node.initialize_as_branch();
@@ -232,51 +414,866 @@ impl BTree {
             meta.header.root_page_id = new_root_buffer.page_id;
             meta_buffer.is_dirty.set(true);
         }
+        Ok(replaced)
+    }
+
+    pub fn insert(
+        &self,
+        bufmgr: &mut BufferPoolManager,
+        key: &[u8],
+        value: &[u8],
+    ) -> Result<(), Error> {
+        self.write(bufmgr, key, value, WriteMode::Insert)
+    }
+
+    pub fn update(
+        &self,
+        bufmgr: &mut BufferPoolManager,
+        key: &[u8],
+        value: &[u8],
+    ) -> Result<(), Error> {
+        self.write(bufmgr, key, value, WriteMode::Update)
+    }
+
+    pub fn upsert(
+        &self,
+        bufmgr: &mut BufferPoolManager,
+        key: &[u8],
+        value: &[u8],
+    ) -> Result<(), Error> {
+        self.write(bufmgr, key, value, WriteMode::Upsert)
+    }
+
+    fn delete_internal(
+        &self,
+        bufmgr: &mut BufferPoolManager,
+        buffer: Rc<Buffer>,
+        key: &[u8],
+        layout: Layout,
+    ) -> Result<bool, Error> {
+        let node = node::Node::new(buffer.page.borrow_mut() as RefMut<[_]>);
+        match node::Body::new(node.header.node_type, node.body) {
+            node::Body::Leaf(mut leaf) => {
+                let slot_id = leaf.search_slot_id(key).map_err(|_| Error::KeyNotFound)?;
+                overflow::free(bufmgr, leaf.pair_at(slot_id).value)?;
+                leaf.remove(slot_id);
+                buffer.is_dirty.set(true);
+                Ok(!leaf.is_half_full())
+            }
+            node::Body::Branch(mut branch) => {
+                let child_idx = branch.search_child_idx(key);
+                let child_page_id = branch.child_at(child_idx);
+                let child_node_buffer = bufmgr.fetch_page(child_page_id)?;
+                if !self.delete_internal(bufmgr, child_node_buffer, key, layout)? {
+                    return Ok(false);
+                }
+                self.rebalance(bufmgr, &mut branch, child_idx, layout)?;
+                buffer.is_dirty.set(true);
+                Ok(!branch.is_half_full())
+            }
+        }
+    }
+
+    fn rebalance(
+        &self,
+        bufmgr: &mut BufferPoolManager,
+        branch: &mut branch::Branch<impl ByteSliceMut>,
+        child_idx: usize,
+        layout: Layout,
+    ) -> Result<(), Error> {
+        if branch.num_pairs() == 0 {
+            return Ok(());
+        }
+        let left_idx = child_idx.saturating_sub(1);
+        let left_buffer = bufmgr.fetch_page(branch.child_at(left_idx))?;
+        let right_buffer = bufmgr.fetch_page(branch.child_at(left_idx + 1))?;
+        let separator = branch.pair_at(left_idx).key.to_vec();
+
+        let merged = {
+            let left_node = node::Node::new(left_buffer.page.borrow_mut() as RefMut<[_]>);
+            let right_node = node::Node::new(right_buffer.page.borrow_mut() as RefMut<[_]>);
+            match (
+                node::Body::new(left_node.header.node_type, left_node.body),
+                node::Body::new(right_node.header.node_type, right_node.body),
+            ) {
+                (node::Body::Leaf(mut left), node::Body::Leaf(mut right)) => {
+                    if left.merge_into(&mut right).is_some() {
+                        let prev_leaf_page_id = left.prev_page_id();
+                        right.set_prev_page_id(prev_leaf_page_id);
+                        if let Some(prev_leaf_page_id) = prev_leaf_page_id {
+                            let prev_leaf_buffer = bufmgr.fetch_page(prev_leaf_page_id)?;
+                            let node =
+                                node::Node::new(prev_leaf_buffer.page.borrow_mut() as RefMut<[_]>);
+                            let mut prev_leaf = leaf::Leaf::new(node.body);
+                            prev_leaf.set_next_page_id(Some(right_buffer.page_id));
+                            prev_leaf_buffer.is_dirty.set(true);
+                        }
+                        true
+                    } else {
+                        redistribute_leaves(branch, left_idx, &mut left, &mut right, layout);
+                        false
+                    }
+                }
+                (node::Body::Branch(mut left), node::Body::Branch(mut right)) => {
+                    if left.merge_into(&mut right, &separator).is_some() {
+                        true
+                    } else {
+                        redistribute_branches(branch, left_idx, &mut left, &mut right, separator);
+                        false
+                    }
+                }
+                _ => unreachable!(),
+            }
+        };
+        left_buffer.is_dirty.set(true);
+        right_buffer.is_dirty.set(true);
+        if merged {
+            branch.remove(left_idx);
+            let left_page_id = left_buffer.page_id;
+            drop(left_buffer);
+            bufmgr.deallocate_page(left_page_id)?;
+        }
+        Ok(())
+    }
+
+    pub fn delete(&self, bufmgr: &mut BufferPoolManager, key: &[u8]) -> Result<(), Error> {
+        let meta_buffer = bufmgr.fetch_page(self.meta_page_id)?;
+        let mut meta = meta::Meta::new(meta_buffer.page.borrow_mut() as RefMut<[_]>);
+        let root_page_id = meta.header.root_page_id;
+        let root_buffer = bufmgr.fetch_page(root_page_id)?;
+        let layout = meta.layout();
+        self.delete_internal(bufmgr, Rc::clone(&root_buffer), key, layout)?;
+        let collapsed = {
+            let root = node::Node::new(root_buffer.page.borrow() as Ref<[_]>);
+            match node::Body::new(root.header.node_type, root.body) {
+                node::Body::Branch(branch) if branch.num_pairs() == 0 => Some(branch.right_child()),
+                _ => None,
+            }
+        };
+        if let Some(new_root_page_id) = collapsed {
+            meta.header.root_page_id = new_root_page_id;
+            meta_buffer.is_dirty.set(true);
+            drop(root_buffer);
+            bufmgr.deallocate_page(root_page_id)?;
+        }
+        Ok(())
+    }
+
+    pub fn destroy(&self, bufmgr: &mut BufferPoolManager) -> Result<(), Error> {
+        let root_page_id = {
+            let meta_buffer = bufmgr.fetch_page(self.meta_page_id)?;
+            let meta = meta::Meta::new(meta_buffer.page.borrow() as Ref<[_]>);
+            meta.header.root_page_id
+        };
+        self.destroy_node(bufmgr, root_page_id)?;
+        bufmgr.deallocate_page(self.meta_page_id)?;
+        Ok(())
+    }
+
+    // moves the nodes of `other` under this tree's meta page and frees the old ones, so the
+    // tree keeps its meta page id
+    pub fn replace(&self, bufmgr: &mut BufferPoolManager, other: BTree) -> Result<(), Error> {
+        let (root_page_id, layout) = {
+            let other_buffer = bufmgr.fetch_page(other.meta_page_id)?;
+            let other_meta = meta::Meta::new(other_buffer.page.borrow() as Ref<[_]>);
+            (other_meta.header.root_page_id, other_meta.layout())
+        };
+        let old_root_page_id = {
+            let meta_buffer = bufmgr.fetch_page(self.meta_page_id)?;
+            let mut meta = meta::Meta::new(meta_buffer.page.borrow_mut() as RefMut<[_]>);
+            let old_root_page_id = meta.header.root_page_id;
+            meta.header.root_page_id = root_page_id;
+            meta.set_layout(layout);
+            meta_buffer.is_dirty.set(true);
+            old_root_page_id
+        };
+        bufmgr.deallocate_page(other.meta_page_id)?;
+        self.destroy_node(bufmgr, old_root_page_id)
+    }
+
+    fn destroy_node(&self, bufmgr: &mut BufferPoolManager, page_id: PageId) -> Result<(), Error> {
+        let mut child_page_ids = vec![];
+        {
+            let buffer = bufmgr.fetch_page(page_id)?;
+            let node = node::Node::new(buffer.page.borrow() as Ref<[_]>);
+            let body = node::Body::new(node.header.node_type, node.body);
+            match body {
+                node::Body::Branch(branch) => {
+                    child_page_ids.extend((0..=branch.num_pairs()).map(|idx| branch.child_at(idx)));
+                }
+                node::Body::Leaf(leaf) => {
+                    for slot_id in 0..leaf.num_pairs() {
+                        overflow::free(bufmgr, leaf.pair_at(slot_id).value)?;
+                    }
+                }
+            }
+        }
+        for child_page_id in child_page_ids {
+            self.destroy_node(bufmgr, child_page_id)?;
+        }
+        bufmgr.deallocate_page(page_id)?;
         Ok(())
     }
 }
 
-pub struct Iter {
-    buffer: Rc<Buffer>,
+// the least key greater than every key that starts with `prefix`
+fn prefix_successor(prefix: &[u8]) -> Option<Vec<u8>> {
+    let mut key = prefix.to_vec();
+    while let Some(last) = key.pop() {
+        if last < u8::MAX {
+            key.push(last + 1);
+            return Some(key);
+        }
+    }
+    None
+}
+
+fn common_prefix_len(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b).take_while(|(a, b)| a == b).count()
+}
+
+// the shortest prefix of `upper` that still sorts after `lower`, where `lower < upper`
+fn shortest_separator(lower: &[u8], upper: &[u8]) -> Vec<u8> {
+    debug_assert!(lower < upper);
+    upper[..common_prefix_len(lower, upper) + 1].to_vec()
+}
+
+// the separator between two neighbouring leaves, of which the right one is not empty
+fn leaf_separator(
+    left: &leaf::Leaf<impl ByteSlice>,
+    right: &leaf::Leaf<impl ByteSlice>,
+    layout: Layout,
+) -> Vec<u8> {
+    let first_key = right.pair_at(0).key;
+    match left.num_pairs().checked_sub(1) {
+        Some(last_id) if layout.suffix_truncation => {
+            shortest_separator(&left.pair_at(last_id).key, &first_key)
+        }
+        _ => first_key.into_owned(),
+    }
+}
+
+fn compare_prefix(key: &[u8], bound: &[u8]) -> Ordering {
+    key[..key.len().min(bound.len())].cmp(bound)
+}
+
+fn redistribute_leaves(
+    parent: &mut branch::Branch<impl ByteSliceMut>,
+    left_idx: usize,
+    left: &mut leaf::Leaf<impl ByteSliceMut>,
+    right: &mut leaf::Leaf<impl ByteSliceMut>,
+    layout: Layout,
+) {
+    // pairs going back to the leaf they came from always fit, as its prefix is left as it was
+    let mut moved = 0;
+    if left.used_space() > right.used_space() {
+        while !right.is_half_full()
+            && left.used_space() > right.used_space()
+            && left.transfer_last(right).is_some()
+        {
+            moved += 1;
+        }
+        if parent
+            .set_key_at(left_idx, &leaf_separator(left, right, layout))
+            .is_none()
+        {
+            for _ in 0..moved {
+                right.transfer(left).expect("left leaf must have space");
+            }
+        }
+    } else {
+        while !left.is_half_full()
+            && right.used_space() > left.used_space()
+            && right.num_pairs() > 1
+            && right.transfer(left).is_some()
+        {
+            moved += 1;
+        }
+        if parent
+            .set_key_at(left_idx, &leaf_separator(left, right, layout))
+            .is_none()
+        {
+            for _ in 0..moved {
+                left.transfer_last(right)
+                    .expect("right leaf must have space");
+            }
+        }
+    }
+}
+
+fn redistribute_branches(
+    parent: &mut branch::Branch<impl ByteSliceMut>,
+    left_idx: usize,
+    left: &mut branch::Branch<impl ByteSliceMut>,
+    right: &mut branch::Branch<impl ByteSliceMut>,
+    mut separator: Vec<u8>,
+) {
+    let mut moved = 0;
+    if left.used_space() > right.used_space() {
+        while !right.is_half_full() && left.used_space() > right.used_space() {
+            match left.shift_last(right, &separator) {
+                Some(key) => separator = key,
+                None => break,
+            }
+            moved += 1;
+        }
+        if parent.set_key_at(left_idx, &separator).is_none() {
+            for _ in 0..moved {
+                separator = right
+                    .shift_first(left, &separator)
+                    .expect("left branch must have space");
+            }
+        }
+    } else {
+        while !left.is_half_full() && right.used_space() > left.used_space() {
+            match right.shift_first(left, &separator) {
+                Some(key) => separator = key,
+                None => break,
+            }
+            moved += 1;
+        }
+        if parent.set_key_at(left_idx, &separator).is_none() {
+            for _ in 0..moved {
+                separator = left
+                    .shift_last(right, &separator)
+                    .expect("right branch must have space");
+            }
+        }
+    }
+}
+
+// a cursor between two pairs: `next` yields the pair after it and `prev` the pair before it
+pub struct Iter<B = Rc<Buffer>> {
+    buffer: B,
     slot_id: usize,
+    lower: Bound<Vec<u8>>,
+    upper: Bound<Vec<u8>>,
 }
 
-impl Iter {
+impl<B: ReadPage> Iter<B> {
     fn get(&self) -> Option<(Vec<u8>, Vec<u8>)> {
-        let leaf_node = node::Node::new(self.buffer.page.borrow() as Ref<[_]>);
-        let leaf = leaf::Leaf::new(leaf_node.body);
-        if self.slot_id < leaf.num_pairs() {
-            let pair = leaf.pair_at(self.slot_id);
-            Some((pair.key.to_vec(), pair.value.to_vec()))
-        } else {
-            None
-        }
+        self.buffer.read_page(|page| {
+            let leaf = leaf::Leaf::new(node::Node::new(page).body);
+            if self.slot_id < leaf.num_pairs() {
+                let pair = leaf.pair_at(self.slot_id);
+                Some((pair.key.to_vec(), pair.value.to_vec()))
+            } else {
+                None
+            }
+        })
     }
 
-    fn advance(&mut self, bufmgr: &mut BufferPoolManager) -> Result<(), Error> {
+    fn advance<S: FetchPage<Buffer = B>>(&mut self, bufmgr: &mut S) -> Result<(), Error> {
         self.slot_id += 1;
-        let next_page_id = {
-            let leaf_node = node::Node::new(self.buffer.page.borrow() as Ref<[_]>);
-            let leaf = leaf::Leaf::new(leaf_node.body);
-            if self.slot_id < leaf.num_pairs() {
+        self.skip_exhausted_leaves(bufmgr)
+    }
+
+    fn skip_exhausted_leaves<S: FetchPage<Buffer = B>>(
+        &mut self,
+        bufmgr: &mut S,
+    ) -> Result<(), Error> {
+        loop {
+            let (num_pairs, next_page_id) = self.buffer.read_page(|page| {
+                let leaf = leaf::Leaf::new(node::Node::new(page).body);
+                (leaf.num_pairs(), leaf.next_page_id())
+            });
+            if self.slot_id < num_pairs {
                 return Ok(());
             }
-            leaf.next_page_id()
-        };
-        if let Some(next_page_id) = next_page_id {
-            self.buffer = bufmgr.fetch_page(next_page_id)?;
-            self.slot_id = 0;
+            // skip leaves emptied by deletion that could not be merged
+            match next_page_id {
+                Some(next_page_id) => {
+                    self.buffer = bufmgr.fetch_page(next_page_id)?;
+                    self.slot_id = 0;
+                }
+                None => return Ok(()),
+            }
+        }
+    }
+
+    fn within_lower(&self, key: &[u8]) -> bool {
+        match &self.lower {
+            Bound::Included(bound) => compare_prefix(key, bound).is_ge(),
+            Bound::Excluded(bound) => compare_prefix(key, bound).is_gt(),
+            Bound::Unbounded => true,
+        }
+    }
+
+    fn within_upper(&self, key: &[u8]) -> bool {
+        match &self.upper {
+            Bound::Included(bound) => compare_prefix(key, bound).is_le(),
+            Bound::Excluded(bound) => compare_prefix(key, bound).is_lt(),
+            Bound::Unbounded => true,
         }
-        Ok(())
     }
 
     #[allow(clippy::type_complexity)]
-    pub fn next(
+    pub fn next<S: FetchPage<Buffer = B>>(
         &mut self,
-        bufmgr: &mut BufferPoolManager,
+        bufmgr: &mut S,
     ) -> Result<Option<(Vec<u8>, Vec<u8>)>, Error> {
-        let value = self.get();
-        self.advance(bufmgr)?;
-        Ok(value)
+        match self.get() {
+            Some((key, value)) if self.within_upper(&key) => {
+                self.advance(bufmgr)?;
+                Ok(Some((key, overflow::load(bufmgr, &value)?)))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    pub fn prev<S: FetchPage<Buffer = B>>(
+        &mut self,
+        bufmgr: &mut S,
+    ) -> Result<Option<(Vec<u8>, Vec<u8>)>, Error> {
+        while self.slot_id == 0 {
+            let prev_page_id = self
+                .buffer
+                .read_page(|page| leaf::Leaf::new(node::Node::new(page).body).prev_page_id());
+            let prev_page_id = match prev_page_id {
+                Some(prev_page_id) => prev_page_id,
+                None => return Ok(None),
+            };
+            self.buffer = bufmgr.fetch_page(prev_page_id)?;
+            self.slot_id = self
+                .buffer
+                .read_page(|page| leaf::Leaf::new(node::Node::new(page).body).num_pairs());
+        }
+        self.slot_id -= 1;
+        match self.get() {
+            Some((key, value)) if self.within_lower(&key) => {
+                Ok(Some((key, overflow::load(bufmgr, &value)?)))
+            }
+            _ => {
+                self.advance(bufmgr)?;
+                Ok(None)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::disk_manager;
+    use crate::relly::tuple;
+
+    fn keys(bufmgr: &mut BufferPoolManager, btree: &BTree) -> Vec<u64> {
+        let mut iter = btree.search(bufmgr, SearchMode::Start).unwrap();
+        let mut keys = vec![];
+        while let Some((key, _)) = iter.next(bufmgr).unwrap() {
+            keys.push(u64::from_be_bytes(key.try_into().unwrap()));
+        }
+        keys
+    }
+
+    fn root_is_leaf(bufmgr: &mut BufferPoolManager, btree: &BTree) -> bool {
+        let root_buffer = btree.fetch_root_page(bufmgr).unwrap();
+        let root = node::Node::new(root_buffer.page.borrow() as Ref<[_]>);
+        root.header.node_type == node::NODE_TYPE_LEAF
+    }
+
+    #[test]
+    fn test_delete() {
+        let mut bufmgr = disk_manager::temp_bufmgr();
+        let btree = BTree::create(&mut bufmgr).unwrap();
+        let value = vec![0xab; 500];
+        for i in 0u64..200 {
+            btree.insert(&mut bufmgr, &i.to_be_bytes(), &value).unwrap();
+        }
+        assert!(!root_is_leaf(&mut bufmgr, &btree));
+
+        for i in (0u64..200).step_by(2) {
+            btree.delete(&mut bufmgr, &i.to_be_bytes()).unwrap();
+        }
+        let expected: Vec<u64> = (1u64..200).step_by(2).collect();
+        assert_eq!(expected, keys(&mut bufmgr, &btree));
+        assert!(matches!(
+            btree.delete(&mut bufmgr, &0u64.to_be_bytes()),
+            Err(Error::KeyNotFound)
+        ));
+
+        for i in (1u64..200).rev().step_by(2) {
+            btree.delete(&mut bufmgr, &i.to_be_bytes()).unwrap();
+        }
+        assert!(keys(&mut bufmgr, &btree).is_empty());
+        assert!(root_is_leaf(&mut bufmgr, &btree));
+
+        btree
+            .insert(&mut bufmgr, &42u64.to_be_bytes(), b"again")
+            .unwrap();
+        assert_eq!(vec![42], keys(&mut bufmgr, &btree));
+    }
+
+    #[test]
+    fn test_delete_variable_size() {
+        let mut bufmgr = disk_manager::temp_bufmgr();
+        let btree = BTree::create(&mut bufmgr).unwrap();
+        for i in 0u64..300 {
+            let value = vec![0xcd; (i as usize * 37) % 900];
+            btree.insert(&mut bufmgr, &i.to_be_bytes(), &value).unwrap();
+        }
+        let mut expected: Vec<u64> = (0u64..300).collect();
+        for i in (0u64..300).filter(|i| i % 3 != 0) {
+            btree.delete(&mut bufmgr, &i.to_be_bytes()).unwrap();
+            expected.retain(|&key| key != i);
+        }
+        assert_eq!(expected, keys(&mut bufmgr, &btree));
+
+        let mut iter = btree
+            .search(&mut bufmgr, SearchMode::Key(150u64.to_be_bytes().to_vec()))
+            .unwrap();
+        let (key, value) = iter.next(&mut bufmgr).unwrap().unwrap();
+        assert_eq!(150u64.to_be_bytes().as_slice(), key.as_slice());
+        assert_eq!((150 * 37) % 900, value.len());
+    }
+
+    #[test]
+    fn test_delete_deep() {
+        let key = |i: u64| {
+            let mut key = vec![0u8; 300];
+            key[292..].copy_from_slice(&i.to_be_bytes());
+            key
+        };
+        let mut bufmgr = disk_manager::temp_bufmgr();
+        let btree = BTree::create(&mut bufmgr).unwrap();
+        for i in 0u64..1000 {
+            btree.insert(&mut bufmgr, &key(i), b"value").unwrap();
+        }
+        let mut expected: Vec<u64> = (0u64..1000).collect();
+        for n in 0u64..1000 {
+            let i = n * 7 % 1000;
+            btree.delete(&mut bufmgr, &key(i)).unwrap();
+            expected.retain(|&key| key != i);
+            if n % 100 == 99 {
+                let mut iter = btree.search(&mut bufmgr, SearchMode::Start).unwrap();
+                let mut actual = vec![];
+                while let Some((key, _)) = iter.next(&mut bufmgr).unwrap() {
+                    actual.push(u64::from_be_bytes(key[292..].try_into().unwrap()));
+                }
+                assert_eq!(expected, actual);
+            }
+        }
+        assert!(root_is_leaf(&mut bufmgr, &btree));
+
+        // everything but the meta page and the root leaf has been freed and gets reused
+        let num_pages = bufmgr.num_pages();
+        assert_eq!(num_pages - 3, bufmgr.num_free_pages());
+        for i in 0u64..1000 {
+            btree.insert(&mut bufmgr, &key(i), b"value").unwrap();
+        }
+        assert_eq!(num_pages, bufmgr.num_pages());
+        btree.destroy(&mut bufmgr).unwrap();
+        assert_eq!(num_pages - 1, bufmgr.num_free_pages());
+    }
+
+    #[test]
+    fn test_layout() {
+        assert_eq!(b"ab".to_vec(), shortest_separator(b"aa\xff", b"abc"));
+        assert_eq!(b"ab".to_vec(), shortest_separator(b"a", b"abc"));
+
+        // composite keys whose first element repeats, each padded to 9-byte blocks
+        let key = |i: u64| {
+            let mut key = vec![];
+            let elems = [
+                format!("category-{}", i % 4).into_bytes(),
+                i.to_be_bytes().to_vec(),
+            ];
+            tuple::encode(elems.iter(), &mut key);
+            key
+        };
+        let mut shapes = vec![];
+        for (suffix_truncation, prefix_compression) in
+            [(false, false), (true, false), (false, true), (true, true)]
+        {
+            let layout = Layout {
+                suffix_truncation,
+                prefix_compression,
+            };
+            let mut bufmgr = disk_manager::temp_bufmgr();
+            let btree = BTree::create_with_layout(&mut bufmgr, layout).unwrap();
+            assert_eq!(layout, btree.layout(&mut bufmgr).unwrap());
+            for i in (0u64..3000).map(|n| n * 7 % 3000) {
+                btree.insert(&mut bufmgr, &key(i), b"value").unwrap();
+            }
+            shapes.push(btree.shape(&mut bufmgr).unwrap());
+
+            for i in (0u64..3000).filter(|i| i % 3 != 0) {
+                btree.delete(&mut bufmgr, &key(i)).unwrap();
+            }
+            let mut expected: Vec<_> = (0u64..3000).step_by(3).map(key).collect();
+            expected.sort();
+            let mut iter = btree.search(&mut bufmgr, SearchMode::Start).unwrap();
+            let mut actual = vec![];
+            while let Some((key, _)) = iter.next(&mut bufmgr).unwrap() {
+                actual.push(key);
+            }
+            drop(iter);
+            assert_eq!(expected, actual);
+            assert_eq!(None, btree.get(&mut bufmgr, &key(1)).unwrap());
+            assert_eq!(
+                Some(b"value".to_vec()),
+                btree.get(&mut bufmgr, &key(2001)).unwrap()
+            );
+            assert!(btree
+                .check(&mut bufmgr, &mut HashSet::new())
+                .unwrap()
+                .is_empty());
+        }
+        let pages = |shape: &Shape| shape.branch_pages + shape.leaf_pages;
+        assert!(pages(&shapes[1]) <= pages(&shapes[0]));
+        assert!(shapes[2].leaf_pages * 3 < shapes[0].leaf_pages * 2);
+        assert!(pages(&shapes[3]) <= pages(&shapes[2]));
+    }
+
+    #[test]
+    fn test_update_upsert() {
+        let mut bufmgr = disk_manager::temp_bufmgr();
+        let btree = BTree::create(&mut bufmgr).unwrap();
+        for i in 0u64..50 {
+            btree
+                .insert(&mut bufmgr, &i.to_be_bytes(), b"small")
+                .unwrap();
+        }
+        assert!(matches!(
+            btree.update(&mut bufmgr, &50u64.to_be_bytes(), b"missing"),
+            Err(Error::KeyNotFound)
+        ));
+
+        btree
+            .update(&mut bufmgr, &7u64.to_be_bytes(), b"tiny")
+            .unwrap();
+        let large = vec![0xef; 1000];
+        for i in (0u64..50).step_by(5) {
+            btree.update(&mut bufmgr, &i.to_be_bytes(), &large).unwrap();
+        }
+        btree
+            .upsert(&mut bufmgr, &3u64.to_be_bytes(), b"upserted")
+            .unwrap();
+        btree
+            .upsert(&mut bufmgr, &60u64.to_be_bytes(), b"new")
+            .unwrap();
+        assert!(!root_is_leaf(&mut bufmgr, &btree));
+
+        let mut iter = btree.search(&mut bufmgr, SearchMode::Start).unwrap();
+        let mut count = 0;
+        while let Some((key, value)) = iter.next(&mut bufmgr).unwrap() {
+            let i = u64::from_be_bytes(key.try_into().unwrap());
+            let expected: &[u8] = match i {
+                3 => b"upserted",
+                7 => b"tiny",
+                60 => b"new",
+                i if i % 5 == 0 => &large,
+                _ => b"small",
+            };
+            assert_eq!(expected, value.as_slice());
+            count += 1;
+        }
+        assert_eq!(51, count);
+    }
+
+    #[test]
+    fn test_range() {
+        let mut bufmgr = disk_manager::temp_bufmgr();
+        let btree = BTree::create(&mut bufmgr).unwrap();
+        let value = vec![0xab; 500];
+        for i in (0u64..1000).step_by(2) {
+            btree.insert(&mut bufmgr, &i.to_be_bytes(), &value).unwrap();
+        }
+        // leave an empty leaf or two in the middle of the chain
+        for i in (300u64..340).step_by(2) {
+            btree.delete(&mut bufmgr, &i.to_be_bytes()).unwrap();
+        }
+        let walk =
+            |bufmgr: &mut BufferPoolManager, lower: Bound<u64>, upper: Bound<u64>, reverse| {
+                let (lower, upper) = (lower.map(u64::to_be_bytes), upper.map(u64::to_be_bytes));
+                let mut iter = btree
+                    .range(
+                        bufmgr,
+                        lower.as_ref().map(|key| &key[..]),
+                        upper.as_ref().map(|key| &key[..]),
+                        reverse,
+                    )
+                    .unwrap();
+                let mut keys = vec![];
+                loop {
+                    let pair = if reverse {
+                        iter.prev(bufmgr).unwrap()
+                    } else {
+                        iter.next(bufmgr).unwrap()
+                    };
+                    match pair {
+                        Some((key, _)) => keys.push(u64::from_be_bytes(key.try_into().unwrap())),
+                        None => return keys,
+                    }
+                }
+            };
+        let all: Vec<u64> = (0..1000)
+            .step_by(2)
+            .filter(|i| !(300..340).contains(i))
+            .collect();
+        use Bound::*;
+        assert_eq!(all, walk(&mut bufmgr, Unbounded, Unbounded, false));
+        let rev: Vec<u64> = all.iter().rev().copied().collect();
+        assert_eq!(rev, walk(&mut bufmgr, Unbounded, Unbounded, true));
+        let expected: Vec<u64> = all
+            .iter()
+            .copied()
+            .filter(|i| (100..200).contains(i))
+            .collect();
+        assert_eq!(
+            expected,
+            walk(&mut bufmgr, Included(100), Excluded(200), false)
+        );
+        let expected: Vec<u64> = all
+            .iter()
+            .copied()
+            .filter(|i| (290..=350).contains(i))
+            .collect();
+        assert_eq!(
+            expected,
+            walk(&mut bufmgr, Excluded(288), Included(350), false)
+        );
+        let expected: Vec<u64> = expected.into_iter().rev().collect();
+        assert_eq!(
+            expected,
+            walk(&mut bufmgr, Excluded(288), Included(350), true)
+        );
+        assert_eq!(
+            vec![998, 996],
+            walk(&mut bufmgr, Included(995), Unbounded, true)
+        );
+        assert_eq!(vec![2, 0], walk(&mut bufmgr, Unbounded, Excluded(4), true));
+        assert!(walk(&mut bufmgr, Included(301), Included(339), true).is_empty());
+        assert!(walk(&mut bufmgr, Included(500), Included(400), false).is_empty());
+
+        // a cursor yields the same pair when it turns around
+        let mut iter = btree
+            .search(&mut bufmgr, SearchMode::Key(100u64.to_be_bytes().to_vec()))
+            .unwrap();
+        let forward = iter.next(&mut bufmgr).unwrap();
+        assert_eq!(forward, iter.prev(&mut bufmgr).unwrap());
+        assert_eq!(
+            98u64.to_be_bytes().to_vec(),
+            iter.prev(&mut bufmgr).unwrap().unwrap().0
+        );
+        let mut iter = btree.search(&mut bufmgr, SearchMode::End).unwrap();
+        assert!(iter.next(&mut bufmgr).unwrap().is_none());
+        assert_eq!(
+            998u64.to_be_bytes().to_vec(),
+            iter.prev(&mut bufmgr).unwrap().unwrap().0
+        );
+
+        let btree = BTree::create(&mut bufmgr).unwrap();
+        for key in ["a", "ab", "abc", "b", "ba", "c"] {
+            btree.insert(&mut bufmgr, key.as_bytes(), b"").unwrap();
+        }
+        let strings =
+            |bufmgr: &mut BufferPoolManager, lower: Bound<&str>, upper: Bound<&str>, reverse| {
+                let mut iter = btree
+                    .range(
+                        bufmgr,
+                        lower.map(str::as_bytes),
+                        upper.map(str::as_bytes),
+                        reverse,
+                    )
+                    .unwrap();
+                let mut keys = vec![];
+                while let Some((key, _)) = if reverse {
+                    iter.prev(bufmgr).unwrap()
+                } else {
+                    iter.next(bufmgr).unwrap()
+                } {
+                    keys.push(String::from_utf8(key).unwrap());
+                }
+                keys
+            };
+        assert_eq!(
+            vec!["ab", "abc", "b", "ba"],
+            strings(&mut bufmgr, Included("ab"), Included("b"), false)
+        );
+        assert_eq!(
+            vec!["ba", "b", "abc", "ab"],
+            strings(&mut bufmgr, Included("ab"), Included("b"), true)
+        );
+        assert_eq!(
+            vec!["b", "ba"],
+            strings(&mut bufmgr, Excluded("a"), Excluded("c"), false)
+        );
+        assert_eq!(
+            vec!["ba", "b"],
+            strings(&mut bufmgr, Excluded("a"), Excluded("c"), true)
+        );
+    }
+
+    #[test]
+    fn test_overflow() {
+        let mut bufmgr = disk_manager::temp_bufmgr();
+        let live_pages = |bufmgr: &BufferPoolManager| bufmgr.num_pages() - bufmgr.num_free_pages();
+        let btree = BTree::create(&mut bufmgr).unwrap();
+        let value = |i: u64, len: usize| -> Vec<u8> {
+            (0..len).map(|j| (i as usize * 7 + j) as u8).collect()
+        };
+        let lens = [
+            0,
+            overflow::THRESHOLD,
+            overflow::THRESHOLD + 1,
+            3000,
+            9000,
+            100_000,
+        ];
+        for (i, &len) in lens.iter().enumerate() {
+            btree
+                .insert(
+                    &mut bufmgr,
+                    &(i as u64).to_be_bytes(),
+                    &value(i as u64, len),
+                )
+                .unwrap();
+        }
+        for (i, &len) in lens.iter().enumerate() {
+            let key = (i as u64).to_be_bytes();
+            assert_eq!(
+                Some(value(i as u64, len)),
+                btree.get(&mut bufmgr, &key).unwrap()
+            );
+        }
+        let mut iter = btree.search(&mut bufmgr, SearchMode::End).unwrap();
+        for (i, &len) in lens.iter().enumerate().rev() {
+            let (key, found) = iter.prev(&mut bufmgr).unwrap().unwrap();
+            assert_eq!((i as u64).to_be_bytes().to_vec(), key);
+            assert_eq!(value(i as u64, len), found);
+        }
+        drop(iter);
+
+        // a failed write must not leak the chain it stored
+        let before = live_pages(&bufmgr);
+        let result = btree.insert(&mut bufmgr, &5u64.to_be_bytes(), &value(0, 50_000));
+        assert!(matches!(result, Err(Error::DuplicateKey)));
+        assert_eq!(before, live_pages(&bufmgr));
+
+        btree
+            .update(&mut bufmgr, &4u64.to_be_bytes(), b"short")
+            .unwrap();
+        assert_eq!(before - 3, live_pages(&bufmgr));
+        btree
+            .upsert(&mut bufmgr, &0u64.to_be_bytes(), &value(0, 5000))
+            .unwrap();
+        assert_eq!(before - 1, live_pages(&bufmgr));
+        btree.delete(&mut bufmgr, &5u64.to_be_bytes()).unwrap();
+        assert_eq!(before - 1 - 25, live_pages(&bufmgr));
+        assert_eq!(
+            Some(value(0, 5000)),
+            btree.get(&mut bufmgr, &0u64.to_be_bytes()).unwrap()
+        );
+        assert_eq!(
+            Some(b"short".to_vec()),
+            btree.get(&mut bufmgr, &4u64.to_be_bytes()).unwrap()
+        );
+
+        btree.destroy(&mut bufmgr).unwrap();
+        assert_eq!(1, live_pages(&bufmgr));
     }
 }
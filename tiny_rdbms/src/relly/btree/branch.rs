@@ -56,13 +56,45 @@ impl<B: ByteSlice> Branch<B> {
         }
     }
 
-    pub fn pair_at(&self, slot_id: usize) -> Pair {
+    pub fn right_child(&self) -> PageId {
+        self.header.right_child
+    }
+
+    pub fn pair_at(&self, slot_id: usize) -> Pair<'_> {
         Pair::from_bytes(&self.body[slot_id])
     }
 
     pub fn max_pair_size(&self) -> usize {
         self.body.capacity() / 2 - size_of::<slotted::Pointer>()
     }
+
+    pub fn is_half_full(&self) -> bool {
+        2 * self.body.free_space() < self.body.capacity()
+    }
+
+    pub fn is_consistent(&self) -> bool {
+        self.body.is_consistent()
+    }
+
+    pub fn used_space(&self) -> usize {
+        self.body.capacity() - self.body.free_space()
+    }
+
+    pub fn free_space(&self) -> usize {
+        self.body.free_space()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.body.capacity()
+    }
+
+    pub fn pair_size(key: &[u8]) -> usize {
+        let pair = Pair {
+            key,
+            value: PageId::INVALID_PAGE_ID.as_bytes(),
+        };
+        pair.to_bytes().len() + size_of::<slotted::Pointer>()
+    }
 }
 
 impl<B: ByteSliceMut> Branch<B> {
@@ -73,6 +105,10 @@ impl<B: ByteSliceMut> Branch<B> {
         self.header.right_child = right_child;
     }
 
+    pub fn set_right_child(&mut self, right_child: PageId) {
+        self.header.right_child = right_child;
+    }
+
     pub fn fill_right_child(&mut self) -> Vec<u8> {
         let last_id = self.num_pairs() - 1;
         let Pair { key, value } = self.pair_at(last_id);
@@ -96,8 +132,18 @@ impl<B: ByteSliceMut> Branch<B> {
         Some(())
     }
 
-    fn is_half_full(&self) -> bool {
-        2 * self.body.free_space() < self.body.capacity()
+    pub fn remove(&mut self, slot_id: usize) {
+        self.body.remove(slot_id);
+    }
+
+    #[must_use = "replacement may fail"]
+    pub fn set_key_at(&mut self, slot_id: usize, key: &[u8]) -> Option<()> {
+        let value = self.pair_at(slot_id).value.to_vec();
+        let pair_bytes = Pair { key, value: &value }.to_bytes();
+        assert!(pair_bytes.len() <= self.max_pair_size());
+        self.body.resize(slot_id, pair_bytes.len())?;
+        self.body[slot_id].copy_from_slice(&pair_bytes);
+        Some(())
     }
 
     pub fn split_insert(
@@ -139,6 +185,62 @@ impl<B: ByteSliceMut> Branch<B> {
         dest.body[next_index].copy_from_slice(&self.body[0]);
         self.body.remove(0);
     }
+
+    #[must_use = "merge may fail"]
+    pub fn merge_into(
+        &mut self,
+        dest: &mut Branch<impl ByteSliceMut>,
+        separator: &[u8],
+    ) -> Option<()> {
+        if dest.body.free_space() < self.used_space() + Self::pair_size(separator) {
+            return None;
+        }
+        dest.insert(0, separator, self.header.right_child)
+            .expect("dest branch must have space");
+        while self.num_pairs() > 0 {
+            let last_id = self.num_pairs() - 1;
+            dest.body
+                .insert(0, self.body[last_id].len())
+                .expect("no space in dest branch");
+            dest.body[0].copy_from_slice(&self.body[last_id]);
+            self.body.remove(last_id);
+        }
+        Some(())
+    }
+
+    #[must_use = "shift may fail"]
+    pub fn shift_last(
+        &mut self,
+        dest: &mut Branch<impl ByteSliceMut>,
+        separator: &[u8],
+    ) -> Option<Vec<u8>> {
+        let last_id = self.num_pairs().checked_sub(1)?;
+        dest.insert(0, separator, self.header.right_child)?;
+        let Pair { key, value } = self.pair_at(last_id);
+        let right_child: PageId = value.into();
+        let key_vec = key.to_vec();
+        self.body.remove(last_id);
+        self.header.right_child = right_child;
+        Some(key_vec)
+    }
+
+    #[must_use = "shift may fail"]
+    pub fn shift_first(
+        &mut self,
+        dest: &mut Branch<impl ByteSliceMut>,
+        separator: &[u8],
+    ) -> Option<Vec<u8>> {
+        if self.num_pairs() == 0 {
+            return None;
+        }
+        dest.insert(dest.num_pairs(), separator, dest.header.right_child)?;
+        let Pair { key, value } = self.pair_at(0);
+        let left_child: PageId = value.into();
+        let key_vec = key.to_vec();
+        self.body.remove(0);
+        dest.header.right_child = left_child;
+        Some(key_vec)
+    }
 }
 
 #[cfg(test)]
@@ -186,4 +288,38 @@ mod tests {
         assert_eq!(PageId(2), branch.search_child(&11u64.to_be_bytes()));
         assert_eq!(PageId(2), branch.search_child(&12u64.to_be_bytes()));
     }
+
+    #[test]
+    fn test_merge_shift() {
+        let mut data = vec![0u8; 100];
+        let mut left = Branch::new(data.as_mut_slice());
+        left.initialize(&5u64.to_be_bytes(), PageId(1), PageId(2));
+
+        let mut data2 = vec![0u8; 100];
+        let mut right = Branch::new(data2.as_mut_slice());
+        right.initialize(&11u64.to_be_bytes(), PageId(3), PageId(4));
+
+        let separator = left.shift_last(&mut right, &8u64.to_be_bytes()).unwrap();
+        assert_eq!(&5u64.to_be_bytes(), separator.as_slice());
+        assert_eq!(0, left.num_pairs());
+        assert_eq!(PageId(1), left.right_child());
+        assert_eq!(PageId(2), right.search_child(&5u64.to_be_bytes()));
+        assert_eq!(PageId(3), right.search_child(&8u64.to_be_bytes()));
+
+        let separator = right.shift_first(&mut left, &separator).unwrap();
+        assert_eq!(&8u64.to_be_bytes(), separator.as_slice());
+        assert_eq!(PageId(2), left.right_child());
+        assert_eq!(1, right.num_pairs());
+
+        left.merge_into(&mut right, &separator).unwrap();
+        assert_eq!(3, right.num_pairs());
+        assert_eq!(PageId(1), right.search_child(&1u64.to_be_bytes()));
+        assert_eq!(PageId(2), right.search_child(&5u64.to_be_bytes()));
+        assert_eq!(PageId(3), right.search_child(&8u64.to_be_bytes()));
+        assert_eq!(PageId(4), right.search_child(&11u64.to_be_bytes()));
+
+        right.set_key_at(0, &3u64.to_be_bytes()).unwrap();
+        assert_eq!(PageId(1), right.search_child(&2u64.to_be_bytes()));
+        assert_eq!(PageId(2), right.search_child(&3u64.to_be_bytes()));
+    }
 }
@@ -0,0 +1,285 @@
+use std::cell::{Ref, RefMut};
+use std::cmp::Ordering;
+use std::mem::size_of;
+use std::ops::Range;
+use std::rc::Rc;
+
+use super::{branch, leaf, meta, node, overflow, shortest_separator, BTree, Error, Layout, Pair};
+use crate::buffer_pool_manager::{Buffer, BufferPoolManager};
+use crate::disk_manager::{PageId, PAGE_SIZE};
+use crate::relly::slotted;
+
+// leaves a little room in every node so that the first inserts after a load do not split
+pub const DEFAULT_FILL_FACTOR: f64 = 0.9;
+
+const LEAF_CAPACITY: usize = PAGE_SIZE
+    - size_of::<node::Header>()
+    - size_of::<leaf::Header>()
+    - size_of::<slotted::Header>();
+const BRANCH_CAPACITY: usize = PAGE_SIZE
+    - size_of::<node::Header>()
+    - size_of::<branch::Header>()
+    - size_of::<slotted::Header>();
+
+// the separator in front of a node and its page id; that of the leftmost node is never used
+type Entry = (Vec<u8>, PageId);
+
+impl BTree {
+    // builds a new tree with the default layout from pairs in strictly ascending key order;
+    // leaves are packed left to right up to `fill_factor` of their capacity, then the branch
+    // levels are built bottom-up
+    pub fn bulk_load(
+        bufmgr: &mut BufferPoolManager,
+        pairs: impl IntoIterator<Item = (Vec<u8>, Vec<u8>)>,
+        fill_factor: f64,
+    ) -> Result<Self, Error> {
+        if !(fill_factor > 0.0 && fill_factor <= 1.0) {
+            return Err(Error::FillFactor(fill_factor));
+        }
+        let meta_buffer = bufmgr.create_page()?;
+        let btree = Self::new(meta_buffer.page_id);
+        let mut level = vec![];
+        if let Err(err) = load_leaves(bufmgr, pairs, fill_factor, &mut level) {
+            drop(meta_buffer);
+            for &(_, page_id) in &level {
+                btree.destroy_node(bufmgr, page_id)?;
+            }
+            bufmgr.deallocate_page(btree.meta_page_id)?;
+            return Err(err);
+        }
+        while level.len() > 1 {
+            level = build_branches(bufmgr, &level, fill_factor)?;
+        }
+        let mut meta = meta::Meta::new(meta_buffer.page.borrow_mut() as RefMut<[_]>);
+        meta.header.root_page_id = level[0].1;
+        meta.set_layout(Layout::default());
+        Ok(btree)
+    }
+}
+
+fn load_leaves(
+    bufmgr: &mut BufferPoolManager,
+    pairs: impl IntoIterator<Item = (Vec<u8>, Vec<u8>)>,
+    fill_factor: f64,
+    leaves: &mut Vec<Entry>,
+) -> Result<(), Error> {
+    let limit = (LEAF_CAPACITY as f64 * fill_factor) as usize;
+    let mut buffer = create_leaf(bufmgr, None)?;
+    leaves.push((vec![], buffer.page_id));
+    let mut prev_key: Option<Vec<u8>> = None;
+    for (key, value) in pairs {
+        match prev_key.as_ref().map(|prev_key| key.cmp(prev_key)) {
+            Some(Ordering::Less) => return Err(Error::Unsorted),
+            Some(Ordering::Equal) => return Err(Error::DuplicateKey),
+            _ => {}
+        }
+        let stored = overflow::store(bufmgr, &value)?;
+        let pair_size = Pair {
+            key: &key,
+            value: &stored,
+        }
+        .to_bytes()
+        .len()
+            + size_of::<slotted::Pointer>();
+        let is_full = {
+            let node = node::Node::new(buffer.page.borrow() as Ref<[_]>);
+            let leaf = leaf::Leaf::new(node.body);
+            leaf.num_pairs() > 0 && leaf.used_space() + pair_size > limit
+        };
+        if is_full {
+            let new_buffer = create_leaf(bufmgr, Some(buffer.page_id))?;
+            {
+                let node = node::Node::new(buffer.page.borrow_mut() as RefMut<[_]>);
+                leaf::Leaf::new(node.body).set_next_page_id(Some(new_buffer.page_id));
+            }
+            let separator = match &prev_key {
+                Some(prev_key) => shortest_separator(prev_key, &key),
+                None => key.clone(),
+            };
+            leaves.push((separator, new_buffer.page_id));
+            buffer = new_buffer;
+        }
+        {
+            let node = node::Node::new(buffer.page.borrow_mut() as RefMut<[_]>);
+            let mut leaf = leaf::Leaf::new(node.body);
+            leaf.insert(leaf.num_pairs(), &key, &stored)
+                .expect("leaf must have space");
+        }
+        prev_key = Some(key);
+    }
+    Ok(())
+}
+
+fn create_leaf(
+    bufmgr: &mut BufferPoolManager,
+    prev_page_id: Option<PageId>,
+) -> Result<Rc<Buffer>, Error> {
+    let buffer = bufmgr.create_page()?;
+    {
+        let mut node = node::Node::new(buffer.page.borrow_mut() as RefMut<[_]>);
+        node.initialize_as_leaf();
+        let mut leaf = leaf::Leaf::new(node.body);
+        leaf.initialize();
+        leaf.set_prev_page_id(prev_page_id);
+    }
+    Ok(buffer)
+}
+
+// one level up from `children`: every branch takes children while its separators fit within the
+// fill factor, and always at least two of them
+fn build_branches(
+    bufmgr: &mut BufferPoolManager,
+    children: &[Entry],
+    fill_factor: f64,
+) -> Result<Vec<Entry>, Error> {
+    let limit = (BRANCH_CAPACITY as f64 * fill_factor) as usize;
+    let pair_size = |idx: usize| branch::Branch::<&[u8]>::pair_size(&children[idx].0);
+    let mut groups: Vec<Range<usize>> = vec![];
+    let mut start = 0;
+    let (mut used, mut prev_used) = (0, 0);
+    for idx in 1..children.len() {
+        if idx - start >= 2 && used + pair_size(idx) > limit {
+            groups.push(start..idx);
+            start = idx;
+            prev_used = used;
+            used = 0;
+        } else {
+            used += pair_size(idx);
+        }
+    }
+    if children.len() - start == 1 {
+        // a lone last child joins the previous branch if it fits, or takes its last child along
+        let prev = groups.last_mut().expect("first branch has two children");
+        if prev_used + pair_size(start) <= BRANCH_CAPACITY {
+            prev.end = children.len();
+        } else {
+            prev.end -= 1;
+            let start = prev.end;
+            groups.push(start..children.len());
+        }
+    } else {
+        groups.push(start..children.len());
+    }
+
+    let mut parents = vec![];
+    for group in groups {
+        let buffer = bufmgr.create_page()?;
+        let mut node = node::Node::new(buffer.page.borrow_mut() as RefMut<[_]>);
+        node.initialize_as_branch();
+        let mut branch = branch::Branch::new(node.body);
+        let (first, second) = (&children[group.start], &children[group.start + 1]);
+        branch.initialize(&second.0, first.1, second.1);
+        for (key, page_id) in &children[group.start + 2..group.end] {
+            branch
+                .insert(branch.num_pairs(), key, branch.right_child())
+                .expect("branch must have space");
+            branch.set_right_child(*page_id);
+        }
+        parents.push((first.0.clone(), buffer.page_id));
+    }
+    Ok(parents)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::disk_manager;
+    use crate::relly::btree::SearchMode;
+
+    fn live_pages(bufmgr: &BufferPoolManager) -> u64 {
+        bufmgr.num_pages() - bufmgr.num_free_pages()
+    }
+
+    fn pair(i: u64) -> (Vec<u8>, Vec<u8>) {
+        let len = if i.is_multiple_of(1000) { 3000 } else { 20 };
+        (i.to_be_bytes().to_vec(), vec![i as u8; len])
+    }
+
+    #[test]
+    fn test_bulk_load() {
+        for (num_pairs, fill_factor) in [(0, 1.0), (1, 1.0), (20_000, 1.0), (20_000, 0.5)] {
+            let mut bufmgr = disk_manager::temp_bufmgr();
+            let btree =
+                BTree::bulk_load(&mut bufmgr, (0..num_pairs).map(pair), fill_factor).unwrap();
+            assert!(btree
+                .check(&mut bufmgr, &mut HashSet::new())
+                .unwrap()
+                .is_empty());
+            let mut iter = btree.search(&mut bufmgr, SearchMode::Start).unwrap();
+            for i in 0..num_pairs {
+                assert_eq!(Some(pair(i)), iter.next(&mut bufmgr).unwrap());
+            }
+            assert_eq!(None, iter.next(&mut bufmgr).unwrap());
+            drop(iter);
+
+            // the loaded tree keeps working as a regular one
+            for i in (0..num_pairs).step_by(7) {
+                btree.delete(&mut bufmgr, &i.to_be_bytes()).unwrap();
+            }
+            btree
+                .insert(&mut bufmgr, &u64::MAX.to_be_bytes(), b"last")
+                .unwrap();
+            let expected: Vec<_> = (0..num_pairs)
+                .filter(|i| !i.is_multiple_of(7))
+                .map(pair)
+                .chain([(u64::MAX.to_be_bytes().to_vec(), b"last".to_vec())])
+                .collect();
+            let mut iter = btree.search(&mut bufmgr, SearchMode::Start).unwrap();
+            let mut found = vec![];
+            while let Some(pair) = iter.next(&mut bufmgr).unwrap() {
+                found.push(pair);
+            }
+            drop(iter);
+            assert_eq!(expected, found);
+            assert!(btree
+                .check(&mut bufmgr, &mut HashSet::new())
+                .unwrap()
+                .is_empty());
+        }
+    }
+
+    #[test]
+    fn test_bulk_load_packed() {
+        let mut bufmgr = disk_manager::temp_bufmgr();
+        let before = live_pages(&bufmgr);
+        let btree = BTree::create(&mut bufmgr).unwrap();
+        for (key, value) in (0..20_000).map(pair) {
+            btree.insert(&mut bufmgr, &key, &value).unwrap();
+        }
+        let inserted = live_pages(&bufmgr) - before;
+
+        let before = live_pages(&bufmgr);
+        BTree::bulk_load(&mut bufmgr, (0..20_000).map(pair), 1.0).unwrap();
+        let packed = live_pages(&bufmgr) - before;
+        let before = live_pages(&bufmgr);
+        BTree::bulk_load(&mut bufmgr, (0..20_000).map(pair), DEFAULT_FILL_FACTOR).unwrap();
+        let loaded = live_pages(&bufmgr) - before;
+        assert!(packed < loaded && loaded < inserted);
+        assert!(packed * 10 < inserted * 6);
+    }
+
+    #[test]
+    fn test_bulk_load_unsorted() {
+        let mut bufmgr = disk_manager::temp_bufmgr();
+        let before = live_pages(&bufmgr);
+        let unsorted = (0..5000).map(pair).chain([pair(42)]);
+        assert!(matches!(
+            BTree::bulk_load(&mut bufmgr, unsorted, 1.0),
+            Err(Error::Unsorted)
+        ));
+        let duplicate = (0..5000).map(pair).chain([pair(4999)]);
+        assert!(matches!(
+            BTree::bulk_load(&mut bufmgr, duplicate, 1.0),
+            Err(Error::DuplicateKey)
+        ));
+        for fill_factor in [0.0, 1.5, f64::NAN] {
+            assert!(matches!(
+                BTree::bulk_load(&mut bufmgr, (0..10).map(pair), fill_factor),
+                Err(Error::FillFactor(_))
+            ));
+        }
+        assert_eq!(before, live_pages(&bufmgr));
+    }
+}
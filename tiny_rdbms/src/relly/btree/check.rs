@@ -0,0 +1,230 @@
+use std::cell::Ref;
+use std::collections::HashSet;
+use std::rc::Rc;
+
+use super::{branch, leaf, meta, node, overflow, BTree, Error};
+use crate::buffer_pool_manager::{self, Buffer, BufferPoolManager};
+use crate::disk_manager::PageId;
+
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum Corruption {
+    #[error("page {0:?} fails its checksum")]
+    Checksum(PageId),
+    #[error("page {0:?} is reachable more than once")]
+    ReachableTwice(PageId),
+    #[error("page {0:?} is not a btree node")]
+    NodeType(PageId),
+    #[error("free space accounting of page {0:?} is inconsistent")]
+    FreeSpace(PageId),
+    #[error("keys of page {0:?} are out of order")]
+    KeyOrder(PageId),
+    #[error("keys of page {0:?} fall outside the range given by its parent")]
+    KeyRange(PageId),
+    #[error("sibling links of leaf {0:?} do not match its neighbours")]
+    LeafLink(PageId),
+}
+
+struct Checker<'a> {
+    bufmgr: &'a mut BufferPoolManager,
+    visited: &'a mut HashSet<PageId>,
+    // (page id, prev, next) of every leaf in key order
+    leaves: Vec<(PageId, Option<PageId>, Option<PageId>)>,
+    corruptions: Vec<Corruption>,
+}
+
+impl BTree {
+    // walks the whole tree without modifying it; `visited` is shared between trees of the same
+    // file so that a page claimed by two of them is reported as well
+    pub fn check(
+        &self,
+        bufmgr: &mut BufferPoolManager,
+        visited: &mut HashSet<PageId>,
+    ) -> Result<Vec<Corruption>, Error> {
+        let mut checker = Checker {
+            bufmgr,
+            visited,
+            leaves: vec![],
+            corruptions: vec![],
+        };
+        if let Some(meta_buffer) = checker.visit(self.meta_page_id)? {
+            let root_page_id = meta::Meta::new(meta_buffer.page.borrow() as Ref<[_]>)
+                .header
+                .root_page_id;
+            drop(meta_buffer);
+            checker.check_node(root_page_id, None, None)?;
+            checker.check_leaf_links();
+        }
+        Ok(checker.corruptions)
+    }
+}
+
+impl Checker<'_> {
+    fn visit(&mut self, page_id: PageId) -> Result<Option<Rc<Buffer>>, Error> {
+        if !self.visited.insert(page_id) {
+            self.corruptions.push(Corruption::ReachableTwice(page_id));
+            return Ok(None);
+        }
+        match self.bufmgr.fetch_page(page_id) {
+            Ok(buffer) => Ok(Some(buffer)),
+            Err(buffer_pool_manager::Error::Corrupted(page_id)) => {
+                self.corruptions.push(Corruption::Checksum(page_id));
+                Ok(None)
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    // every key of the subtree must lie in `lower..upper`
+    fn check_node(
+        &mut self,
+        page_id: PageId,
+        lower: Option<&[u8]>,
+        upper: Option<&[u8]>,
+    ) -> Result<(), Error> {
+        let buffer = match self.visit(page_id)? {
+            Some(buffer) => buffer,
+            None => return Ok(()),
+        };
+        let mut values = vec![];
+        let mut children = vec![];
+        let keys = {
+            let node = node::Node::new(buffer.page.borrow() as Ref<[_]>);
+            match node.header.node_type {
+                node::NODE_TYPE_LEAF => {
+                    let leaf = leaf::Leaf::new(node.body);
+                    if !leaf.is_consistent() {
+                        self.corruptions.push(Corruption::FreeSpace(page_id));
+                        return Ok(());
+                    }
+                    self.leaves
+                        .push((page_id, leaf.prev_page_id(), leaf.next_page_id()));
+                    (0..leaf.num_pairs())
+                        .map(|slot_id| {
+                            let pair = leaf.pair_at(slot_id);
+                            values.push(pair.value.to_vec());
+                            pair.key.to_vec()
+                        })
+                        .collect::<Vec<_>>()
+                }
+                node::NODE_TYPE_BRANCH => {
+                    let branch = branch::Branch::new(node.body);
+                    if !branch.is_consistent() {
+                        self.corruptions.push(Corruption::FreeSpace(page_id));
+                        return Ok(());
+                    }
+                    children.extend((0..=branch.num_pairs()).map(|idx| branch.child_at(idx)));
+                    (0..branch.num_pairs())
+                        .map(|slot_id| branch.pair_at(slot_id).key.to_vec())
+                        .collect()
+                }
+                _ => {
+                    self.corruptions.push(Corruption::NodeType(page_id));
+                    return Ok(());
+                }
+            }
+        };
+        drop(buffer);
+
+        if keys.windows(2).any(|pair| pair[0] >= pair[1]) {
+            self.corruptions.push(Corruption::KeyOrder(page_id));
+        }
+        let in_range = |key: &Vec<u8>| {
+            lower.is_none_or(|lower| lower <= key.as_slice())
+                && upper.is_none_or(|upper| key.as_slice() < upper)
+        };
+        if !keys.iter().all(in_range) {
+            self.corruptions.push(Corruption::KeyRange(page_id));
+        }
+        for value in &values {
+            self.check_overflow(value)?;
+        }
+        // child i of a branch holds the keys from separator i - 1 up to separator i
+        for (idx, &child_page_id) in children.iter().enumerate() {
+            let child_lower = idx.checked_sub(1).map(|idx| keys[idx].as_slice()).or(lower);
+            let child_upper = keys.get(idx).map(Vec::as_slice).or(upper);
+            self.check_node(child_page_id, child_lower, child_upper)?;
+        }
+        Ok(())
+    }
+
+    fn check_overflow(&mut self, stored: &[u8]) -> Result<(), Error> {
+        let mut next_page_id = overflow::first_page_id(stored);
+        while let Some(page_id) = next_page_id {
+            next_page_id = match self.visit(page_id)? {
+                Some(buffer) => {
+                    overflow::Overflow::new(buffer.page.borrow() as Ref<[_]>).next_page_id()
+                }
+                None => None,
+            };
+        }
+        Ok(())
+    }
+
+    fn check_leaf_links(&mut self) {
+        for (idx, &(page_id, prev_page_id, next_page_id)) in self.leaves.iter().enumerate() {
+            let expected_prev = idx.checked_sub(1).map(|idx| self.leaves[idx].0);
+            let expected_next = self.leaves.get(idx + 1).map(|leaf| leaf.0);
+            if prev_page_id != expected_prev || next_page_id != expected_next {
+                self.corruptions.push(Corruption::LeafLink(page_id));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefMut;
+
+    use super::*;
+    use crate::buffer_pool_manager::BufferPool;
+    use crate::disk_manager::{self, DiskManager};
+    use crate::relly::btree::SearchMode;
+
+    #[test]
+    fn test_check() {
+        let disk = DiskManager::new(disk_manager::tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let btree = BTree::create(&mut bufmgr).unwrap();
+        for i in 0u64..500 {
+            let len = if i.is_multiple_of(50) { 5000 } else { 100 };
+            btree
+                .insert(&mut bufmgr, &i.to_be_bytes(), &vec![i as u8; len])
+                .unwrap();
+        }
+        for i in (0u64..500).step_by(3) {
+            btree.delete(&mut bufmgr, &i.to_be_bytes()).unwrap();
+        }
+        let mut visited = HashSet::new();
+        assert_eq!(
+            Vec::<Corruption>::new(),
+            btree.check(&mut bufmgr, &mut visited).unwrap()
+        );
+        let live_pages = bufmgr.num_pages() - bufmgr.num_free_pages() - 1;
+        assert_eq!(live_pages, visited.len() as u64);
+        assert_eq!(
+            vec![Corruption::ReachableTwice(btree.meta_page_id)],
+            btree.check(&mut bufmgr, &mut visited).unwrap()
+        );
+
+        let iter = btree
+            .search(&mut bufmgr, SearchMode::Key(300u64.to_be_bytes().to_vec()))
+            .unwrap();
+        let leaf_buffer = iter.buffer.clone();
+        drop(iter);
+        {
+            let mut node = node::Node::new(leaf_buffer.page.borrow_mut() as RefMut<[_]>);
+            let mut leaf = leaf::Leaf::new(&mut node.body[..]);
+            assert!(leaf.next_page_id().is_some());
+            leaf.set_next_page_id(None);
+        }
+        assert_eq!(
+            vec![Corruption::LeafLink(leaf_buffer.page_id)],
+            btree.check(&mut bufmgr, &mut HashSet::new()).unwrap()
+        );
+
+        // the slotted page header sits right after the node and leaf headers
+        leaf_buffer.page.borrow_mut()[34] ^= 0x01;
+        let corruptions = btree.check(&mut bufmgr, &mut HashSet::new()).unwrap();
+        assert!(corruptions.contains(&Corruption::FreeSpace(leaf_buffer.page_id)));
+    }
+}
@@ -1,17 +1,30 @@
+use std::borrow::Cow;
 use std::mem::size_of;
 
 use zerocopy::{AsBytes, ByteSlice, ByteSliceMut, FromBytes, LayoutVerified};
 
-use super::Pair;
+use super::{common_prefix_len, Pair};
 use crate::disk_manager::PageId;
 use crate::relly::bsearch::binary_search_by;
 use crate::relly::slotted::{self, Slotted};
 
+// the keys of a compressed leaf all start with the prefix kept in its first slot, and the pairs
+// in the other slots hold only the rest of their keys
+const PREFIX_COMPRESSION: u32 = 1;
+
 #[derive(Debug, FromBytes, AsBytes)]
 #[repr(C)]
 pub struct Header {
     prev_page_id: PageId,
     next_page_id: PageId,
+    flags: u32,
+    _pad: u32,
+}
+
+// a pair of a leaf, with the key put back together from the page prefix when compressed
+pub struct LeafPair<'a> {
+    pub key: Cow<'a, [u8]>,
+    pub value: &'a [u8],
 }
 
 pub struct Leaf<B> {
@@ -35,38 +48,110 @@ impl<B: ByteSlice> Leaf<B> {
         self.header.next_page_id.valid()
     }
 
+    pub fn is_compressed(&self) -> bool {
+        self.header.flags & PREFIX_COMPRESSION != 0
+    }
+
+    // the prefix every key of the leaf starts with, which is empty unless it is compressed
+    pub fn prefix(&self) -> &[u8] {
+        if self.is_compressed() {
+            &self.body[0]
+        } else {
+            &[]
+        }
+    }
+
+    fn first_slot(&self) -> usize {
+        self.is_compressed() as usize
+    }
+
     pub fn num_pairs(&self) -> usize {
-        self.body.num_slots()
+        self.body.num_slots().saturating_sub(self.first_slot())
     }
 
     pub fn search_slot_id(&self, key: &[u8]) -> Result<usize, usize> {
-        binary_search_by(self.num_pairs(), |slot_id| {
-            self.pair_at(slot_id).key.cmp(key)
-        })
+        let prefix = self.prefix();
+        match key.strip_prefix(prefix) {
+            Some(rest) => binary_search_by(self.num_pairs(), |slot_id| {
+                self.stored_pair(slot_id).key.cmp(rest)
+            }),
+            // a key without the prefix sorts before or after all of the keys with it
+            None if key < prefix => Err(0),
+            None => Err(self.num_pairs()),
+        }
     }
 
     #[cfg(test)]
-    pub fn search_pair(&self, key: &[u8]) -> Option<Pair> {
+    pub fn search_pair(&self, key: &[u8]) -> Option<LeafPair<'_>> {
         let slot_id = self.search_slot_id(key).ok()?;
         Some(self.pair_at(slot_id))
     }
 
-    pub fn pair_at(&self, slot_id: usize) -> Pair {
-        Pair::from_bytes(&self.body[slot_id])
+    pub fn pair_at(&self, slot_id: usize) -> LeafPair<'_> {
+        let Pair { key, value } = self.stored_pair(slot_id);
+        let key = match self.prefix() {
+            [] => Cow::Borrowed(key),
+            prefix => Cow::Owned([prefix, key].concat()),
+        };
+        LeafPair { key, value }
+    }
+
+    // the pair as it is stored, with the prefix taken off its key
+    fn stored_pair(&self, slot_id: usize) -> Pair<'_> {
+        Pair::from_bytes(&self.body[self.first_slot() + slot_id])
+    }
+
+    fn pairs(&self) -> Vec<(Vec<u8>, Vec<u8>)> {
+        (0..self.num_pairs())
+            .map(|slot_id| {
+                let pair = self.pair_at(slot_id);
+                (pair.key.into_owned(), pair.value.to_vec())
+            })
+            .collect()
     }
 
     pub fn max_pair_size(&self) -> usize {
         self.body.capacity() / 2 - size_of::<slotted::Pointer>()
     }
+
+    pub fn is_half_full(&self) -> bool {
+        2 * self.body.free_space() < self.body.capacity()
+    }
+
+    pub fn is_consistent(&self) -> bool {
+        self.body.is_consistent() && self.body.num_slots() >= self.first_slot()
+    }
+
+    pub fn used_space(&self) -> usize {
+        self.body.capacity() - self.body.free_space()
+    }
+
+    pub fn free_space(&self) -> usize {
+        self.body.free_space()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.body.capacity()
+    }
 }
 
 impl<B: ByteSliceMut> Leaf<B> {
     pub fn initialize(&mut self) {
         self.header.prev_page_id = PageId::INVALID_PAGE_ID;
         self.header.next_page_id = PageId::INVALID_PAGE_ID;
+        self.header.flags = 0;
         self.body.initialize();
     }
 
+    // an empty leaf whose keys get stored without the prefix they share
+    pub fn initialize_compressed(&mut self) {
+        self.initialize();
+        self.header.flags = PREFIX_COMPRESSION;
+        self.body
+            .insert(0, 0)
+            .expect("new leaf must have space for its prefix");
+    }
+
     pub fn set_prev_page_id(&mut self, prev_page_id: Option<PageId>) {
         self.header.prev_page_id = prev_page_id.into()
     }
@@ -77,24 +162,87 @@ impl<B: ByteSliceMut> Leaf<B> {
 
     #[must_use = "insertion may fail"]
     pub fn insert(&mut self, slot_id: usize, key: &[u8], value: &[u8]) -> Option<()> {
-        let pair = Pair { key, value };
+        let prefix_len = self.prefix().len();
+        if !key.starts_with(self.prefix()) {
+            // the prefix shrinks to what the new key shares with it
+            let mut pairs = self.pairs();
+            pairs.insert(slot_id, (key.to_vec(), value.to_vec()));
+            return self.pack(&pairs);
+        }
+        let pair = Pair {
+            key: &key[prefix_len..],
+            value,
+        };
         let pair_bytes = pair.to_bytes();
         assert!(pair_bytes.len() <= self.max_pair_size());
+        let slot_id = self.first_slot() + slot_id;
         self.body.insert(slot_id, pair_bytes.len())?;
         self.body[slot_id].copy_from_slice(&pair_bytes);
         Some(())
     }
 
-    fn is_half_full(&self) -> bool {
-        2 * self.body.free_space() < self.body.capacity()
+    #[must_use = "update may fail"]
+    pub fn update(&mut self, slot_id: usize, value: &[u8]) -> Option<()> {
+        let key = self.stored_pair(slot_id).key.to_vec();
+        let pair = Pair { key: &key, value };
+        let pair_bytes = pair.to_bytes();
+        assert!(pair_bytes.len() <= self.max_pair_size());
+        let slot_id = self.first_slot() + slot_id;
+        self.body.resize(slot_id, pair_bytes.len())?;
+        self.body[slot_id].copy_from_slice(&pair_bytes);
+        Some(())
+    }
+
+    pub fn remove(&mut self, slot_id: usize) {
+        let slot_id = self.first_slot() + slot_id;
+        self.body.remove(slot_id);
+    }
+
+    // rewrites the leaf with `pairs`, which must be in key order, stored under the longest prefix
+    // they share if the leaf is compressed; nothing changes when they do not fit
+    #[must_use = "packing may fail"]
+    fn pack(&mut self, pairs: &[(Vec<u8>, Vec<u8>)]) -> Option<()> {
+        let compressed = self.is_compressed();
+        if packed_size(pairs, compressed) > self.body.capacity() {
+            return None;
+        }
+        self.body.initialize();
+        let prefix = match pairs.first() {
+            Some((key, _)) if compressed => &key[..shared_prefix_len(pairs)],
+            _ => &[],
+        };
+        if compressed {
+            self.body
+                .insert(0, prefix.len())
+                .expect("leaf must have space for its prefix");
+            self.body[0].copy_from_slice(prefix);
+        }
+        for (key, value) in pairs {
+            let pair_bytes = Pair {
+                key: &key[prefix.len()..],
+                value,
+            }
+            .to_bytes();
+            let slot_id = self.body.num_slots();
+            self.body
+                .insert(slot_id, pair_bytes.len())
+                .expect("leaf must have space");
+            self.body[slot_id].copy_from_slice(&pair_bytes);
+        }
+        Some(())
     }
 
+    // the new leaf takes the lower half of the pairs; the tree picks the separator between them
     pub fn split_insert(
         &mut self,
         new_leaf: &mut Leaf<impl ByteSliceMut>,
         new_key: &[u8],
         new_value: &[u8],
-    ) -> Vec<u8> {
+    ) {
+        if self.is_compressed() {
+            new_leaf.initialize_compressed();
+            return self.split_insert_packed(new_leaf, new_key, new_value);
+        }
         new_leaf.initialize();
         loop {
             if new_leaf.is_half_full() {
@@ -105,26 +253,124 @@ impl<B: ByteSliceMut> Leaf<B> {
                     .expect("old leaf must have space");
                 break;
             }
-            if self.pair_at(0).key < new_key {
-                self.transfer(new_leaf);
+            if *self.pair_at(0).key < *new_key {
+                self.transfer(new_leaf).expect("new leaf must have space");
             } else {
                 new_leaf
                     .insert(new_leaf.num_pairs(), new_key, new_value)
                     .expect("new leaf must have space");
                 while !new_leaf.is_half_full() {
-                    self.transfer(new_leaf);
+                    self.transfer(new_leaf).expect("new leaf must have space");
                 }
                 break;
             }
         }
-        self.pair_at(0).key.to_vec()
     }
 
-    pub fn transfer(&mut self, dest: &mut Leaf<impl ByteSliceMut>) {
-        let next_index = dest.num_pairs();
-        assert!(dest.body.insert(next_index, self.body[0].len()).is_some());
-        dest.body[next_index].copy_from_slice(&self.body[0]);
-        self.body.remove(0);
+    // a compressed leaf can hold more than two leaves do when their prefixes are shorter, so the
+    // split point is the most even one at which both halves fit under their own prefix
+    fn split_insert_packed(
+        &mut self,
+        new_leaf: &mut Leaf<impl ByteSliceMut>,
+        new_key: &[u8],
+        new_value: &[u8],
+    ) {
+        let slot_id = self
+            .search_slot_id(new_key)
+            .expect_err("key must be unique");
+        let mut pairs = self.pairs();
+        pairs.insert(slot_id, (new_key.to_vec(), new_value.to_vec()));
+
+        let prefix_len = shared_prefix_len(&pairs);
+        let sizes: Vec<_> = pairs
+            .iter()
+            .map(|(key, value)| pair_size(&key[prefix_len..], value))
+            .collect();
+        let total: usize = sizes.iter().sum();
+        let mut used = 0;
+        let even = sizes
+            .iter()
+            .position(|size| {
+                used += size;
+                2 * used >= total
+            })
+            .map_or(1, |idx| idx + 1);
+        // a new key without the old prefix sorts before or after all the others, so splitting it
+        // off leaves the old pairs as they were and always fits
+        let capacity = self.body.capacity();
+        let mid = [even, slot_id, slot_id + 1]
+            .into_iter()
+            .chain(1..pairs.len())
+            .filter(|mid| (1..pairs.len()).contains(mid))
+            .find(|&mid| {
+                packed_size(&pairs[..mid], true) <= capacity
+                    && packed_size(&pairs[mid..], true) <= capacity
+            })
+            .expect("split leaves must have space");
+        new_leaf
+            .pack(&pairs[..mid])
+            .expect("new leaf must have space");
+        self.pack(&pairs[mid..]).expect("old leaf must have space");
+    }
+
+    #[must_use = "transfer may fail"]
+    pub fn transfer(&mut self, dest: &mut Leaf<impl ByteSliceMut>) -> Option<()> {
+        let LeafPair { key, value } = self.pair_at(0);
+        dest.insert(dest.num_pairs(), &key, value)?;
+        self.remove(0);
+        Some(())
+    }
+
+    #[must_use = "transfer may fail"]
+    pub fn transfer_last(&mut self, dest: &mut Leaf<impl ByteSliceMut>) -> Option<()> {
+        let last_id = self.num_pairs().checked_sub(1)?;
+        let LeafPair { key, value } = self.pair_at(last_id);
+        dest.insert(0, &key, value)?;
+        self.remove(last_id);
+        Some(())
+    }
+
+    // moves every pair into `dest`, the next leaf, packing it anew; nothing changes when they do
+    // not fit
+    #[must_use = "merge may fail"]
+    pub fn merge_into(&mut self, dest: &mut Leaf<impl ByteSliceMut>) -> Option<()> {
+        let mut pairs = self.pairs();
+        pairs.extend(dest.pairs());
+        dest.pack(&pairs)?;
+        self.pack(&[]).expect("empty leaf must have space");
+        Some(())
+    }
+}
+
+fn pair_size(key: &[u8], value: &[u8]) -> usize {
+    Pair { key, value }.to_bytes().len() + size_of::<slotted::Pointer>()
+}
+
+// the space `pairs` take up in a leaf, stored under their shared prefix when compressed
+fn packed_size(pairs: &[(Vec<u8>, Vec<u8>)], compressed: bool) -> usize {
+    let prefix_len = if compressed {
+        shared_prefix_len(pairs)
+    } else {
+        0
+    };
+    let size: usize = pairs
+        .iter()
+        .map(|(key, value)| pair_size(&key[prefix_len..], value))
+        .sum();
+    if compressed {
+        size + prefix_len + size_of::<slotted::Pointer>()
+    } else {
+        size
+    }
+}
+
+fn shared_prefix_len(pairs: &[(Vec<u8>, Vec<u8>)]) -> usize {
+    let mut keys = pairs.iter().map(|(key, _)| key.as_slice());
+    match keys.next() {
+        Some(first) => keys.fold(first.len(), |len, key| {
+            common_prefix_len(&first[..len], key)
+        }),
+        None => 0,
     }
 }
 
@@ -134,27 +380,27 @@ mod tests {
 
     #[test]
     fn test_leaf_insert() {
-        let mut page_data = vec![0; 100];
+        let mut page_data = vec![0; 108];
         let mut leaf_page = Leaf::new(page_data.as_mut_slice());
         leaf_page.initialize();
 
         let id = leaf_page.search_slot_id(b"deadbeef").unwrap_err();
         assert_eq!(0, id);
         leaf_page.insert(id, b"deadbeef", b"world").unwrap();
-        assert_eq!(b"deadbeef", leaf_page.pair_at(0).key);
+        assert_eq!(b"deadbeef", leaf_page.pair_at(0).key.as_ref());
 
         let id = leaf_page.search_slot_id(b"facebook").unwrap_err();
         assert_eq!(1, id);
         leaf_page.insert(id, b"facebook", b"!").unwrap();
-        assert_eq!(b"deadbeef", leaf_page.pair_at(0).key);
-        assert_eq!(b"facebook", leaf_page.pair_at(1).key);
+        assert_eq!(b"deadbeef", leaf_page.pair_at(0).key.as_ref());
+        assert_eq!(b"facebook", leaf_page.pair_at(1).key.as_ref());
 
         let id = leaf_page.search_slot_id(b"beefdead").unwrap_err();
         assert_eq!(0, id);
         leaf_page.insert(id, b"beefdead", b"hello").unwrap();
-        assert_eq!(b"beefdead", leaf_page.pair_at(0).key);
-        assert_eq!(b"deadbeef", leaf_page.pair_at(1).key);
-        assert_eq!(b"facebook", leaf_page.pair_at(2).key);
+        assert_eq!(b"beefdead", leaf_page.pair_at(0).key.as_ref());
+        assert_eq!(b"deadbeef", leaf_page.pair_at(1).key.as_ref());
+        assert_eq!(b"facebook", leaf_page.pair_at(2).key.as_ref());
         assert_eq!(
             &b"hello"[..],
             leaf_page.search_pair(b"beefdead").unwrap().value
@@ -163,7 +409,7 @@ mod tests {
 
     #[test]
     fn test_leaf_split_insert() {
-        let mut page_data = vec![0; 62];
+        let mut page_data = vec![0; 70];
         let mut leaf_page = Leaf::new(page_data.as_mut_slice());
         leaf_page.initialize();
         let id = leaf_page.search_slot_id(b"deadbeef").unwrap_err();
@@ -174,7 +420,7 @@ mod tests {
         assert!(leaf_page.insert(id, b"beefdead", b"hello").is_none());
 
         let mut leaf_page = Leaf::new(page_data.as_mut_slice());
-        let mut new_page_data = vec![0; 62];
+        let mut new_page_data = vec![0; 70];
         let mut new_leaf_page = Leaf::new(new_page_data.as_mut_slice());
         leaf_page.split_insert(&mut new_leaf_page, b"beefdead", b"hello");
         assert_eq!(
@@ -182,4 +428,113 @@ mod tests {
             new_leaf_page.search_pair(b"deadbeef").unwrap().value
         );
     }
+
+    #[test]
+    fn test_leaf_update() {
+        let mut page_data = vec![0; 108];
+        let mut leaf_page = Leaf::new(page_data.as_mut_slice());
+        leaf_page.initialize();
+        leaf_page.insert(0, b"beefdead", b"hello").unwrap();
+        leaf_page.insert(1, b"cafebabe", b"hello").unwrap();
+        leaf_page.insert(2, b"deadbeef", b"world").unwrap();
+        leaf_page.insert(3, b"facebook", b"!").unwrap();
+
+        leaf_page.update(2, b"hi").unwrap();
+        assert_eq!(
+            &b"hi"[..],
+            leaf_page.search_pair(b"deadbeef").unwrap().value
+        );
+        leaf_page.update(3, b"!!!!").unwrap();
+        assert_eq!(
+            &b"!!!!"[..],
+            leaf_page.search_pair(b"facebook").unwrap().value
+        );
+        assert_eq!(
+            &b"hi"[..],
+            leaf_page.search_pair(b"deadbeef").unwrap().value
+        );
+        assert_eq!(
+            &b"hello"[..],
+            leaf_page.search_pair(b"cafebabe").unwrap().value
+        );
+        assert!(leaf_page.update(2, b"hello, world").is_none());
+        assert_eq!(
+            &b"hi"[..],
+            leaf_page.search_pair(b"deadbeef").unwrap().value
+        );
+    }
+
+    #[test]
+    fn test_leaf_remove_transfer_last() {
+        let mut page_data = vec![0; 108];
+        let mut leaf_page = Leaf::new(page_data.as_mut_slice());
+        leaf_page.initialize();
+        leaf_page.insert(0, b"deadbeef", b"world").unwrap();
+        leaf_page.insert(1, b"facebook", b"!").unwrap();
+        leaf_page.insert(0, b"beefdead", b"hello").unwrap();
+
+        leaf_page.remove(1);
+        assert_eq!(2, leaf_page.num_pairs());
+        assert!(leaf_page.search_pair(b"deadbeef").is_none());
+        assert_eq!(&b"!"[..], leaf_page.search_pair(b"facebook").unwrap().value);
+
+        let mut new_page_data = vec![0; 108];
+        let mut new_leaf_page = Leaf::new(new_page_data.as_mut_slice());
+        new_leaf_page.initialize();
+        leaf_page.transfer_last(&mut new_leaf_page).unwrap();
+        leaf_page.transfer_last(&mut new_leaf_page).unwrap();
+        assert!(leaf_page.transfer_last(&mut new_leaf_page).is_none());
+        assert_eq!(0, leaf_page.num_pairs());
+        assert_eq!(b"beefdead", new_leaf_page.pair_at(0).key.as_ref());
+        assert_eq!(b"facebook", new_leaf_page.pair_at(1).key.as_ref());
+        new_leaf_page.merge_into(&mut leaf_page).unwrap();
+        assert_eq!(0, new_leaf_page.num_pairs());
+        assert_eq!(b"facebook", leaf_page.pair_at(1).key.as_ref());
+    }
+
+    #[test]
+    fn test_leaf_prefix_compression() {
+        let key = |name: &str| format!("user/{}", name).into_bytes();
+        let names = ["alice", "bob", "carol", "dave", "erin"];
+        let mut page_data = vec![0; 108];
+        let mut leaf_page = Leaf::new(page_data.as_mut_slice());
+        leaf_page.initialize_compressed();
+        for (slot_id, name) in names[..4].iter().enumerate() {
+            leaf_page.insert(slot_id, &key(name), b"1").unwrap();
+        }
+        assert!(leaf_page.insert(4, &key("erin"), b"1").is_none());
+
+        // each half is stored under the prefix its keys share
+        let mut new_page_data = vec![0; 108];
+        let mut new_leaf_page = Leaf::new(new_page_data.as_mut_slice());
+        leaf_page.split_insert(&mut new_leaf_page, &key("erin"), b"1");
+        assert!(new_leaf_page.is_compressed());
+        assert_eq!(b"user/", new_leaf_page.prefix());
+        assert!(leaf_page.prefix().starts_with(b"user/"));
+        for name in names {
+            let pair = match leaf_page.search_pair(&key(name)) {
+                Some(pair) => pair,
+                None => new_leaf_page.search_pair(&key(name)).unwrap(),
+            };
+            assert_eq!(key(name), pair.key.as_ref());
+        }
+        assert_eq!(Err(0), leaf_page.search_slot_id(b"admin"));
+        assert_eq!(Err(leaf_page.num_pairs()), leaf_page.search_slot_id(b"zed"));
+
+        // a key without the prefix shrinks it, after which everything fits only without that key
+        new_leaf_page.insert(0, b"admin", b"2").unwrap();
+        assert_eq!(b"", new_leaf_page.prefix());
+        assert_eq!(b"admin", new_leaf_page.pair_at(0).key.as_ref());
+        assert_eq!(
+            &b"1"[..],
+            new_leaf_page.search_pair(&key("alice")).unwrap().value
+        );
+        assert!(new_leaf_page.merge_into(&mut leaf_page).is_none());
+        new_leaf_page.remove(0);
+        new_leaf_page.merge_into(&mut leaf_page).unwrap();
+        assert_eq!(0, new_leaf_page.num_pairs());
+        assert_eq!(b"user/", leaf_page.prefix());
+        assert_eq!(5, leaf_page.num_pairs());
+        assert!(leaf_page.is_consistent());
+    }
 }
@@ -1,11 +1,16 @@
-use zerocopy::{AsBytes, ByteSlice, FromBytes, LayoutVerified};
+use zerocopy::{AsBytes, ByteSlice, ByteSliceMut, FromBytes, LayoutVerified};
 
+use super::Layout;
 use crate::disk_manager::PageId;
 
+const SUFFIX_TRUNCATION: u64 = 1;
+const PREFIX_COMPRESSION: u64 = 2;
+
 #[derive(Debug, FromBytes, AsBytes)]
 #[repr(C)]
 pub struct Header {
     pub root_page_id: PageId,
+    layout: u64,
 }
 
 pub struct Meta<B> {
@@ -19,4 +24,23 @@ impl<B: ByteSlice> Meta<B> {
             LayoutVerified::new_from_prefix(bytes).expect("meta page must be aligned");
         Self { header, _unused }
     }
+
+    pub fn layout(&self) -> Layout {
+        Layout {
+            suffix_truncation: self.header.layout & SUFFIX_TRUNCATION != 0,
+            prefix_compression: self.header.layout & PREFIX_COMPRESSION != 0,
+        }
+    }
+}
+
+impl<B: ByteSliceMut> Meta<B> {
+    pub fn set_layout(&mut self, layout: Layout) {
+        self.header.layout = 0;
+        if layout.suffix_truncation {
+            self.header.layout |= SUFFIX_TRUNCATION;
+        }
+        if layout.prefix_compression {
+            self.header.layout |= PREFIX_COMPRESSION;
+        }
+    }
 }
@@ -22,6 +22,10 @@ impl<B: ByteSlice> Node<B> {
         let (header, body) = LayoutVerified::new_from_prefix(bytes).expect("node must be aligned");
         Self { header, body }
     }
+
+    pub fn is_node(&self) -> bool {
+        matches!(self.header.node_type, NODE_TYPE_LEAF | NODE_TYPE_BRANCH)
+    }
 }
 
 impl<B: ByteSliceMut> Node<B> {
@@ -47,4 +51,25 @@ impl<B: ByteSlice> Body<B> {
             _ => unreachable!(),
         }
     }
+
+    pub fn num_pairs(&self) -> usize {
+        match self {
+            Body::Leaf(leaf) => leaf.num_pairs(),
+            Body::Branch(branch) => branch.num_pairs(),
+        }
+    }
+
+    pub fn free_space(&self) -> usize {
+        match self {
+            Body::Leaf(leaf) => leaf.free_space(),
+            Body::Branch(branch) => branch.free_space(),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        match self {
+            Body::Leaf(leaf) => leaf.capacity(),
+            Body::Branch(branch) => branch.capacity(),
+        }
+    }
 }
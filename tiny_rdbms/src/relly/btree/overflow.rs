@@ -0,0 +1,126 @@
+use std::cell::{Ref, RefMut};
+use std::mem::size_of;
+use std::rc::Rc;
+
+use zerocopy::{AsBytes, ByteSlice, FromBytes, LayoutVerified};
+
+use crate::buffer_pool_manager::{Buffer, BufferPoolManager, Error, FetchPage, ReadPage};
+use crate::disk_manager::{PageId, PAGE_SIZE};
+
+// values longer than this are moved out of the leaf into a chain of overflow pages
+pub const THRESHOLD: usize = 1024;
+
+const CHUNK_SIZE: usize = PAGE_SIZE - size_of::<Header>();
+
+// the leaf keeps a tag byte followed by either the value itself or a `Pointer` to its chain
+const TAG_INLINE: u8 = 0;
+const TAG_OVERFLOW: u8 = 1;
+
+#[derive(Debug, FromBytes, AsBytes)]
+#[repr(C)]
+pub struct Header {
+    next_page_id: PageId,
+}
+
+#[derive(Debug, FromBytes, AsBytes)]
+#[repr(C)]
+struct Pointer {
+    len: u64,
+    first_page_id: PageId,
+}
+
+pub struct Overflow<B> {
+    header: LayoutVerified<B, Header>,
+    body: B,
+}
+
+impl<B: ByteSlice> Overflow<B> {
+    pub fn new(bytes: B) -> Self {
+        let (header, body) =
+            LayoutVerified::new_from_prefix(bytes).expect("overflow page must be aligned");
+        Self { header, body }
+    }
+
+    pub fn next_page_id(&self) -> Option<PageId> {
+        self.header.next_page_id.valid()
+    }
+}
+
+fn pointer(stored: &[u8]) -> Option<Pointer> {
+    match stored.split_first() {
+        Some((&TAG_INLINE, _)) => None,
+        Some((&TAG_OVERFLOW, pointer)) => {
+            Some(Pointer::read_from(pointer).expect("invalid overflow pointer"))
+        }
+        _ => panic!("invalid stored value"),
+    }
+}
+
+pub fn first_page_id(stored: &[u8]) -> Option<PageId> {
+    pointer(stored).map(|pointer| pointer.first_page_id)
+}
+
+pub fn store(bufmgr: &mut BufferPoolManager, value: &[u8]) -> Result<Vec<u8>, Error> {
+    if value.len() <= THRESHOLD {
+        let mut stored = vec![TAG_INLINE];
+        stored.extend_from_slice(value);
+        return Ok(stored);
+    }
+    let mut first_page_id = PageId::INVALID_PAGE_ID;
+    let mut prev_buffer: Option<Rc<Buffer>> = None;
+    for chunk in value.chunks(CHUNK_SIZE) {
+        let buffer = bufmgr.create_page()?;
+        {
+            let mut page = Overflow::new(buffer.page.borrow_mut() as RefMut<[_]>);
+            page.header.next_page_id = PageId::INVALID_PAGE_ID;
+            page.body[..chunk.len()].copy_from_slice(chunk);
+        }
+        match &prev_buffer {
+            Some(prev_buffer) => {
+                let mut prev = Overflow::new(prev_buffer.page.borrow_mut() as RefMut<[_]>);
+                prev.header.next_page_id = buffer.page_id;
+            }
+            None => first_page_id = buffer.page_id,
+        }
+        prev_buffer = Some(buffer);
+    }
+    let pointer = Pointer {
+        len: value.len() as u64,
+        first_page_id,
+    };
+    let mut stored = vec![TAG_OVERFLOW];
+    stored.extend_from_slice(pointer.as_bytes());
+    Ok(stored)
+}
+
+pub fn load<S: FetchPage>(bufmgr: &mut S, stored: &[u8]) -> Result<Vec<u8>, Error> {
+    let pointer = match pointer(stored) {
+        Some(pointer) => pointer,
+        None => return Ok(stored[1..].to_vec()),
+    };
+    let len = pointer.len as usize;
+    let mut value = Vec::with_capacity(len);
+    let mut page_id = pointer.first_page_id;
+    while value.len() < len {
+        let chunk_len = (len - value.len()).min(CHUNK_SIZE);
+        page_id = bufmgr.fetch_page(page_id)?.read_page(|page| {
+            let page = Overflow::new(page);
+            value.extend_from_slice(&page.body[..chunk_len]);
+            page.header.next_page_id
+        });
+    }
+    Ok(value)
+}
+
+pub fn free(bufmgr: &mut BufferPoolManager, stored: &[u8]) -> Result<(), Error> {
+    let mut next_page_id = first_page_id(stored);
+    while let Some(page_id) = next_page_id {
+        next_page_id = {
+            let buffer = bufmgr.fetch_page(page_id)?;
+            let page = Overflow::new(buffer.page.borrow() as Ref<[_]>);
+            page.next_page_id()
+        };
+        bufmgr.deallocate_page(page_id)?;
+    }
+    Ok(())
+}
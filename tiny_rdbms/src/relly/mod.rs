@@ -3,3 +3,4 @@ pub mod btree;
 pub mod memcmpable;
 pub mod slotted;
 pub mod tuple;
+pub mod value;
@@ -52,6 +52,25 @@ impl<B: ByteSlice> Slotted<B> {
         self.header.free_space_offset as usize - self.pointers_size()
     }
 
+    // the cells are packed between the free space offset and the end of the body, so the
+    // pointers must cover that region exactly once
+    pub fn is_consistent(&self) -> bool {
+        let free_space_offset = self.header.free_space_offset as usize;
+        if free_space_offset > self.capacity() || self.pointers_size() > free_space_offset {
+            return false;
+        }
+        let mut ranges: Vec<_> = self.pointers().iter().map(Pointer::range).collect();
+        ranges.sort_by_key(|range| (range.start, range.end));
+        let mut offset = free_space_offset;
+        for range in ranges {
+            if range.start != offset {
+                return false;
+            }
+            offset = range.end;
+        }
+        offset == self.capacity()
+    }
+
     fn pointers_size(&self) -> usize {
         size_of::<Pointer>() * self.num_slots()
     }
@@ -176,5 +195,11 @@ mod tests {
         assert_eq!(&slotted[1], b", ");
         assert_eq!(&slotted[2], b"world");
         assert_eq!(&slotted[3], b"!");
+        assert!(slotted.is_consistent());
+        slotted.resize(2, 2).unwrap();
+        slotted.remove(1);
+        assert!(slotted.is_consistent());
+        slotted.header.free_space_offset -= 1;
+        assert!(!slotted.is_consistent());
     }
 }
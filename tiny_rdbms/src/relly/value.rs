@@ -0,0 +1,317 @@
+use std::fmt::{self, Debug, Display};
+
+use serde::{Deserialize, Serialize};
+
+const TAG_NULL: u8 = 0x00;
+const TAG_VALUE: u8 = 0x01;
+const SIGN_BIT: u64 = 1 << 63;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("invalid encoding for {0}")]
+    InvalidEncoding(DataType),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DataType {
+    Int,
+    UInt,
+    Float,
+    Bool,
+    Text,
+    Bytes,
+}
+
+impl DataType {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_uppercase().as_str() {
+            "INT" | "INTEGER" | "BIGINT" => Some(DataType::Int),
+            "UINT" | "UNSIGNED" => Some(DataType::UInt),
+            "FLOAT" | "REAL" | "DOUBLE" => Some(DataType::Float),
+            "BOOL" | "BOOLEAN" => Some(DataType::Bool),
+            "TEXT" | "VARCHAR" | "STRING" => Some(DataType::Text),
+            "BYTES" | "BLOB" => Some(DataType::Bytes),
+            _ => None,
+        }
+    }
+}
+
+impl Display for DataType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            DataType::Int => "INT",
+            DataType::UInt => "UINT",
+            DataType::Float => "FLOAT",
+            DataType::Bool => "BOOL",
+            DataType::Text => "TEXT",
+            DataType::Bytes => "BYTES",
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+    Bool(bool),
+    Text(String),
+    Bytes(Vec<u8>),
+}
+
+impl Value {
+    pub fn data_type(&self) -> Option<DataType> {
+        match self {
+            Value::Null => None,
+            Value::Int(_) => Some(DataType::Int),
+            Value::UInt(_) => Some(DataType::UInt),
+            Value::Float(_) => Some(DataType::Float),
+            Value::Bool(_) => Some(DataType::Bool),
+            Value::Text(_) => Some(DataType::Text),
+            Value::Bytes(_) => Some(DataType::Bytes),
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        match self {
+            Value::Null => bytes.push(TAG_NULL),
+            Value::Int(v) => {
+                bytes.push(TAG_VALUE);
+                bytes.extend_from_slice(&(*v as u64 ^ SIGN_BIT).to_be_bytes());
+            }
+            Value::UInt(v) => {
+                bytes.push(TAG_VALUE);
+                bytes.extend_from_slice(&v.to_be_bytes());
+            }
+            Value::Float(v) => {
+                let bits = v.to_bits();
+                let bits = if bits & SIGN_BIT != 0 {
+                    !bits
+                } else {
+                    bits | SIGN_BIT
+                };
+                bytes.push(TAG_VALUE);
+                bytes.extend_from_slice(&bits.to_be_bytes());
+            }
+            Value::Bool(v) => {
+                bytes.push(TAG_VALUE);
+                bytes.push(*v as u8);
+            }
+            Value::Text(v) => {
+                bytes.push(TAG_VALUE);
+                bytes.extend_from_slice(v.as_bytes());
+            }
+            Value::Bytes(v) => {
+                bytes.push(TAG_VALUE);
+                bytes.extend_from_slice(v);
+            }
+        }
+        bytes
+    }
+
+    pub fn decode(bytes: &[u8], data_type: DataType) -> Result<Self, Error> {
+        let invalid = || Error::InvalidEncoding(data_type);
+        let payload = match bytes.split_first() {
+            Some((&TAG_NULL, [])) => return Ok(Value::Null),
+            Some((&TAG_VALUE, payload)) => payload,
+            _ => return Err(invalid()),
+        };
+        let u64_payload = || {
+            payload
+                .try_into()
+                .map(u64::from_be_bytes)
+                .map_err(|_| invalid())
+        };
+        let value = match data_type {
+            DataType::Int => Value::Int((u64_payload()? ^ SIGN_BIT) as i64),
+            DataType::UInt => Value::UInt(u64_payload()?),
+            DataType::Float => {
+                let bits = u64_payload()?;
+                let bits = if bits & SIGN_BIT != 0 {
+                    bits ^ SIGN_BIT
+                } else {
+                    !bits
+                };
+                Value::Float(f64::from_bits(bits))
+            }
+            DataType::Bool => match payload {
+                [0] => Value::Bool(false),
+                [1] => Value::Bool(true),
+                _ => return Err(invalid()),
+            },
+            DataType::Text => {
+                Value::Text(String::from_utf8(payload.to_vec()).map_err(|_| invalid())?)
+            }
+            DataType::Bytes => Value::Bytes(payload.to_vec()),
+        };
+        Ok(value)
+    }
+}
+
+pub fn is_null(bytes: &[u8]) -> bool {
+    bytes == [TAG_NULL]
+}
+
+pub fn decode_tuple(
+    tuple: &[impl AsRef<[u8]>],
+    data_types: &[DataType],
+) -> Result<Vec<Value>, Error> {
+    tuple
+        .iter()
+        .zip(data_types)
+        .map(|(elem, &data_type)| Value::decode(elem.as_ref(), data_type))
+        .collect()
+}
+
+impl From<i64> for Value {
+    fn from(v: i64) -> Self {
+        Value::Int(v)
+    }
+}
+
+impl From<u64> for Value {
+    fn from(v: u64) -> Self {
+        Value::UInt(v)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(v: f64) -> Self {
+        Value::Float(v)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(v: bool) -> Self {
+        Value::Bool(v)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(v: &str) -> Self {
+        Value::Text(v.to_string())
+    }
+}
+
+impl From<&[u8]> for Value {
+    fn from(v: &[u8]) -> Self {
+        Value::Bytes(v.to_vec())
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Null => f.write_str("NULL"),
+            Value::Int(v) => Display::fmt(v, f),
+            Value::UInt(v) => Display::fmt(v, f),
+            Value::Float(v) => Display::fmt(v, f),
+            Value::Bool(v) => Display::fmt(v, f),
+            Value::Text(v) => Display::fmt(v, f),
+            Value::Bytes(v) => {
+                f.write_str("x'")?;
+                for byte in v {
+                    write!(f, "{:02x}", byte)?;
+                }
+                f.write_str("'")
+            }
+        }
+    }
+}
+
+pub struct Pretty<'a>(pub &'a [Value]);
+
+impl<'a> Debug for Pretty<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_tuple("Tuple");
+        for value in self.0 {
+            match value {
+                Value::Text(s) => d.field(s),
+                value => d.field(&format_args!("{}", value)),
+            };
+        }
+        d.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_ordered(values: &[Value]) {
+        let encoded: Vec<_> = values.iter().map(Value::encode).collect();
+        for pair in encoded.windows(2) {
+            assert!(pair[0] < pair[1], "{:?} < {:?}", pair[0], pair[1]);
+        }
+        for (value, bytes) in values.iter().zip(&encoded) {
+            let data_type = values
+                .iter()
+                .find_map(Value::data_type)
+                .unwrap_or(DataType::Int);
+            assert_eq!(value, &Value::decode(bytes, data_type).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_order() {
+        assert_ordered(&[
+            Value::Null,
+            Value::Int(i64::MIN),
+            Value::Int(-10),
+            Value::Int(-1),
+            Value::Int(0),
+            Value::Int(2),
+            Value::Int(10),
+            Value::Int(i64::MAX),
+        ]);
+        assert_ordered(&[
+            Value::Null,
+            Value::UInt(0),
+            Value::UInt(9),
+            Value::UInt(300),
+        ]);
+        assert_ordered(&[
+            Value::Null,
+            Value::Float(f64::NEG_INFINITY),
+            Value::Float(-2.5),
+            Value::Float(-0.5),
+            Value::Float(0.0),
+            Value::Float(0.25),
+            Value::Float(10.0),
+            Value::Float(f64::INFINITY),
+        ]);
+        assert_ordered(&[Value::Null, Value::Bool(false), Value::Bool(true)]);
+        assert_ordered(&[
+            Value::Null,
+            Value::from(""),
+            Value::from("Alice"),
+            Value::from("Bob"),
+            Value::from("Bobby"),
+        ]);
+        assert_ordered(&[
+            Value::Null,
+            Value::from(&b"\x00"[..]),
+            Value::from(&b"\xff"[..]),
+        ]);
+    }
+
+    #[test]
+    fn test_tuple() {
+        let mut bytes = vec![];
+        let values = [Value::Int(-3), Value::Null, Value::from("hello")];
+        crate::relly::tuple::encode(values.iter().map(Value::encode), &mut bytes);
+        let mut elems = vec![];
+        crate::relly::tuple::decode(&bytes, &mut elems);
+        let decoded =
+            decode_tuple(&elems, &[DataType::Int, DataType::Text, DataType::Text]).unwrap();
+        assert_eq!(values.to_vec(), decoded);
+        assert_eq!(
+            r#"Tuple(-3, NULL, "hello")"#,
+            format!("{:?}", Pretty(&decoded))
+        );
+        assert!(Value::decode(&[0x01, 0xff], DataType::Int).is_err());
+    }
+}
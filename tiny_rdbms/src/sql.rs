@@ -0,0 +1,840 @@
+use std::fs;
+
+use anyhow::{Context, Result};
+
+use crate::buffer_pool_manager::BufferPoolManager;
+use crate::catalog::{Catalog, TableSchema};
+use crate::lock_manager::{self, LockMode};
+use crate::mvcc::Snapshot;
+use crate::query_executor::{self, PlanNode, Profile, Tuple};
+use crate::relly::value::{self, DataType, Value};
+use crate::transaction::{Transaction, TransactionManager};
+
+pub mod ast;
+pub mod csv;
+pub mod format;
+pub mod parser;
+pub mod planner;
+pub mod tokenizer;
+
+use ast::Statement;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("syntax error: {0}")]
+    Syntax(String),
+    #[error("no such table: {0}")]
+    NoSuchTable(String),
+    #[error("no such column: {0}")]
+    NoSuchColumn(String),
+    #[error("{0}")]
+    Invalid(String),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum QueryResult {
+    CreateTable,
+    DropTable,
+    Insert(usize),
+    Delete(usize),
+    Begin,
+    Commit,
+    Rollback,
+    // dead versions removed, and pages given back to the file system
+    Vacuum {
+        versions: usize,
+        pages: u64,
+    },
+    Copy(usize),
+    // `types` has the declared type of each column
+    Select {
+        columns: Vec<String>,
+        types: Vec<DataType>,
+        rows: Vec<Vec<Value>>,
+    },
+    // a SELECT whose rows went to the caller of `execute_streaming`, and how many there were
+    Rows(usize),
+}
+
+// the rows of a statement as `execute_streaming` hands them over, column names first
+pub enum Output {
+    Columns {
+        columns: Vec<String>,
+        types: Vec<DataType>,
+    },
+    Row(Vec<Value>),
+}
+
+impl QueryResult {
+    // the command completion text shown after a statement
+    pub fn tag(&self) -> String {
+        match self {
+            QueryResult::CreateTable => "CREATE TABLE".to_string(),
+            QueryResult::DropTable => "DROP TABLE".to_string(),
+            QueryResult::Insert(count) => format!("INSERT {}", count),
+            QueryResult::Delete(count) => format!("DELETE {}", count),
+            QueryResult::Begin => "BEGIN".to_string(),
+            QueryResult::Commit => "COMMIT".to_string(),
+            QueryResult::Rollback => "ROLLBACK".to_string(),
+            QueryResult::Vacuum { .. } => "VACUUM".to_string(),
+            QueryResult::Copy(count) => format!("COPY {}", count),
+            QueryResult::Select { rows, .. } => format!("SELECT {}", rows.len()),
+            QueryResult::Rows(count) => format!("SELECT {}", count),
+        }
+    }
+}
+
+pub struct Database {
+    catalog: Catalog,
+    transactions: TransactionManager,
+    transaction: Option<Transaction>,
+    // whether COPY may read files on this machine; off unless the user sits at it
+    file_access: bool,
+}
+
+impl Database {
+    pub fn open(bufmgr: &mut BufferPoolManager) -> Result<Self> {
+        let catalog = Catalog::open(bufmgr)?;
+        let transactions = TransactionManager::open(bufmgr)?;
+        let tables: Vec<_> = catalog
+            .list_tables(bufmgr)?
+            .iter()
+            .map(TableSchema::table)
+            .collect();
+        transactions.recover(bufmgr, &tables)?;
+        Ok(Self {
+            catalog,
+            transactions,
+            transaction: None,
+            file_access: false,
+        })
+    }
+
+    // another connection to the same database, with its own transaction
+    pub fn session(&self) -> Self {
+        Self {
+            catalog: self.catalog.clone(),
+            transactions: self.transactions.clone(),
+            transaction: None,
+            file_access: self.file_access,
+        }
+    }
+
+    pub fn set_file_access(&mut self, allowed: bool) {
+        self.file_access = allowed;
+    }
+
+    pub fn catalog(&self) -> &Catalog {
+        &self.catalog
+    }
+
+    pub fn in_transaction(&self) -> bool {
+        self.transaction.is_some()
+    }
+
+    // queries see what the open transaction sees, or else everything committed so far
+    fn snapshot(&self) -> Snapshot {
+        match &self.transaction {
+            Some(txn) => txn.snapshot().clone(),
+            None => self.transactions.snapshot(),
+        }
+    }
+
+    fn table(&self, bufmgr: &mut BufferPoolManager, name: &str) -> Result<TableSchema> {
+        self.catalog
+            .get_table(bufmgr, name)?
+            .ok_or_else(|| Error::NoSuchTable(name.to_string()).into())
+    }
+
+    pub fn execute(&mut self, bufmgr: &mut BufferPoolManager, sql: &str) -> Result<QueryResult> {
+        let (mut columns, mut types, mut rows) = (vec![], vec![], vec![]);
+        let result = self.execute_streaming(bufmgr, sql, &mut |output| match output {
+            Output::Columns {
+                columns: names,
+                types: data_types,
+            } => (columns, types) = (names, data_types),
+            Output::Row(row) => rows.push(row),
+        })?;
+        Ok(match result {
+            QueryResult::Rows(_) => QueryResult::Select {
+                columns,
+                types,
+                rows,
+            },
+            result => result,
+        })
+    }
+
+    // like `execute`, but passes rows to `out` as the executor produces them; a statement that
+    // fails halfway may have passed some already. A statement that needs a lock another
+    // transaction holds fails with `lock_manager::Error::Blocked` before passing any rows and
+    // without effects; the caller runs it again once some other statement has ended a
+    // transaction, see `is_blocked`
+    pub fn execute_streaming(
+        &mut self,
+        bufmgr: &mut BufferPoolManager,
+        sql: &str,
+        out: &mut dyn FnMut(Output),
+    ) -> Result<QueryResult> {
+        let result = self.execute_statement(bufmgr, sql, out);
+        if !result.as_ref().is_err_and(is_blocked) {
+            if let Some(txn) = &self.transaction {
+                txn.stop_waiting();
+            }
+        }
+        result
+    }
+
+    fn execute_statement(
+        &mut self,
+        bufmgr: &mut BufferPoolManager,
+        sql: &str,
+        out: &mut dyn FnMut(Output),
+    ) -> Result<QueryResult> {
+        match parser::parse(sql)? {
+            Statement::Begin => {
+                if self.transaction.is_some() {
+                    return Err(
+                        Error::Invalid("transaction already in progress".to_string()).into(),
+                    );
+                }
+                self.transaction = Some(self.transactions.begin(bufmgr)?);
+                Ok(QueryResult::Begin)
+            }
+            Statement::Commit => {
+                self.take_transaction()?.commit(bufmgr)?;
+                Ok(QueryResult::Commit)
+            }
+            Statement::Rollback => {
+                self.take_transaction()?.rollback(bufmgr)?;
+                Ok(QueryResult::Rollback)
+            }
+            Statement::CreateTable(create) => {
+                self.check_no_transaction("CREATE TABLE")?;
+                let schema = planner::plan_create_table(&create)?;
+                self.catalog.create_table(bufmgr, schema)?;
+                bufmgr.flush()?;
+                Ok(QueryResult::CreateTable)
+            }
+            Statement::DropTable(name) => {
+                self.check_no_transaction("DROP TABLE")?;
+                let schema = self.table(bufmgr, &name)?;
+                let txn = self.lock_table(bufmgr, &schema)?;
+                self.catalog.drop_table(bufmgr, &name)?;
+                txn.commit(bufmgr)?;
+                Ok(QueryResult::DropTable)
+            }
+            Statement::Vacuum => {
+                self.check_no_transaction("VACUUM")?;
+                let horizon = self.transactions.horizon();
+                let mut versions = 0;
+                for schema in self.catalog.list_tables(bufmgr)? {
+                    versions += schema.table().vacuum(bufmgr, horizon)?;
+                }
+                let pages = bufmgr.vacuum()?;
+                Ok(QueryResult::Vacuum { versions, pages })
+            }
+            Statement::Copy(copy) => {
+                self.check_no_transaction("COPY")?;
+                if !self.file_access {
+                    return Err(Error::Invalid(
+                        "COPY FROM a file is not allowed in this session".to_string(),
+                    )
+                    .into());
+                }
+                let schema = self.table(bufmgr, &copy.table)?;
+                let input = fs::read_to_string(&copy.path)
+                    .with_context(|| format!("cannot read {}", copy.path))?;
+                let mut records = csv::parse(&input)?;
+                if copy.header && !records.is_empty() {
+                    records.remove(0);
+                }
+                let rows = planner::plan_copy(records, &schema)?;
+                let mut txn = self.lock_table(bufmgr, &schema)?;
+                if let Err(err) = self
+                    .catalog
+                    .load_table(bufmgr, &schema.name, &rows, &mut txn)
+                {
+                    txn.rollback(bufmgr)?;
+                    return Err(err);
+                }
+                txn.commit(bufmgr)?;
+                Ok(QueryResult::Copy(rows.len()))
+            }
+            Statement::Explain(explain) => {
+                let schema = self.table(bufmgr, &explain.select.table)?;
+                let plan = planner::plan_select(&explain.select, &schema)?;
+                if explain.analyze {
+                    self.lock_for_read(bufmgr, &schema)?;
+                }
+                let snapshot = self.snapshot();
+                let text = if explain.analyze {
+                    plan.with_plan_node(&snapshot, |node| -> Result<String> {
+                        let profile = Profile::new(node);
+                        let mut result = Ok(vec![]);
+                        profile.instrument(node, &mut |node| result = collect(bufmgr, node));
+                        result?;
+                        Ok(profile.to_string())
+                    })?
+                } else {
+                    plan.with_plan_node(&snapshot, query_executor::explain)
+                };
+                out(Output::Columns {
+                    columns: vec!["QUERY PLAN".to_string()],
+                    types: vec![DataType::Text],
+                });
+                for line in text.lines() {
+                    out(Output::Row(vec![Value::Text(line.to_string())]));
+                }
+                Ok(QueryResult::Rows(text.lines().count()))
+            }
+            Statement::Select(select) => {
+                let schema = self.table(bufmgr, &select.table)?;
+                let plan = planner::plan_select(&select, &schema)?;
+                self.lock_for_read(bufmgr, &schema)?;
+                let data_types = schema.data_types();
+                out(Output::Columns {
+                    columns: plan.columns.clone(),
+                    types: plan.projection.iter().map(|&i| data_types[i]).collect(),
+                });
+                let mut count = 0;
+                plan.with_plan_node(&self.snapshot(), |node| {
+                    for_each_row(bufmgr, node, |row| {
+                        let row = row
+                            .iter()
+                            .zip(&plan.projection)
+                            .map(|(elem, &i)| Value::decode(elem, data_types[i]))
+                            .collect::<Result<_, value::Error>>()?;
+                        out(Output::Row(row));
+                        count += 1;
+                        Ok(())
+                    })
+                })?;
+                Ok(QueryResult::Rows(count))
+            }
+            statement => {
+                // DML runs in the open transaction, or in its own one when none is open; either
+                // way a failing statement leaves no partial effects behind, and a blocked one
+                // starts over when it runs again
+                let (mut txn, autocommit) = match self.transaction.take() {
+                    Some(txn) => (txn, false),
+                    None => (self.transactions.begin(bufmgr)?, true),
+                };
+                let savepoint = txn.savepoint();
+                let result = self.execute_dml(bufmgr, &mut txn, statement);
+                if result.is_err() {
+                    txn.rollback_to(bufmgr, savepoint)?;
+                }
+                if autocommit {
+                    txn.commit(bufmgr)?;
+                } else {
+                    self.transaction = Some(txn);
+                }
+                self.check_deadlock(bufmgr, result)
+            }
+        }
+    }
+
+    fn execute_dml(
+        &self,
+        bufmgr: &mut BufferPoolManager,
+        txn: &mut Transaction,
+        statement: Statement,
+    ) -> Result<QueryResult> {
+        match statement {
+            Statement::Insert(insert) => {
+                let schema = self.table(bufmgr, &insert.table)?;
+                let rows = planner::plan_insert(&insert, &schema)?;
+                let table = schema.table();
+                for row in &rows {
+                    let record: Vec<&[u8]> = row.iter().map(Vec::as_slice).collect();
+                    txn.insert(bufmgr, &table, &record)?;
+                }
+                Ok(QueryResult::Insert(rows.len()))
+            }
+            Statement::Delete(delete) => {
+                let schema = self.table(bufmgr, &delete.table)?;
+                let plan = planner::plan_delete(&delete, &schema)?;
+                let rows = plan.with_plan_node(txn.snapshot(), |node| collect(bufmgr, node))?;
+                let table = schema.table();
+                for row in &rows {
+                    let pkey: Vec<&[u8]> = row[..table.num_key_elems]
+                        .iter()
+                        .map(Vec::as_slice)
+                        .collect();
+                    txn.delete(bufmgr, &table, &pkey)?;
+                }
+                Ok(QueryResult::Delete(rows.len()))
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    // DROP TABLE and COPY replace the table's pages outright, which would pull them from under
+    // the writes of another session's open transaction; the lock keeps them apart, and the
+    // commit flushes the result
+    fn lock_table(
+        &self,
+        bufmgr: &mut BufferPoolManager,
+        schema: &TableSchema,
+    ) -> Result<Transaction> {
+        let txn = self.transactions.begin(bufmgr)?;
+        if let Err(err) = txn.lock_table(&schema.table(), LockMode::Exclusive) {
+            txn.rollback(bufmgr)?;
+            return Err(err);
+        }
+        Ok(txn)
+    }
+
+    // an open transaction reads under a shared lock on the table, so that no other transaction
+    // writes to it until this one ends; a statement on its own only reads committed data
+    fn lock_for_read(
+        &mut self,
+        bufmgr: &mut BufferPoolManager,
+        schema: &TableSchema,
+    ) -> Result<()> {
+        let result = match &self.transaction {
+            Some(txn) => txn.lock_table(&schema.table(), LockMode::Shared),
+            None => Ok(()),
+        };
+        self.check_deadlock(bufmgr, result)
+    }
+
+    // a deadlock victim rolls back its transaction, so that the others in the cycle can go on
+    fn check_deadlock<T>(
+        &mut self,
+        bufmgr: &mut BufferPoolManager,
+        result: Result<T>,
+    ) -> Result<T> {
+        if let Err(err) = &result {
+            if let Some(lock_manager::Error::Deadlock(_)) = err.downcast_ref() {
+                if let Some(txn) = self.transaction.take() {
+                    txn.rollback(bufmgr)?;
+                }
+            }
+        }
+        result
+    }
+
+    fn take_transaction(&mut self) -> Result<Transaction> {
+        self.transaction
+            .take()
+            .ok_or_else(|| Error::Invalid("no transaction in progress".to_string()).into())
+    }
+
+    fn check_no_transaction(&self, statement: &str) -> Result<()> {
+        if self.transaction.is_some() {
+            return Err(Error::Invalid(format!(
+                "{} is not allowed inside a transaction",
+                statement
+            ))
+            .into());
+        }
+        Ok(())
+    }
+}
+
+// whether a statement failed only because it has to wait for a lock, and should run again
+pub fn is_blocked(err: &anyhow::Error) -> bool {
+    matches!(err.downcast_ref(), Some(lock_manager::Error::Blocked(_)))
+}
+
+// runs `node` to the end, or until `f` fails, and then lets the executor free what it holds
+fn for_each_row(
+    bufmgr: &mut BufferPoolManager,
+    node: &dyn PlanNode,
+    mut f: impl FnMut(Tuple) -> Result<()>,
+) -> Result<()> {
+    let mut exec = node.start(bufmgr)?;
+    let result = loop {
+        match exec.next(bufmgr) {
+            Ok(Some(row)) => {
+                if let Err(err) = f(row) {
+                    break Err(err);
+                }
+            }
+            Ok(None) => break Ok(()),
+            Err(err) => break Err(err),
+        }
+    };
+    exec.finish(bufmgr)?;
+    result
+}
+
+fn collect(bufmgr: &mut BufferPoolManager, node: &dyn PlanNode) -> Result<Vec<Tuple>> {
+    let mut rows = vec![];
+    for_each_row(bufmgr, node, |row| {
+        rows.push(row);
+        Ok(())
+    })?;
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer_pool_manager::BufferPool;
+    use crate::disk_manager::{self, DiskManager};
+
+    fn rows(result: QueryResult) -> Vec<Vec<String>> {
+        match result {
+            QueryResult::Select { rows, .. } => rows
+                .into_iter()
+                .map(|row| row.iter().map(Value::to_string).collect())
+                .collect(),
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_execute() {
+        let disk = DiskManager::new(disk_manager::tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let mut db = Database::open(&mut bufmgr).unwrap();
+
+        let mut execute = |sql: &str| db.execute(&mut bufmgr, sql);
+        assert_eq!(
+            QueryResult::CreateTable,
+            execute(
+                "CREATE TABLE users (id TEXT, first_name TEXT, last_name TEXT, PRIMARY KEY (id))"
+            )
+            .unwrap()
+        );
+        assert!(execute("CREATE TABLE users (id TEXT)").is_err());
+        assert_eq!(
+            QueryResult::Insert(5),
+            execute(
+                "INSERT INTO users VALUES ('z', 'Alice', 'Smith'), ('x', 'Bob', 'Johnson'), \
+                 ('y', 'Charlie', 'Williams'), ('w', 'Dave', 'Miller'), ('v', 'Eve', 'Brown')"
+            )
+            .unwrap()
+        );
+        // the insert created the commit log, so the dropped table's pages end the file
+        assert_eq!(
+            QueryResult::CreateTable,
+            execute("CREATE TABLE books (id TEXT, title TEXT)").unwrap()
+        );
+        assert_eq!(QueryResult::DropTable, execute("DROP TABLE books").unwrap());
+        assert_eq!(
+            QueryResult::Vacuum {
+                versions: 0,
+                pages: 2
+            },
+            execute("VACUUM").unwrap()
+        );
+        assert!(execute("SELECT * FROM books").is_err());
+        assert_eq!(
+            QueryResult::Insert(1),
+            execute("INSERT INTO users (last_name, id, first_name) VALUES ('Davis', 'u', 'Frank')")
+                .unwrap()
+        );
+        assert!(execute("INSERT INTO users VALUES ('t', 'Grace')").is_err());
+        assert!(execute("INSERT INTO people VALUES ('t')").is_err());
+
+        assert_eq!(
+            vec![vec!["x", "Bob"], vec!["y", "Charlie"]],
+            rows(
+                execute("SELECT id, first_name FROM users WHERE id >= 'w' AND id < 'z' AND first_name < 'Dave'")
+                    .unwrap()
+            )
+        );
+        assert_eq!(
+            vec![vec!["Williams"], vec!["Smith"], vec!["Miller"]],
+            rows(
+                execute(
+                    "SELECT last_name FROM users WHERE last_name > 'Jones' ORDER BY last_name DESC"
+                )
+                .unwrap()
+            )
+        );
+
+        assert_eq!(
+            QueryResult::Delete(2),
+            execute("DELETE FROM users WHERE id = 'u' OR first_name = 'Alice'").unwrap()
+        );
+        assert_eq!(
+            vec![vec!["v"], vec!["w"], vec!["x"], vec!["y"]],
+            rows(execute("SELECT id FROM users").unwrap())
+        );
+    }
+
+    #[test]
+    fn test_typed() {
+        let disk = DiskManager::new(disk_manager::tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let mut db = Database::open(&mut bufmgr).unwrap();
+
+        let mut execute = |sql: &str| db.execute(&mut bufmgr, sql);
+        execute("CREATE TABLE scores (id INT PRIMARY KEY, score FLOAT, passed BOOL, note TEXT)")
+            .unwrap();
+        assert_eq!(
+            QueryResult::Insert(6),
+            execute(
+                "INSERT INTO scores VALUES (10, 80.5, TRUE, 'good'), (2, -1.5, FALSE, NULL), \
+                 (-7, 0, FALSE, 'retry'), (100, 99, TRUE, NULL), (9, 60, TRUE, 'ok'), \
+                 (-100, 12.25, FALSE, 'absent')"
+            )
+            .unwrap()
+        );
+        assert!(execute("INSERT INTO scores VALUES ('x', 1, TRUE, NULL)").is_err());
+        assert!(execute("INSERT INTO scores VALUES (NULL, 1, TRUE, NULL)").is_err());
+
+        assert_eq!(
+            vec![vec!["-7"], vec!["2"], vec!["9"], vec!["10"]],
+            rows(execute("SELECT id FROM scores WHERE id >= -7 AND id <= 10").unwrap())
+        );
+        assert_eq!(
+            vec![vec!["-100", "12.25"], vec!["-7", "0"], vec!["2", "-1.5"]],
+            rows(execute("SELECT id, score FROM scores WHERE score < 50 ORDER BY id").unwrap())
+        );
+        assert_eq!(
+            vec![vec!["100", "NULL"], vec!["2", "NULL"]],
+            rows(
+                execute("SELECT id, note FROM scores WHERE note IS NULL ORDER BY id DESC").unwrap()
+            )
+        );
+        assert_eq!(
+            vec![vec!["9"], vec!["10"]],
+            rows(execute("SELECT id FROM scores WHERE passed = TRUE AND NOT note = 'x'").unwrap())
+        );
+        assert_eq!(
+            vec![vec!["10"], vec!["9"], vec!["2"], vec!["-7"]],
+            rows(
+                execute("SELECT id FROM scores WHERE id BETWEEN -7 AND 10 ORDER BY id DESC")
+                    .unwrap()
+            )
+        );
+        assert_eq!(
+            vec![vec!["-100"], vec!["100"]],
+            rows(execute("SELECT id FROM scores WHERE id NOT BETWEEN -7 AND 10").unwrap())
+        );
+        assert_eq!(
+            QueryResult::Delete(3),
+            execute("DELETE FROM scores WHERE passed = FALSE").unwrap()
+        );
+        assert_eq!(
+            vec![vec!["100"], vec!["10"], vec!["9"]],
+            rows(execute("SELECT id FROM scores ORDER BY score DESC").unwrap())
+        );
+        assert_eq!(
+            vec![vec!["10"]],
+            rows(execute("SELECT id FROM scores ORDER BY score DESC LIMIT 1 OFFSET 1").unwrap())
+        );
+        assert_eq!(
+            vec![vec!["9"], vec!["10"]],
+            rows(execute("SELECT id FROM scores LIMIT 2").unwrap())
+        );
+
+        // a value this long is stored in overflow pages and read back whole by the scan
+        let essay = "lorem ipsum ".repeat(2000);
+        execute(&format!(
+            "INSERT INTO scores VALUES (50, 75, TRUE, '{}')",
+            essay
+        ))
+        .unwrap();
+        assert_eq!(
+            vec![vec![essay.as_str()]],
+            rows(execute("SELECT note FROM scores WHERE id = 50").unwrap())
+        );
+    }
+
+    #[test]
+    fn test_copy() {
+        let disk = DiskManager::new(disk_manager::tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let mut db = Database::open(&mut bufmgr).unwrap();
+        db.set_file_access(true);
+        let mut execute = |sql: &str| db.execute(&mut bufmgr, sql);
+        execute("CREATE TABLE t (id INT PRIMARY KEY, name TEXT, active BOOL)").unwrap();
+
+        let path = std::env::temp_dir().join(format!("tiny_rdbms-copy-{}.csv", std::process::id()));
+        let mut csv = "id,name,active\n".to_string();
+        for i in (0..3000).rev() {
+            csv.push_str(&format!("{},\"name, {}\",{}\n", i, i, i % 2 == 0));
+        }
+        csv.push_str("-1,,\n");
+        fs::write(&path, csv).unwrap();
+        let copy = format!("COPY t FROM '{}' WITH HEADER", path.display());
+        assert_eq!(QueryResult::Copy(3001), execute(&copy).unwrap());
+        assert_eq!(
+            vec![
+                vec!["-1".to_string(), "NULL".to_string(), "NULL".to_string()],
+                vec!["0".to_string(), "name, 0".to_string(), "true".to_string()],
+            ],
+            rows(execute("SELECT * FROM t WHERE id < 1").unwrap())
+        );
+        assert_eq!(
+            vec![vec![
+                "2999".to_string(),
+                "name, 2999".to_string(),
+                "false".to_string()
+            ]],
+            rows(execute("SELECT * FROM t ORDER BY id DESC LIMIT 1").unwrap())
+        );
+        assert!(execute(&copy).is_err());
+        execute("INSERT INTO t VALUES (5000, 'new', true)").unwrap();
+        execute("DELETE FROM t WHERE id >= 100").unwrap();
+        assert_eq!(101, rows(execute("SELECT id FROM t").unwrap()).len());
+
+        // a bad row leaves the table as it was
+        execute("CREATE TABLE u (id INT PRIMARY KEY, name TEXT)").unwrap();
+        fs::write(&path, "1,a\n2,b\n1,c\n").unwrap();
+        assert!(execute(&format!("COPY u FROM '{}'", path.display())).is_err());
+        fs::write(&path, "1,a\nx,b\n").unwrap();
+        assert!(execute(&format!("COPY u FROM '{}'", path.display())).is_err());
+        assert!(rows(execute("SELECT * FROM u").unwrap()).is_empty());
+        fs::write(&path, "2,b\n1,a\n").unwrap();
+        assert_eq!(
+            QueryResult::Copy(2),
+            execute(&format!("COPY u FROM '{}'", path.display())).unwrap()
+        );
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            vec![
+                vec!["1".to_string(), "a".to_string()],
+                vec!["2".to_string(), "b".to_string()]
+            ],
+            rows(execute("SELECT * FROM u").unwrap())
+        );
+        assert!(execute("COPY u FROM 'no/such/file.csv'").is_err());
+    }
+
+    #[test]
+    fn test_explain() {
+        let disk = DiskManager::new(disk_manager::tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let mut db = Database::open(&mut bufmgr).unwrap();
+        let mut execute = |sql: &str| db.execute(&mut bufmgr, sql).unwrap();
+        execute("CREATE TABLE users (id INT, name TEXT, PRIMARY KEY (id))");
+        execute("INSERT INTO users VALUES (1, 'Alice'), (2, 'Bob'), (3, 'Carol'), (4, 'Dave')");
+
+        let sql = "SELECT name FROM users WHERE id > 1 AND (name = 'Bob' OR name = 'Dave') \
+                   ORDER BY name DESC LIMIT 5";
+        assert_eq!(
+            vec![
+                vec!["Project columns [1]"],
+                vec!["-> Limit 5, offset 0"],
+                vec!["   -> Sort in memory up to 1048576 bytes"],
+                vec!["      -> Filter: id > 1 AND (name = 'Bob' OR name = 'Dave')"],
+                vec!["         -> SeqScan table page 3, key > (x'018000000000000001')"],
+            ],
+            rows(execute(&format!("EXPLAIN {}", sql)))
+        );
+        let analyzed = rows(execute(&format!("EXPLAIN ANALYZE {}", sql)));
+        assert_eq!(
+            vec!["   -> Sort in memory up to 1048576 bytes (loops=1 rows=2 next=3 fetches=2)"],
+            analyzed[2]
+        );
+        assert!(analyzed[3][0].ends_with("(loops=1 rows=2 next=3 fetches=2)"));
+        assert!(analyzed[4][0].ends_with("(loops=1 rows=3 next=4 fetches=2)"));
+    }
+
+    #[test]
+    fn test_streaming() {
+        let disk = DiskManager::new(disk_manager::tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let mut db = Database::open(&mut bufmgr).unwrap();
+        db.execute(&mut bufmgr, "CREATE TABLE t (id INT PRIMARY KEY)")
+            .unwrap();
+        db.execute(&mut bufmgr, "INSERT INTO t VALUES (1), (2), (3)")
+            .unwrap();
+
+        let mut outputs = vec![];
+        let mut out = |output| {
+            outputs.push(match output {
+                Output::Columns { columns, .. } => columns.join(","),
+                Output::Row(row) => row[0].to_string(),
+            })
+        };
+        let result = db
+            .execute_streaming(&mut bufmgr, "SELECT id FROM t WHERE id > 1", &mut out)
+            .unwrap();
+        assert_eq!(QueryResult::Rows(2), result);
+        assert_eq!("SELECT 2", result.tag());
+        assert!(db
+            .execute_streaming(&mut bufmgr, "SELECT id FROM missing", &mut out)
+            .is_err());
+        assert_eq!(vec!["id", "2", "3"], outputs);
+    }
+
+    #[test]
+    fn test_transaction() {
+        let disk = DiskManager::new(disk_manager::tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(10));
+        let mut db = Database::open(&mut bufmgr).unwrap();
+
+        let mut execute = |sql: &str| db.execute(&mut bufmgr, sql);
+        execute("CREATE TABLE t (id INT PRIMARY KEY, name TEXT)").unwrap();
+        execute("INSERT INTO t VALUES (1, 'a'), (2, 'b')").unwrap();
+        assert!(execute("INSERT INTO t VALUES (3, 'c'), (1, 'dup')").is_err());
+        assert_eq!(
+            vec![vec!["1"], vec!["2"]],
+            rows(execute("SELECT id FROM t").unwrap())
+        );
+
+        assert!(execute("COMMIT").is_err());
+        assert_eq!(QueryResult::Begin, execute("BEGIN").unwrap());
+        assert!(execute("BEGIN").is_err());
+        assert!(execute("CREATE TABLE u (id INT)").is_err());
+        execute("INSERT INTO t VALUES (3, 'c')").unwrap();
+        execute("DELETE FROM t WHERE id = 1").unwrap();
+        assert!(execute("INSERT INTO t VALUES (4, 'd'), (2, 'dup')").is_err());
+        assert_eq!(
+            vec![vec!["2"], vec!["3"]],
+            rows(execute("SELECT id FROM t").unwrap())
+        );
+        assert_eq!(QueryResult::Rollback, execute("ROLLBACK").unwrap());
+        assert_eq!(
+            vec![vec!["1"], vec!["2"]],
+            rows(execute("SELECT id FROM t").unwrap())
+        );
+
+        execute("BEGIN").unwrap();
+        execute("DELETE FROM t WHERE id = 2").unwrap();
+        assert_eq!(QueryResult::Commit, execute("COMMIT").unwrap());
+        assert_eq!(vec![vec!["1"]], rows(execute("SELECT id FROM t").unwrap()));
+
+        // an open transaction keeps other sessions from dropping the table it wrote to
+        let mut other = db.session();
+        db.execute(&mut bufmgr, "BEGIN").unwrap();
+        db.execute(&mut bufmgr, "INSERT INTO t VALUES (2, 'b')")
+            .unwrap();
+        let err = other.execute(&mut bufmgr, "DROP TABLE t").unwrap_err();
+        assert!(is_blocked(&err), "{}", err);
+        // and from reading it in a transaction of their own
+        other.execute(&mut bufmgr, "BEGIN").unwrap();
+        let err = other.execute(&mut bufmgr, "SELECT id FROM t").unwrap_err();
+        assert!(is_blocked(&err), "{}", err);
+        db.execute(&mut bufmgr, "COMMIT").unwrap();
+        assert_eq!(
+            vec![vec!["1"]],
+            rows(other.execute(&mut bufmgr, "SELECT id FROM t").unwrap())
+        );
+        other.execute(&mut bufmgr, "COMMIT").unwrap();
+
+        // the victim of a deadlock loses its transaction
+        db.execute(&mut bufmgr, "BEGIN").unwrap();
+        other.execute(&mut bufmgr, "BEGIN").unwrap();
+        db.execute(&mut bufmgr, "DELETE FROM t WHERE id = 1")
+            .unwrap();
+        other
+            .execute(&mut bufmgr, "DELETE FROM t WHERE id = 2")
+            .unwrap();
+        let err = db
+            .execute(&mut bufmgr, "DELETE FROM t WHERE id = 2")
+            .unwrap_err();
+        assert!(is_blocked(&err), "{}", err);
+        let err = other
+            .execute(&mut bufmgr, "DELETE FROM t WHERE id = 1")
+            .unwrap_err();
+        assert!(err.to_string().starts_with("deadlock"), "{}", err);
+        assert!(!other.in_transaction());
+        assert_eq!(
+            QueryResult::Delete(1),
+            db.execute(&mut bufmgr, "DELETE FROM t WHERE id = 2")
+                .unwrap()
+        );
+        db.execute(&mut bufmgr, "COMMIT").unwrap();
+        other.execute(&mut bufmgr, "DROP TABLE t").unwrap();
+    }
+}
@@ -0,0 +1,97 @@
+use super::Error;
+
+// RFC 4180 records: quoted fields may contain commas, line breaks and doubled quotes, and an
+// empty unquoted field stands for NULL; blank lines are skipped
+pub fn parse(input: &str) -> Result<Vec<Vec<Option<String>>>, Error> {
+    let mut records = vec![];
+    let mut record = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut line = 1;
+    let mut chars = input.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if field.is_empty() && !quoted => {
+                quoted = true;
+                loop {
+                    match chars.next() {
+                        Some('"') if chars.peek() == Some(&'"') => {
+                            chars.next();
+                            field.push('"');
+                        }
+                        Some('"') => break,
+                        Some(c) => {
+                            if c == '\n' {
+                                line += 1;
+                            }
+                            field.push(c);
+                        }
+                        None => {
+                            return Err(Error::Invalid(format!(
+                                "CSV line {}: unterminated quoted field",
+                                line
+                            )))
+                        }
+                    }
+                }
+            }
+            ',' => end_field(&mut record, &mut field, &mut quoted),
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                if !record.is_empty() || !field.is_empty() || quoted {
+                    end_field(&mut record, &mut field, &mut quoted);
+                    records.push(std::mem::take(&mut record));
+                }
+                line += 1;
+            }
+            _ if quoted => {
+                return Err(Error::Invalid(format!(
+                    "CSV line {}: unexpected {:?} after quoted field",
+                    line, c
+                )))
+            }
+            _ => field.push(c),
+        }
+    }
+    if !record.is_empty() || !field.is_empty() || quoted {
+        end_field(&mut record, &mut field, &mut quoted);
+        records.push(record);
+    }
+    Ok(records)
+}
+
+fn end_field(record: &mut Vec<Option<String>>, field: &mut String, quoted: &mut bool) {
+    let field = std::mem::take(field);
+    record.push((*quoted || !field.is_empty()).then_some(field));
+    *quoted = false;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(fields: &[Option<&str>]) -> Vec<Option<String>> {
+        fields
+            .iter()
+            .map(|field| field.map(str::to_string))
+            .collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        let input =
+            "id,name,note\r\n1,Alice,\n\n2,\"Bob, Jr.\",\"said \"\"hi\"\"\ntwice\"\n3,,\"\"";
+        assert_eq!(
+            vec![
+                fields(&[Some("id"), Some("name"), Some("note")]),
+                fields(&[Some("1"), Some("Alice"), None]),
+                fields(&[Some("2"), Some("Bob, Jr."), Some("said \"hi\"\ntwice")]),
+                fields(&[Some("3"), None, Some("")]),
+            ],
+            parse(input).unwrap()
+        );
+        assert!(parse("1,\"open\n").is_err());
+        assert!(parse("1,\"closed\"x\n").is_err());
+        assert!(parse("").unwrap().is_empty());
+    }
+}
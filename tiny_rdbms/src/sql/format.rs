@@ -0,0 +1,70 @@
+use crate::relly::value::Value;
+
+// psql style: numbers are right aligned, everything else left aligned
+pub fn format_table(columns: &[impl AsRef<str>], rows: &[Vec<Value>]) -> String {
+    let cells: Vec<Vec<String>> = rows
+        .iter()
+        .map(|row| row.iter().map(Value::to_string).collect())
+        .collect();
+    let widths: Vec<usize> = columns
+        .iter()
+        .enumerate()
+        .map(|(i, column)| {
+            cells
+                .iter()
+                .map(|row| row[i].chars().count())
+                .fold(column.as_ref().chars().count(), usize::max)
+        })
+        .collect();
+
+    let mut out = String::new();
+    let header: Vec<_> = columns
+        .iter()
+        .zip(&widths)
+        .map(|(column, &width)| format!(" {:<width$} ", column.as_ref()))
+        .collect();
+    out.push_str(header.join("|").trim_end());
+    out.push('\n');
+    let rule: Vec<_> = widths.iter().map(|&width| "-".repeat(width + 2)).collect();
+    out.push_str(&rule.join("+"));
+    out.push('\n');
+    for (row, values) in cells.iter().zip(rows) {
+        let line: Vec<_> = row
+            .iter()
+            .zip(values)
+            .zip(&widths)
+            .map(|((cell, value), &width)| match value {
+                Value::Int(_) | Value::UInt(_) | Value::Float(_) => format!(" {:>width$} ", cell),
+                _ => format!(" {:<width$} ", cell),
+            })
+            .collect();
+        out.push_str(line.join("|").trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+// the line psql prints below a result table
+pub fn row_count(count: usize) -> String {
+    match count {
+        1 => "(1 row)".to_string(),
+        count => format!("({} rows)", count),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_table() {
+        let rows = vec![
+            vec![Value::Int(1), Value::Text("Alice".to_string())],
+            vec![Value::Int(-20), Value::Null],
+        ];
+        assert_eq!(
+            " id  | name\n-----+-------\n   1 | Alice\n -20 | NULL\n",
+            format_table(&["id", "name"], &rows)
+        );
+    }
+}
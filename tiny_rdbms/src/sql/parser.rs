@@ -0,0 +1,601 @@
+use super::ast::*;
+use super::tokenizer::{tokenize, Token};
+use super::Error;
+
+pub fn parse(sql: &str) -> Result<Statement, Error> {
+    let mut parser = Parser {
+        tokens: tokenize(sql)?,
+        pos: 0,
+    };
+    let statement = parser.parse_statement()?;
+    parser.consume(&Token::Semicolon);
+    match parser.peek() {
+        Some(token) => Err(Error::Syntax(format!("unexpected token {:?}", token))),
+        None => Ok(statement),
+    }
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn consume(&mut self, token: &Token) -> bool {
+        if self.peek() == Some(token) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn consume_keyword(&mut self, keyword: &str) -> bool {
+        if self.peek().is_some_and(|token| token.is_keyword(keyword)) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: &Token) -> Result<(), Error> {
+        if self.consume(token) {
+            Ok(())
+        } else {
+            Err(self.unexpected(&format!("{:?}", token)))
+        }
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), Error> {
+        if self.consume_keyword(keyword) {
+            Ok(())
+        } else {
+            Err(self.unexpected(keyword))
+        }
+    }
+
+    fn expect_ident(&mut self) -> Result<String, Error> {
+        match self.peek() {
+            Some(Token::Ident(ident)) => {
+                let ident = ident.clone();
+                self.pos += 1;
+                Ok(ident)
+            }
+            _ => Err(self.unexpected("identifier")),
+        }
+    }
+
+    fn unexpected(&self, expected: &str) -> Error {
+        match self.peek() {
+            Some(token) => Error::Syntax(format!("expected {}, found {:?}", expected, token)),
+            None => Error::Syntax(format!("expected {}, found end of input", expected)),
+        }
+    }
+
+    fn parse_list<T>(
+        &mut self,
+        mut parse_elem: impl FnMut(&mut Self) -> Result<T, Error>,
+    ) -> Result<Vec<T>, Error> {
+        let mut elems = vec![parse_elem(self)?];
+        while self.consume(&Token::Comma) {
+            elems.push(parse_elem(self)?);
+        }
+        Ok(elems)
+    }
+
+    fn parse_ident_list(&mut self) -> Result<Vec<String>, Error> {
+        self.expect(&Token::LParen)?;
+        let idents = self.parse_list(Self::expect_ident)?;
+        self.expect(&Token::RParen)?;
+        Ok(idents)
+    }
+
+    fn parse_statement(&mut self) -> Result<Statement, Error> {
+        if self.consume_keyword("CREATE") {
+            self.parse_create_table().map(Statement::CreateTable)
+        } else if self.consume_keyword("DROP") {
+            self.expect_keyword("TABLE")?;
+            self.expect_ident().map(Statement::DropTable)
+        } else if self.consume_keyword("INSERT") {
+            self.parse_insert().map(Statement::Insert)
+        } else if self.consume_keyword("SELECT") {
+            self.parse_select().map(Statement::Select)
+        } else if self.consume_keyword("DELETE") {
+            self.parse_delete().map(Statement::Delete)
+        } else if self.consume_keyword("BEGIN") {
+            self.consume_keyword("TRANSACTION");
+            Ok(Statement::Begin)
+        } else if self.consume_keyword("COMMIT") {
+            Ok(Statement::Commit)
+        } else if self.consume_keyword("ROLLBACK") {
+            Ok(Statement::Rollback)
+        } else if self.consume_keyword("VACUUM") {
+            Ok(Statement::Vacuum)
+        } else if self.consume_keyword("COPY") {
+            self.parse_copy().map(Statement::Copy)
+        } else if self.consume_keyword("EXPLAIN") {
+            let analyze = self.consume_keyword("ANALYZE");
+            self.expect_keyword("SELECT")?;
+            let select = self.parse_select()?;
+            Ok(Statement::Explain(Explain { analyze, select }))
+        } else {
+            Err(self.unexpected("statement"))
+        }
+    }
+
+    fn parse_create_table(&mut self) -> Result<CreateTable, Error> {
+        self.expect_keyword("TABLE")?;
+        let name = self.expect_ident()?;
+        self.expect(&Token::LParen)?;
+        let mut columns = vec![];
+        let mut primary_key = vec![];
+        loop {
+            if self.consume_keyword("PRIMARY") {
+                self.expect_keyword("KEY")?;
+                primary_key = self.parse_ident_list()?;
+            } else {
+                let name = self.expect_ident()?;
+                let data_type = self.expect_ident()?;
+                if self.consume_keyword("PRIMARY") {
+                    self.expect_keyword("KEY")?;
+                    primary_key = vec![name.clone()];
+                }
+                columns.push(ColumnDef { name, data_type });
+            }
+            if !self.consume(&Token::Comma) {
+                break;
+            }
+        }
+        self.expect(&Token::RParen)?;
+        Ok(CreateTable {
+            name,
+            columns,
+            primary_key,
+        })
+    }
+
+    fn parse_insert(&mut self) -> Result<Insert, Error> {
+        self.expect_keyword("INTO")?;
+        let table = self.expect_ident()?;
+        let columns = if self.peek() == Some(&Token::LParen) {
+            Some(self.parse_ident_list()?)
+        } else {
+            None
+        };
+        self.expect_keyword("VALUES")?;
+        let rows = self.parse_list(|parser| {
+            parser.expect(&Token::LParen)?;
+            let row = parser.parse_list(Self::parse_literal)?;
+            parser.expect(&Token::RParen)?;
+            Ok(row)
+        })?;
+        Ok(Insert {
+            table,
+            columns,
+            rows,
+        })
+    }
+
+    fn parse_copy(&mut self) -> Result<CopyFrom, Error> {
+        let table = self.expect_ident()?;
+        self.expect_keyword("FROM")?;
+        let path = match self.peek() {
+            Some(Token::String(path)) => {
+                let path = path.clone();
+                self.pos += 1;
+                path
+            }
+            _ => return Err(self.unexpected("file name")),
+        };
+        let header = if self.consume_keyword("WITH") {
+            self.expect_keyword("HEADER")?;
+            true
+        } else {
+            self.consume_keyword("HEADER")
+        };
+        Ok(CopyFrom {
+            table,
+            path,
+            header,
+        })
+    }
+
+    fn parse_select(&mut self) -> Result<Select, Error> {
+        let projection = if self.consume(&Token::Asterisk) {
+            Projection::All
+        } else {
+            Projection::Columns(self.parse_list(Self::expect_ident)?)
+        };
+        self.expect_keyword("FROM")?;
+        let table = self.expect_ident()?;
+        let selection = self.parse_where()?;
+        let mut order_by = vec![];
+        if self.consume_keyword("ORDER") {
+            self.expect_keyword("BY")?;
+            order_by = self.parse_list(|parser| {
+                let column = parser.expect_ident()?;
+                let desc = if parser.consume_keyword("DESC") {
+                    true
+                } else {
+                    parser.consume_keyword("ASC");
+                    false
+                };
+                Ok(OrderBy { column, desc })
+            })?;
+        }
+        let limit = if self.consume_keyword("LIMIT") {
+            Some(self.parse_count()?)
+        } else {
+            None
+        };
+        let offset = if self.consume_keyword("OFFSET") {
+            self.parse_count()?
+        } else {
+            0
+        };
+        Ok(Select {
+            projection,
+            table,
+            selection,
+            order_by,
+            limit,
+            offset,
+        })
+    }
+
+    fn parse_count(&mut self) -> Result<usize, Error> {
+        match self.peek() {
+            Some(Token::Number(n)) => {
+                let count = n
+                    .parse()
+                    .map_err(|_| Error::Syntax(format!("invalid count: {}", n)))?;
+                self.pos += 1;
+                Ok(count)
+            }
+            _ => Err(self.unexpected("count")),
+        }
+    }
+
+    fn parse_delete(&mut self) -> Result<Delete, Error> {
+        self.expect_keyword("FROM")?;
+        let table = self.expect_ident()?;
+        let selection = self.parse_where()?;
+        Ok(Delete { table, selection })
+    }
+
+    fn parse_where(&mut self) -> Result<Option<Expr>, Error> {
+        if self.consume_keyword("WHERE") {
+            self.parse_expr().map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn parse_expr(&mut self) -> Result<Expr, Error> {
+        let mut expr = self.parse_and()?;
+        while self.consume_keyword("OR") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.parse_and()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, Error> {
+        let mut expr = self.parse_not()?;
+        while self.consume_keyword("AND") {
+            expr = Expr::And(Box::new(expr), Box::new(self.parse_not()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_not(&mut self) -> Result<Expr, Error> {
+        if self.consume_keyword("NOT") {
+            Ok(Expr::Not(Box::new(self.parse_not()?)))
+        } else {
+            self.parse_comparison()
+        }
+    }
+
+    fn parse_comparison(&mut self) -> Result<Expr, Error> {
+        let left = self.parse_operand()?;
+        if self.consume_keyword("IS") {
+            let negated = self.consume_keyword("NOT");
+            self.expect_keyword("NULL")?;
+            return Ok(Expr::IsNull(Box::new(left), negated));
+        }
+        let negated = self.consume_keyword("NOT");
+        if negated || self.consume_keyword("BETWEEN") {
+            if negated {
+                self.expect_keyword("BETWEEN")?;
+            }
+            // `a BETWEEN x AND y` is shorthand for `a >= x AND a <= y`
+            let low = self.parse_operand()?;
+            self.expect_keyword("AND")?;
+            let high = self.parse_operand()?;
+            let expr = Expr::And(
+                Box::new(Expr::Compare(
+                    Box::new(left.clone()),
+                    CompareOp::GtEq,
+                    Box::new(low),
+                )),
+                Box::new(Expr::Compare(
+                    Box::new(left),
+                    CompareOp::LtEq,
+                    Box::new(high),
+                )),
+            );
+            return Ok(if negated {
+                Expr::Not(Box::new(expr))
+            } else {
+                expr
+            });
+        }
+        let op = match self.peek() {
+            Some(Token::Eq) => CompareOp::Eq,
+            Some(Token::NotEq) => CompareOp::NotEq,
+            Some(Token::Lt) => CompareOp::Lt,
+            Some(Token::LtEq) => CompareOp::LtEq,
+            Some(Token::Gt) => CompareOp::Gt,
+            Some(Token::GtEq) => CompareOp::GtEq,
+            _ => return Ok(left),
+        };
+        self.pos += 1;
+        let right = self.parse_operand()?;
+        Ok(Expr::Compare(Box::new(left), op, Box::new(right)))
+    }
+
+    fn parse_operand(&mut self) -> Result<Expr, Error> {
+        match self.peek() {
+            Some(Token::LParen) => {
+                self.pos += 1;
+                let expr = self.parse_expr()?;
+                self.expect(&Token::RParen)?;
+                Ok(expr)
+            }
+            Some(token)
+                if ["TRUE", "FALSE", "NULL"]
+                    .iter()
+                    .any(|keyword| token.is_keyword(keyword)) =>
+            {
+                self.parse_literal().map(Expr::Literal)
+            }
+            Some(Token::Ident(_)) => self.expect_ident().map(Expr::Column),
+            _ => self.parse_literal().map(Expr::Literal),
+        }
+    }
+
+    fn parse_literal(&mut self) -> Result<Literal, Error> {
+        match self.next() {
+            Some(Token::String(s)) => Ok(Literal::String(s)),
+            Some(Token::Number(n)) => Ok(Literal::Number(n)),
+            Some(token) if token.is_keyword("TRUE") => Ok(Literal::Bool(true)),
+            Some(token) if token.is_keyword("FALSE") => Ok(Literal::Bool(false)),
+            Some(token) if token.is_keyword("NULL") => Ok(Literal::Null),
+            _ => {
+                self.pos -= 1;
+                Err(self.unexpected("literal"))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn column(name: &str) -> Box<Expr> {
+        Box::new(Expr::Column(name.to_string()))
+    }
+
+    fn string(s: &str) -> Box<Expr> {
+        Box::new(Expr::Literal(Literal::String(s.to_string())))
+    }
+
+    #[test]
+    fn test_create_table() {
+        let statement =
+            parse("CREATE TABLE users (id TEXT, first_name TEXT, PRIMARY KEY (id));").unwrap();
+        assert_eq!(
+            Statement::CreateTable(CreateTable {
+                name: "users".to_string(),
+                columns: vec![
+                    ColumnDef {
+                        name: "id".to_string(),
+                        data_type: "TEXT".to_string(),
+                    },
+                    ColumnDef {
+                        name: "first_name".to_string(),
+                        data_type: "TEXT".to_string(),
+                    },
+                ],
+                primary_key: vec!["id".to_string()],
+            }),
+            statement
+        );
+        let statement = parse("create table t (a text primary key, b text)").unwrap();
+        assert!(matches!(statement, Statement::CreateTable(create) if create.primary_key == ["a"]));
+    }
+
+    #[test]
+    fn test_copy() {
+        let statement = parse("COPY users FROM 'users.csv' WITH HEADER").unwrap();
+        assert_eq!(
+            Statement::Copy(CopyFrom {
+                table: "users".to_string(),
+                path: "users.csv".to_string(),
+                header: true,
+            }),
+            statement
+        );
+        let statement = parse("copy users from 'users.csv'").unwrap();
+        assert!(matches!(statement, Statement::Copy(copy) if !copy.header));
+        assert!(parse("COPY users FROM users").is_err());
+    }
+
+    #[test]
+    fn test_explain() {
+        let statement = parse("EXPLAIN ANALYZE SELECT * FROM users").unwrap();
+        assert!(matches!(statement, Statement::Explain(explain) if explain.analyze));
+        let statement = parse("explain select id from users where id = 'a'").unwrap();
+        assert!(
+            matches!(statement, Statement::Explain(explain) if !explain.analyze && explain.select.selection.is_some())
+        );
+        assert!(parse("EXPLAIN DELETE FROM users").is_err());
+    }
+
+    #[test]
+    fn test_insert() {
+        let statement = parse("INSERT INTO users (id, age) VALUES ('a', 1), ('b', 2)").unwrap();
+        assert_eq!(
+            Statement::Insert(Insert {
+                table: "users".to_string(),
+                columns: Some(vec!["id".to_string(), "age".to_string()]),
+                rows: vec![
+                    vec![
+                        Literal::String("a".to_string()),
+                        Literal::Number("1".to_string())
+                    ],
+                    vec![
+                        Literal::String("b".to_string()),
+                        Literal::Number("2".to_string())
+                    ],
+                ],
+            }),
+            statement
+        );
+    }
+
+    #[test]
+    fn test_select() {
+        let statement = parse(
+            "SELECT id, name FROM users WHERE id >= 'w' AND NOT (name = 'Bob' OR 'z' < id) \
+             ORDER BY name DESC, id LIMIT 10 OFFSET 5",
+        )
+        .unwrap();
+        assert_eq!(
+            Statement::Select(Select {
+                projection: Projection::Columns(vec!["id".to_string(), "name".to_string()]),
+                table: "users".to_string(),
+                selection: Some(Expr::And(
+                    Box::new(Expr::Compare(column("id"), CompareOp::GtEq, string("w"))),
+                    Box::new(Expr::Not(Box::new(Expr::Or(
+                        Box::new(Expr::Compare(column("name"), CompareOp::Eq, string("Bob"))),
+                        Box::new(Expr::Compare(string("z"), CompareOp::Lt, column("id"))),
+                    )))),
+                )),
+                order_by: vec![
+                    OrderBy {
+                        column: "name".to_string(),
+                        desc: true,
+                    },
+                    OrderBy {
+                        column: "id".to_string(),
+                        desc: false,
+                    },
+                ],
+                limit: Some(10),
+                offset: 5,
+            }),
+            statement
+        );
+    }
+
+    #[test]
+    fn test_literal() {
+        let statement =
+            parse("SELECT * FROM t WHERE a IS NULL OR b IS NOT NULL AND c = TRUE").unwrap();
+        let selection = match statement {
+            Statement::Select(select) => select.selection,
+            _ => unreachable!(),
+        };
+        assert_eq!(
+            Some(Expr::Or(
+                Box::new(Expr::IsNull(column("a"), false)),
+                Box::new(Expr::And(
+                    Box::new(Expr::IsNull(column("b"), true)),
+                    Box::new(Expr::Compare(
+                        column("c"),
+                        CompareOp::Eq,
+                        Box::new(Expr::Literal(Literal::Bool(true)))
+                    )),
+                )),
+            )),
+            selection
+        );
+        let statement = parse("INSERT INTO t VALUES (-1, 2.5, false, null)").unwrap();
+        assert!(
+            matches!(statement, Statement::Insert(insert) if insert.rows[0] == [
+                Literal::Number("-1".to_string()),
+                Literal::Number("2.5".to_string()),
+                Literal::Bool(false),
+                Literal::Null,
+            ])
+        );
+    }
+
+    #[test]
+    fn test_between() {
+        let selection = |sql| match parse(sql).unwrap() {
+            Statement::Select(select) => select.selection.unwrap(),
+            _ => unreachable!(),
+        };
+        let number = |n: &str| Box::new(Expr::Literal(Literal::Number(n.to_string())));
+        let between = Expr::And(
+            Box::new(Expr::Compare(column("a"), CompareOp::GtEq, number("1"))),
+            Box::new(Expr::Compare(column("a"), CompareOp::LtEq, number("5"))),
+        );
+        assert_eq!(
+            Expr::Or(
+                Box::new(between.clone()),
+                Box::new(Expr::IsNull(column("b"), false))
+            ),
+            selection("SELECT * FROM t WHERE a BETWEEN 1 AND 5 OR b IS NULL")
+        );
+        assert_eq!(
+            Expr::Not(Box::new(between)),
+            selection("SELECT * FROM t WHERE a NOT BETWEEN 1 AND 5")
+        );
+        assert!(parse("SELECT * FROM t WHERE a NOT 5").is_err());
+        assert!(parse("SELECT * FROM t WHERE a BETWEEN 1").is_err());
+    }
+
+    #[test]
+    fn test_delete() {
+        let statement = parse("DELETE FROM users WHERE id = 'x'").unwrap();
+        assert_eq!(
+            Statement::Delete(Delete {
+                table: "users".to_string(),
+                selection: Some(Expr::Compare(column("id"), CompareOp::Eq, string("x"))),
+            }),
+            statement
+        );
+    }
+
+    #[test]
+    fn test_transaction() {
+        assert_eq!(Statement::Begin, parse("BEGIN").unwrap());
+        assert_eq!(Statement::Begin, parse("begin transaction;").unwrap());
+        assert_eq!(Statement::Commit, parse("COMMIT").unwrap());
+        assert_eq!(Statement::Rollback, parse("ROLLBACK;").unwrap());
+        assert_eq!(Statement::Vacuum, parse("VACUUM").unwrap());
+    }
+
+    #[test]
+    fn test_error() {
+        assert!(parse("SELECT FROM users").is_err());
+        assert!(parse("SELECT * FROM users WHERE").is_err());
+        assert!(parse("SELECT * FROM users extra").is_err());
+        assert!(parse("SELECT * FROM users LIMIT -1").is_err());
+        assert!(parse("UPDATE users").is_err());
+    }
+}
@@ -0,0 +1,698 @@
+use std::cmp::Ordering;
+use std::ops::Bound;
+
+use super::ast::*;
+use super::Error;
+use crate::catalog::{Column, TableSchema};
+use crate::disk_manager::PageId;
+use crate::mvcc::Snapshot;
+use crate::query_executor::{Filter, Limit, PlanNode, Project, SeqScan, Sort, Tuple, TupleSlice};
+use crate::relly::value::{self, DataType, Value};
+
+#[derive(Debug)]
+pub enum Operand {
+    Column(usize),
+    Literal(Vec<u8>),
+}
+
+impl Operand {
+    fn eval<'a>(&'a self, tuple: TupleSlice<'a>) -> &'a [u8] {
+        match self {
+            Operand::Column(index) => &tuple[*index],
+            Operand::Literal(value) => value,
+        }
+    }
+
+    fn describe(&self, other: &Operand, table: &TableSchema) -> String {
+        let bytes = match self {
+            Operand::Column(index) => return table.columns[*index].name.clone(),
+            Operand::Literal(bytes) => bytes,
+        };
+        let value = match other {
+            Operand::Column(index) => Value::decode(bytes, table.columns[*index].data_type).ok(),
+            Operand::Literal(_) if value::is_null(bytes) => Some(Value::Null),
+            Operand::Literal(_) => None,
+        };
+        match value {
+            Some(Value::Text(s)) => format!("'{}'", s.replace('\'', "''")),
+            Some(value) => value.to_string(),
+            None => Value::Bytes(bytes.clone()).to_string(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Predicate {
+    Compare(Operand, CompareOp, Operand),
+    And(Box<Predicate>, Box<Predicate>),
+    Or(Box<Predicate>, Box<Predicate>),
+    Not(Box<Predicate>),
+    IsNull(Operand, bool),
+}
+
+impl Predicate {
+    pub fn eval(&self, tuple: TupleSlice) -> bool {
+        self.truth(tuple) == Some(true)
+    }
+
+    // three-valued logic: `None` is SQL's UNKNOWN, produced by comparisons with NULL
+    fn truth(&self, tuple: TupleSlice) -> Option<bool> {
+        match self {
+            Predicate::Compare(left, op, right) => {
+                let (left, right) = (left.eval(tuple), right.eval(tuple));
+                if value::is_null(left) || value::is_null(right) {
+                    return None;
+                }
+                let ordering = left.cmp(right);
+                Some(match op {
+                    CompareOp::Eq => ordering.is_eq(),
+                    CompareOp::NotEq => ordering.is_ne(),
+                    CompareOp::Lt => ordering.is_lt(),
+                    CompareOp::LtEq => ordering.is_le(),
+                    CompareOp::Gt => ordering.is_gt(),
+                    CompareOp::GtEq => ordering.is_ge(),
+                })
+            }
+            Predicate::And(left, right) => match (left.truth(tuple), right.truth(tuple)) {
+                (Some(false), _) | (_, Some(false)) => Some(false),
+                (Some(true), Some(true)) => Some(true),
+                _ => None,
+            },
+            Predicate::Or(left, right) => match (left.truth(tuple), right.truth(tuple)) {
+                (Some(true), _) | (_, Some(true)) => Some(true),
+                (Some(false), Some(false)) => Some(false),
+                _ => None,
+            },
+            Predicate::Not(inner) => inner.truth(tuple).map(|truth| !truth),
+            Predicate::IsNull(operand, negated) => {
+                Some(value::is_null(operand.eval(tuple)) != *negated)
+            }
+        }
+    }
+
+    // SQL-like text for EXPLAIN; literals compared with a column are shown as its type
+    pub fn describe(&self, table: &TableSchema) -> String {
+        match self {
+            Predicate::Compare(left, op, right) => format!(
+                "{} {} {}",
+                left.describe(right, table),
+                op,
+                right.describe(left, table)
+            ),
+            Predicate::And(left, right) => {
+                let side = |predicate: &Predicate| match predicate {
+                    Predicate::Or(..) => format!("({})", predicate.describe(table)),
+                    predicate => predicate.describe(table),
+                };
+                format!("{} AND {}", side(left), side(right))
+            }
+            Predicate::Or(left, right) => {
+                format!("{} OR {}", left.describe(table), right.describe(table))
+            }
+            Predicate::Not(inner) => format!("NOT ({})", inner.describe(table)),
+            Predicate::IsNull(operand, negated) => format!(
+                "{} IS {}NULL",
+                operand.describe(operand, table),
+                if *negated { "NOT " } else { "" }
+            ),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ScanPlan {
+    pub table_meta_page_id: PageId,
+    pub lower_bound: Bound<Vec<u8>>,
+    pub upper_bound: Bound<Vec<u8>>,
+    pub reverse: bool,
+    pub filter: Option<Predicate>,
+    pub filter_description: String,
+}
+
+impl ScanPlan {
+    pub fn with_plan_node<R>(&self, snapshot: &Snapshot, f: impl FnOnce(&dyn PlanNode) -> R) -> R {
+        let lower_key = self.lower_bound.as_ref().map(|key| [key.as_slice()]);
+        let upper_key = self.upper_bound.as_ref().map(|key| [key.as_slice()]);
+        let scan = SeqScan {
+            table_meta_page_id: self.table_meta_page_id,
+            lower: lower_key.as_ref().map(|key| &key[..]),
+            upper: upper_key.as_ref().map(|key| &key[..]),
+            reverse: self.reverse,
+            snapshot: Some(snapshot),
+        };
+        match &self.filter {
+            Some(predicate) => {
+                let cond = |tuple: TupleSlice| predicate.eval(tuple);
+                f(&Filter {
+                    inner_plan: &scan,
+                    cond: &cond,
+                    description: &self.filter_description,
+                })
+            }
+            None => f(&scan),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct SelectPlan {
+    pub scan: ScanPlan,
+    pub columns: Vec<String>,
+    pub projection: Vec<usize>,
+    pub order_by: Vec<(usize, bool)>,
+    pub limit: Option<usize>,
+    pub offset: usize,
+}
+
+pub const SORT_MEMORY_LIMIT: usize = 1 << 20;
+
+impl SelectPlan {
+    pub fn with_plan_node<R>(&self, snapshot: &Snapshot, f: impl FnOnce(&dyn PlanNode) -> R) -> R {
+        self.scan.with_plan_node(snapshot, |scan| {
+            let compare = |a: TupleSlice, b: TupleSlice| {
+                self.order_by
+                    .iter()
+                    .map(|&(i, desc)| {
+                        let ordering = a[i].cmp(&b[i]);
+                        if desc {
+                            ordering.reverse()
+                        } else {
+                            ordering
+                        }
+                    })
+                    .find(|ordering| ordering.is_ne())
+                    .unwrap_or(Ordering::Equal)
+            };
+            let sort = Sort {
+                inner_plan: scan,
+                compare: &compare,
+                memory_limit: SORT_MEMORY_LIMIT,
+            };
+            let limit = Limit {
+                inner_plan: if self.order_by.is_empty() {
+                    scan
+                } else {
+                    &sort
+                },
+                offset: self.offset,
+                limit: self.limit,
+            };
+            f(&Project {
+                inner_plan: &limit,
+                columns: &self.projection,
+            })
+        })
+    }
+}
+
+pub fn plan_create_table(create: &CreateTable) -> Result<TableSchema, Error> {
+    for (i, column) in create.columns.iter().enumerate() {
+        if create.columns[..i].iter().any(|c| c.name == column.name) {
+            return Err(Error::Invalid(format!("duplicate column: {}", column.name)));
+        }
+    }
+    let leading_columns = create.columns.iter().map(|column| &column.name);
+    if create.primary_key.len() > create.columns.len()
+        || !create
+            .primary_key
+            .iter()
+            .eq(leading_columns.take(create.primary_key.len()))
+    {
+        return Err(Error::Invalid(
+            "primary key must consist of the leading columns".to_string(),
+        ));
+    }
+    let columns = create
+        .columns
+        .iter()
+        .map(|column| {
+            let data_type = DataType::parse(&column.data_type).ok_or_else(|| {
+                Error::Invalid(format!("unknown data type: {}", column.data_type))
+            })?;
+            Ok(Column {
+                name: column.name.clone(),
+                data_type,
+            })
+        })
+        .collect::<Result<_, Error>>()?;
+    Ok(TableSchema {
+        name: create.name.clone(),
+        columns,
+        num_key_elems: create.primary_key.len().max(1),
+        meta_page_id: PageId::INVALID_PAGE_ID,
+        unique_indices: vec![],
+    })
+}
+
+pub fn plan_insert(insert: &Insert, table: &TableSchema) -> Result<Vec<Tuple>, Error> {
+    let positions: Vec<Option<usize>> = match &insert.columns {
+        Some(columns) => {
+            for (i, column) in columns.iter().enumerate() {
+                column_index(table, column)?;
+                if columns[..i].contains(column) {
+                    return Err(Error::Invalid(format!("duplicate column: {}", column)));
+                }
+            }
+            table
+                .columns
+                .iter()
+                .map(|column| columns.iter().position(|c| *c == column.name))
+                .collect()
+        }
+        None => (0..table.columns.len()).map(Some).collect(),
+    };
+    let num_values = positions.iter().flatten().count();
+    insert
+        .rows
+        .iter()
+        .map(|row| {
+            if row.len() != num_values {
+                return Err(Error::Invalid(format!(
+                    "expected {} values, got {}",
+                    num_values,
+                    row.len()
+                )));
+            }
+            positions
+                .iter()
+                .zip(&table.columns)
+                .enumerate()
+                .map(|(i, (position, column))| {
+                    let value = match position {
+                        Some(position) => literal_value(&row[*position], column.data_type)?,
+                        None => Value::Null,
+                    };
+                    if i < table.num_key_elems && value == Value::Null {
+                        return Err(Error::Invalid(format!(
+                            "primary key column must not be NULL: {}",
+                            column.name
+                        )));
+                    }
+                    Ok(value.encode())
+                })
+                .collect()
+        })
+        .collect()
+}
+
+// CSV fields are typed by their column, so they go through the same checks as INSERT literals
+pub fn plan_copy(
+    records: Vec<Vec<Option<String>>>,
+    table: &TableSchema,
+) -> Result<Vec<Tuple>, Error> {
+    let rows = records
+        .into_iter()
+        .map(|record| {
+            record
+                .into_iter()
+                .enumerate()
+                .map(|(i, field)| {
+                    let data_type = table.columns.get(i).map(|column| column.data_type);
+                    match (field, data_type) {
+                        (None, _) => Literal::Null,
+                        (Some(field), Some(DataType::Int | DataType::UInt | DataType::Float)) => {
+                            Literal::Number(field)
+                        }
+                        (Some(field), Some(DataType::Bool))
+                            if field.eq_ignore_ascii_case("true") =>
+                        {
+                            Literal::Bool(true)
+                        }
+                        (Some(field), Some(DataType::Bool))
+                            if field.eq_ignore_ascii_case("false") =>
+                        {
+                            Literal::Bool(false)
+                        }
+                        (Some(field), _) => Literal::String(field),
+                    }
+                })
+                .collect()
+        })
+        .collect();
+    let insert = Insert {
+        table: table.name.clone(),
+        columns: None,
+        rows,
+    };
+    plan_insert(&insert, table)
+}
+
+pub fn plan_select(select: &Select, table: &TableSchema) -> Result<SelectPlan, Error> {
+    let projection: Vec<usize> = match &select.projection {
+        Projection::All => (0..table.columns.len()).collect(),
+        Projection::Columns(columns) => columns
+            .iter()
+            .map(|column| column_index(table, column))
+            .collect::<Result<_, _>>()?,
+    };
+    let columns = projection
+        .iter()
+        .map(|&i| table.columns[i].name.clone())
+        .collect();
+    let mut order_by: Vec<(usize, bool)> = select
+        .order_by
+        .iter()
+        .map(|order_by| Ok((column_index(table, &order_by.column)?, order_by.desc)))
+        .collect::<Result<_, Error>>()?;
+    let mut scan = plan_scan(select.selection.as_ref(), table)?;
+    // the scan already yields records in primary key order, forwards or backwards
+    if let Some(&(_, desc)) = order_by.first() {
+        if order_by.len() <= table.num_key_elems
+            && order_by
+                .iter()
+                .enumerate()
+                .all(|(i, &order_by)| order_by == (i, desc))
+        {
+            scan.reverse = desc;
+            order_by.clear();
+        }
+    }
+    Ok(SelectPlan {
+        scan,
+        columns,
+        projection,
+        order_by,
+        limit: select.limit,
+        offset: select.offset,
+    })
+}
+
+pub fn plan_delete(delete: &Delete, table: &TableSchema) -> Result<ScanPlan, Error> {
+    plan_scan(delete.selection.as_ref(), table)
+}
+
+fn plan_scan(selection: Option<&Expr>, table: &TableSchema) -> Result<ScanPlan, Error> {
+    let mut plan = ScanPlan {
+        table_meta_page_id: table.meta_page_id,
+        lower_bound: Bound::Unbounded,
+        upper_bound: Bound::Unbounded,
+        reverse: false,
+        filter: selection.map(|expr| compile(expr, table)).transpose()?,
+        filter_description: String::new(),
+    };
+    if let Some(predicate) = &plan.filter {
+        plan.filter_description = predicate.describe(table);
+    }
+    if let Some(predicate) = &plan.filter {
+        let mut conjuncts = vec![];
+        collect_conjuncts(predicate, &mut conjuncts);
+        for (op, value) in conjuncts {
+            let (lower, upper) = match op {
+                CompareOp::Eq => (Bound::Included(value), Bound::Included(value)),
+                CompareOp::Gt => (Bound::Excluded(value), Bound::Unbounded),
+                CompareOp::GtEq => (Bound::Included(value), Bound::Unbounded),
+                CompareOp::Lt => (Bound::Unbounded, Bound::Excluded(value)),
+                CompareOp::LtEq => (Bound::Unbounded, Bound::Included(value)),
+                CompareOp::NotEq => continue,
+            };
+            if is_tighter(lower, &plan.lower_bound, Ordering::Greater) {
+                plan.lower_bound = lower.cloned();
+            }
+            if is_tighter(upper, &plan.upper_bound, Ordering::Less) {
+                plan.upper_bound = upper.cloned();
+            }
+        }
+    }
+    Ok(plan)
+}
+
+// whether `bound` admits fewer keys than `current`; `toward` is the direction in which a bound
+// of this side tightens
+fn is_tighter(bound: Bound<&Vec<u8>>, current: &Bound<Vec<u8>>, toward: Ordering) -> bool {
+    match (bound, current) {
+        (Bound::Unbounded, _) => false,
+        (_, Bound::Unbounded) => true,
+        (Bound::Included(value) | Bound::Excluded(value), Bound::Included(current_value))
+        | (Bound::Included(value) | Bound::Excluded(value), Bound::Excluded(current_value)) => {
+            let ordering = value.cmp(current_value);
+            ordering == toward
+                || (ordering.is_eq()
+                    && matches!((bound, current), (Bound::Excluded(_), Bound::Included(_))))
+        }
+    }
+}
+
+// constraints of the form `<first key column> <op> <literal>` that every result must satisfy
+fn collect_conjuncts<'a>(predicate: &'a Predicate, conjuncts: &mut Vec<(CompareOp, &'a Vec<u8>)>) {
+    match predicate {
+        Predicate::And(left, right) => {
+            collect_conjuncts(left, conjuncts);
+            collect_conjuncts(right, conjuncts);
+        }
+        Predicate::Compare(Operand::Column(0), op, Operand::Literal(value)) => {
+            conjuncts.push((*op, value));
+        }
+        Predicate::Compare(Operand::Literal(value), op, Operand::Column(0)) => {
+            conjuncts.push((op.flip(), value));
+        }
+        _ => {}
+    }
+}
+
+fn compile(expr: &Expr, table: &TableSchema) -> Result<Predicate, Error> {
+    match expr {
+        Expr::Compare(left, op, right) => {
+            let data_type = match (operand_type(left, table)?, operand_type(right, table)?) {
+                (Some(left), Some(right)) if left != right => {
+                    return Err(Error::Invalid(format!(
+                        "cannot compare {} with {}",
+                        left, right
+                    )));
+                }
+                (Some(data_type), _) | (_, Some(data_type)) => Some(data_type),
+                (None, None) => None,
+            };
+            Ok(Predicate::Compare(
+                compile_operand(left, table, data_type)?,
+                *op,
+                compile_operand(right, table, data_type)?,
+            ))
+        }
+        Expr::And(left, right) => Ok(Predicate::And(
+            Box::new(compile(left, table)?),
+            Box::new(compile(right, table)?),
+        )),
+        Expr::Or(left, right) => Ok(Predicate::Or(
+            Box::new(compile(left, table)?),
+            Box::new(compile(right, table)?),
+        )),
+        Expr::Not(inner) => Ok(Predicate::Not(Box::new(compile(inner, table)?))),
+        Expr::IsNull(inner, negated) => Ok(Predicate::IsNull(
+            compile_operand(inner, table, None)?,
+            *negated,
+        )),
+        Expr::Column(_) | Expr::Literal(_) => {
+            Err(Error::Invalid("expected a comparison".to_string()))
+        }
+    }
+}
+
+fn operand_type(expr: &Expr, table: &TableSchema) -> Result<Option<DataType>, Error> {
+    match expr {
+        Expr::Column(column) => Ok(Some(table.columns[column_index(table, column)?].data_type)),
+        _ => Ok(None),
+    }
+}
+
+fn compile_operand(
+    expr: &Expr,
+    table: &TableSchema,
+    data_type: Option<DataType>,
+) -> Result<Operand, Error> {
+    match expr {
+        Expr::Column(column) => column_index(table, column).map(Operand::Column),
+        Expr::Literal(literal) => {
+            let data_type = data_type.unwrap_or_else(|| literal_type(literal));
+            Ok(Operand::Literal(
+                literal_value(literal, data_type)?.encode(),
+            ))
+        }
+        _ => Err(Error::Invalid("expected a column or a literal".to_string())),
+    }
+}
+
+fn column_index(table: &TableSchema, column: &str) -> Result<usize, Error> {
+    table
+        .column_index(column)
+        .ok_or_else(|| Error::NoSuchColumn(column.to_string()))
+}
+
+fn literal_type(literal: &Literal) -> DataType {
+    match literal {
+        Literal::String(_) => DataType::Text,
+        Literal::Number(n) if n.contains(['.', 'e', 'E']) => DataType::Float,
+        Literal::Number(_) | Literal::Null => DataType::Int,
+        Literal::Bool(_) => DataType::Bool,
+    }
+}
+
+fn literal_value(literal: &Literal, data_type: DataType) -> Result<Value, Error> {
+    let invalid = || Error::Invalid(format!("invalid {} literal: {:?}", data_type, literal));
+    let value = match (literal, data_type) {
+        (Literal::Null, _) => Value::Null,
+        (Literal::String(s), DataType::Text) => Value::Text(s.clone()),
+        (Literal::String(s), DataType::Bytes) => Value::Bytes(s.as_bytes().to_vec()),
+        (Literal::Number(n), DataType::Int) => Value::Int(n.parse().map_err(|_| invalid())?),
+        (Literal::Number(n), DataType::UInt) => Value::UInt(n.parse().map_err(|_| invalid())?),
+        (Literal::Number(n), DataType::Float) => Value::Float(n.parse().map_err(|_| invalid())?),
+        (Literal::Bool(b), DataType::Bool) => Value::Bool(*b),
+        _ => return Err(invalid()),
+    };
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::parser::parse;
+
+    fn table() -> TableSchema {
+        let column = |name: &str, data_type| Column {
+            name: name.to_string(),
+            data_type,
+        };
+        TableSchema {
+            name: "users".to_string(),
+            columns: vec![
+                column("id", DataType::Text),
+                column("name", DataType::Text),
+                column("age", DataType::Int),
+            ],
+            num_key_elems: 1,
+            meta_page_id: PageId(1),
+            unique_indices: vec![],
+        }
+    }
+
+    fn scan(sql: &str) -> ScanPlan {
+        match parse(sql).unwrap() {
+            Statement::Select(select) => plan_select(&select, &table()).unwrap().scan,
+            _ => unreachable!(),
+        }
+    }
+
+    fn text(s: &str) -> Vec<u8> {
+        Value::from(s).encode()
+    }
+
+    #[test]
+    fn test_key_range() {
+        let plan = scan("SELECT * FROM users");
+        assert_eq!(Bound::Unbounded, plan.lower_bound);
+        assert_eq!(Bound::Unbounded, plan.upper_bound);
+
+        let plan = scan("SELECT * FROM users WHERE id = 'x'");
+        assert_eq!(Bound::Included(text("x")), plan.lower_bound);
+        assert_eq!(Bound::Included(text("x")), plan.upper_bound);
+
+        let plan =
+            scan("SELECT * FROM users WHERE 'b' < id AND id > 'c' AND id < 'y' AND name = 'z'");
+        assert_eq!(Bound::Excluded(text("c")), plan.lower_bound);
+        assert_eq!(Bound::Excluded(text("y")), plan.upper_bound);
+
+        let plan = scan("SELECT * FROM users WHERE id <= 'y' AND id < 'y' AND id >= 'c'");
+        assert_eq!(Bound::Included(text("c")), plan.lower_bound);
+        assert_eq!(Bound::Excluded(text("y")), plan.upper_bound);
+
+        let plan = scan("SELECT * FROM users WHERE id BETWEEN 'c' AND 'y'");
+        assert_eq!(Bound::Included(text("c")), plan.lower_bound);
+        assert_eq!(Bound::Included(text("y")), plan.upper_bound);
+
+        let plan = scan("SELECT * FROM users WHERE id = 'x' OR id = 'y'");
+        assert_eq!(Bound::Unbounded, plan.lower_bound);
+        assert_eq!(Bound::Unbounded, plan.upper_bound);
+
+        let plan = scan("SELECT * FROM users WHERE name = 'x'");
+        assert_eq!(Bound::Unbounded, plan.lower_bound);
+        assert!(plan.filter.is_some());
+    }
+
+    #[test]
+    fn test_order_by_key() {
+        let select = |sql| match parse(sql).unwrap() {
+            Statement::Select(select) => plan_select(&select, &table()).unwrap(),
+            _ => unreachable!(),
+        };
+        let plan = select("SELECT * FROM users ORDER BY id DESC");
+        assert!(plan.scan.reverse);
+        assert!(plan.order_by.is_empty());
+        let plan = select("SELECT * FROM users ORDER BY id");
+        assert!(!plan.scan.reverse);
+        assert!(plan.order_by.is_empty());
+        let plan = select("SELECT * FROM users ORDER BY age DESC");
+        assert!(!plan.scan.reverse);
+        assert_eq!(vec![(2, true)], plan.order_by);
+    }
+
+    #[test]
+    fn test_error() {
+        let select = |sql| match parse(sql).unwrap() {
+            Statement::Select(select) => plan_select(&select, &table()),
+            _ => unreachable!(),
+        };
+        assert!(matches!(
+            select("SELECT email FROM users"),
+            Err(Error::NoSuchColumn(_))
+        ));
+        assert!(select("SELECT * FROM users WHERE id").is_err());
+        assert!(select("SELECT * FROM users WHERE age = 'x'").is_err());
+        assert!(select("SELECT * FROM users WHERE age = 1.5").is_err());
+        assert!(select("SELECT * FROM users WHERE age = name").is_err());
+
+        let create = |sql| match parse(sql).unwrap() {
+            Statement::CreateTable(create) => plan_create_table(&create),
+            _ => unreachable!(),
+        };
+        assert!(create("CREATE TABLE t (a TEXT, b TEXT, PRIMARY KEY (b))").is_err());
+        assert!(create("CREATE TABLE t (a TEXT, a TEXT)").is_err());
+        assert!(create("CREATE TABLE t (a DATE)").is_err());
+        assert_eq!(
+            2,
+            create("CREATE TABLE t (a TEXT, b TEXT, c TEXT, PRIMARY KEY (a, b))")
+                .unwrap()
+                .num_key_elems
+        );
+    }
+
+    #[test]
+    fn test_typed() {
+        let table = table();
+        let predicate = |sql| match parse(sql).unwrap() {
+            Statement::Select(select) => plan_select(&select, &table).unwrap().scan.filter.unwrap(),
+            _ => unreachable!(),
+        };
+        let row = |age: Value| vec![text("x"), text("Bob"), age.encode()];
+
+        let between = predicate("SELECT * FROM users WHERE age >= 2 AND age < 10");
+        assert!(!between.eval(&row(Value::Int(-3))));
+        assert!(between.eval(&row(Value::Int(2))));
+        assert!(between.eval(&row(Value::Int(9))));
+        assert!(!between.eval(&row(Value::Int(10))));
+        assert!(!between.eval(&row(Value::Null)));
+
+        let not_equal = predicate("SELECT * FROM users WHERE NOT age = 2");
+        assert!(not_equal.eval(&row(Value::Int(3))));
+        assert!(!not_equal.eval(&row(Value::Null)));
+
+        let is_null = predicate("SELECT * FROM users WHERE age IS NULL OR age = 1");
+        assert!(is_null.eval(&row(Value::Null)));
+        assert!(is_null.eval(&row(Value::Int(1))));
+        assert!(!is_null.eval(&row(Value::Int(2))));
+
+        let insert = |sql| match parse(sql).unwrap() {
+            Statement::Insert(insert) => plan_insert(&insert, &table),
+            _ => unreachable!(),
+        };
+        assert_eq!(
+            vec![vec![
+                text("x"),
+                Value::Null.encode(),
+                Value::Int(-1).encode()
+            ]],
+            insert("INSERT INTO users (age, id) VALUES (-1, 'x')").unwrap()
+        );
+        assert!(insert("INSERT INTO users (name) VALUES ('Bob')").is_err());
+        assert!(insert("INSERT INTO users (id, id) VALUES ('x', 'y')").is_err());
+        assert!(insert("INSERT INTO users VALUES ('x', 'Bob', 'old')").is_err());
+    }
+}
@@ -0,0 +1,150 @@
+use super::Error;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Token {
+    Ident(String),
+    String(String),
+    Number(String),
+    LParen,
+    RParen,
+    Comma,
+    Semicolon,
+    Asterisk,
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+}
+
+impl Token {
+    pub fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self, Token::Ident(ident) if ident.eq_ignore_ascii_case(keyword))
+    }
+}
+
+pub fn tokenize(sql: &str) -> Result<Vec<Token>, Error> {
+    let mut tokens = vec![];
+    let mut chars = sql.char_indices().peekable();
+    while let Some((pos, c)) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            ',' => Token::Comma,
+            ';' => Token::Semicolon,
+            '*' => Token::Asterisk,
+            '=' => Token::Eq,
+            '!' => match chars.next() {
+                Some((_, '=')) => Token::NotEq,
+                _ => return Err(Error::Syntax(format!("unexpected '!' at {}", pos))),
+            },
+            '<' => match chars.peek() {
+                Some((_, '=')) => {
+                    chars.next();
+                    Token::LtEq
+                }
+                Some((_, '>')) => {
+                    chars.next();
+                    Token::NotEq
+                }
+                _ => Token::Lt,
+            },
+            '>' => match chars.peek() {
+                Some((_, '=')) => {
+                    chars.next();
+                    Token::GtEq
+                }
+                _ => Token::Gt,
+            },
+            '\'' => {
+                let mut s = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '\'')) => {
+                            // a doubled quote is an escaped quote
+                            if let Some((_, '\'')) = chars.peek() {
+                                chars.next();
+                                s.push('\'');
+                            } else {
+                                break;
+                            }
+                        }
+                        Some((_, c)) => s.push(c),
+                        None => {
+                            return Err(Error::Syntax(format!(
+                                "unterminated string literal at {}",
+                                pos
+                            )))
+                        }
+                    }
+                }
+                Token::String(s)
+            }
+            c if c.is_ascii_digit() || c == '-' => {
+                let mut s = c.to_string();
+                while let Some(&(_, c)) = chars.peek() {
+                    if !(c.is_ascii_digit() || c == '.') {
+                        break;
+                    }
+                    s.push(c);
+                    chars.next();
+                }
+                if s == "-" {
+                    return Err(Error::Syntax(format!("unexpected '-' at {}", pos)));
+                }
+                Token::Number(s)
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let mut s = c.to_string();
+                while let Some(&(_, c)) = chars.peek() {
+                    if !(c.is_alphanumeric() || c == '_') {
+                        break;
+                    }
+                    s.push(c);
+                    chars.next();
+                }
+                Token::Ident(s)
+            }
+            c => {
+                return Err(Error::Syntax(format!(
+                    "unexpected character {:?} at {}",
+                    c, pos
+                )))
+            }
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test() {
+        let tokens = tokenize("SELECT * FROM t WHERE id >= 'it''s' AND n<>-12.5;").unwrap();
+        assert_eq!(
+            vec![
+                Token::Ident("SELECT".to_string()),
+                Token::Asterisk,
+                Token::Ident("FROM".to_string()),
+                Token::Ident("t".to_string()),
+                Token::Ident("WHERE".to_string()),
+                Token::Ident("id".to_string()),
+                Token::GtEq,
+                Token::String("it's".to_string()),
+                Token::Ident("AND".to_string()),
+                Token::Ident("n".to_string()),
+                Token::NotEq,
+                Token::Number("-12.5".to_string()),
+                Token::Semicolon,
+            ],
+            tokens
+        );
+        assert!(tokenize("'open").is_err());
+        assert!(tokenize("a ? b").is_err());
+    }
+}
@@ -1,29 +1,399 @@
+use std::collections::BTreeSet;
+use std::iter;
+
 use anyhow::Result;
 
 use crate::buffer_pool_manager::BufferPoolManager;
 use crate::disk_manager::PageId;
-use crate::relly::btree::BTree;
+use crate::mvcc::{self, Version, Xid};
+use crate::relly::btree::{self, BTree, SearchMode};
 use crate::relly::tuple;
 
+// records are stored as their versions, see `mvcc`; the methods here work on the latest one and
+// write frozen versions, while `Transaction` creates and ends versions under a snapshot; unique
+// indices keep an entry for every secondary key among a record's versions, so that older
+// snapshots still find the records they see
+#[derive(Clone)]
 pub struct SimpleTable {
     pub meta_page_id: PageId,
     pub num_key_elems: usize,
+    pub unique_indices: Vec<UniqueIndex>,
 }
 
 impl SimpleTable {
     pub fn create(&mut self, buffer_manager: &mut BufferPoolManager) -> Result<()> {
         let btree = BTree::create(buffer_manager)?;
         self.meta_page_id = btree.meta_page_id;
+        for unique_index in &mut self.unique_indices {
+            unique_index.create(buffer_manager)?;
+        }
+        Ok(())
+    }
+
+    // replaces the trees of an empty table with ones built from `records`, which may come in any
+    // order, as versions created by `xmin`; the trees keep their meta page ids
+    pub fn bulk_load(
+        &self,
+        buffer_manager: &mut BufferPoolManager,
+        records: &[Vec<Vec<u8>>],
+        xmin: Xid,
+        fill_factor: f64,
+    ) -> Result<()> {
+        let mut pairs = vec![];
+        let mut skeys = vec![vec![]; self.unique_indices.len()];
+        for record in records {
+            let record: Vec<&[u8]> = record.iter().map(Vec::as_slice).collect();
+            let (key, data) = self.encode(&record);
+            for (unique_index, skeys) in self.unique_indices.iter().zip(&mut skeys) {
+                skeys.push((unique_index.encode(&record), key.clone()));
+            }
+            let version = Version {
+                xmin,
+                xmax: None,
+                data,
+            };
+            pairs.push((key, mvcc::encode(&[version])));
+        }
+        // index entries include the primary key, so duplicate secondary keys do not collide in
+        // the trees and are caught here
+        let mut index_pairs = vec![];
+        for mut skeys in skeys {
+            skeys.sort_unstable();
+            if skeys.windows(2).any(|pair| pair[0].0 == pair[1].0) {
+                return Err(btree::Error::DuplicateKey.into());
+            }
+            index_pairs.push(
+                skeys
+                    .into_iter()
+                    .map(|(skey, pkey)| ([skey, pkey.clone()].concat(), pkey))
+                    .collect(),
+            );
+        }
+
+        let mut btrees: Vec<BTree> = vec![];
+        for mut pairs in iter::once(pairs).chain(index_pairs) {
+            pairs.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+            match BTree::bulk_load(buffer_manager, pairs, fill_factor) {
+                Ok(btree) => btrees.push(btree),
+                Err(err) => {
+                    for btree in &btrees {
+                        btree.destroy(buffer_manager)?;
+                    }
+                    return Err(err.into());
+                }
+            }
+        }
+        let meta_page_ids = iter::once(self.meta_page_id)
+            .chain(self.unique_indices.iter().map(|index| index.meta_page_id));
+        for (meta_page_id, btree) in meta_page_ids.zip(btrees) {
+            BTree::new(meta_page_id).replace(buffer_manager, btree)?;
+        }
+        Ok(())
+    }
+
+    pub fn destroy(&self, buffer_manager: &mut BufferPoolManager) -> Result<()> {
+        BTree::new(self.meta_page_id).destroy(buffer_manager)?;
+        for unique_index in &self.unique_indices {
+            BTree::new(unique_index.meta_page_id).destroy(buffer_manager)?;
+        }
         Ok(())
     }
 
     pub fn insert(&self, buffer_manager: &mut BufferPoolManager, record: &[&[u8]]) -> Result<()> {
         let btree = BTree::new(self.meta_page_id);
+        let (key, data) = self.encode(record);
+        self.check_unique(buffer_manager, &key, record, |_| true)?;
+        btree.insert(
+            buffer_manager,
+            &key,
+            &mvcc::encode(&[Version::frozen(data)]),
+        )?;
+        for unique_index in &self.unique_indices {
+            unique_index.insert(buffer_manager, &key, record)?;
+        }
+        Ok(())
+    }
+
+    pub fn update(&self, buffer_manager: &mut BufferPoolManager, record: &[&[u8]]) -> Result<()> {
+        let (key, data) = self.encode(record);
+        self.check_unique(buffer_manager, &key, record, |_| true)?;
+        let versions = self
+            .versions(buffer_manager, &key)?
+            .filter(|versions| mvcc::latest(versions).is_some())
+            .ok_or(btree::Error::KeyNotFound)?;
+        self.replace_versions(buffer_manager, &key, &versions, &[Version::frozen(data)])
+    }
+
+    pub fn upsert(&self, buffer_manager: &mut BufferPoolManager, record: &[&[u8]]) -> Result<()> {
+        let (key, data) = self.encode(record);
+        self.check_unique(buffer_manager, &key, record, |_| true)?;
+        let versions = self.versions(buffer_manager, &key)?.unwrap_or_default();
+        self.replace_versions(buffer_manager, &key, &versions, &[Version::frozen(data)])
+    }
+
+    pub fn delete(&self, buffer_manager: &mut BufferPoolManager, pkey: &[&[u8]]) -> Result<()> {
+        let key = self.encode_key(pkey);
+        let versions = self
+            .versions(buffer_manager, &key)?
+            .ok_or(btree::Error::KeyNotFound)?;
+        self.replace_versions(buffer_manager, &key, &versions, &[])
+    }
+
+    pub fn get(
+        &self,
+        buffer_manager: &mut BufferPoolManager,
+        pkey: &[&[u8]],
+    ) -> Result<Option<Vec<Vec<u8>>>> {
+        self.fetch(buffer_manager, &self.encode_key(pkey))
+    }
+
+    pub fn encode_key(&self, pkey: &[&[u8]]) -> Vec<u8> {
         let mut key = vec![];
-        tuple::encode(record[..self.num_key_elems].iter(), &mut key);
-        let mut value = vec![];
-        tuple::encode(record[self.num_key_elems..].iter(), &mut value);
-        btree.insert(buffer_manager, &key, &value)?;
+        tuple::encode(pkey[..self.num_key_elems].iter(), &mut key);
+        key
+    }
+
+    // the primary key and the data of a version
+    pub fn encode(&self, record: &[&[u8]]) -> (Vec<u8>, Vec<u8>) {
+        let mut data = vec![];
+        tuple::encode(record[self.num_key_elems..].iter(), &mut data);
+        (self.encode_key(record), data)
+    }
+
+    pub fn decode(key: &[u8], data: &[u8]) -> Vec<Vec<u8>> {
+        let mut record = vec![];
+        tuple::decode(key, &mut record);
+        tuple::decode(data, &mut record);
+        record
+    }
+
+    fn fetch(
+        &self,
+        buffer_manager: &mut BufferPoolManager,
+        key: &[u8],
+    ) -> Result<Option<Vec<Vec<u8>>>> {
+        Ok(self.versions(buffer_manager, key)?.and_then(|versions| {
+            mvcc::latest(&versions).map(|version| Self::decode(key, &version.data))
+        }))
+    }
+
+    pub fn versions(
+        &self,
+        buffer_manager: &mut BufferPoolManager,
+        key: &[u8],
+    ) -> Result<Option<Vec<Version>>> {
+        let btree = BTree::new(self.meta_page_id);
+        Ok(btree
+            .get(buffer_manager, key)?
+            .map(|value| mvcc::decode(&value))
+            .transpose()?)
+    }
+
+    // stores `versions` under `key` as they are; an empty list removes the record
+    pub fn put_versions(
+        &self,
+        buffer_manager: &mut BufferPoolManager,
+        key: &[u8],
+        versions: &[Version],
+    ) -> Result<()> {
+        let btree = BTree::new(self.meta_page_id);
+        if versions.is_empty() {
+            match btree.delete(buffer_manager, key) {
+                Ok(()) | Err(btree::Error::KeyNotFound) => {}
+                Err(err) => return Err(err.into()),
+            }
+        } else {
+            btree.upsert(buffer_manager, key, &mvcc::encode(versions))?;
+        }
+        Ok(())
+    }
+
+    // stores `versions` under `key` in place of `old_versions`, and adds and removes index
+    // entries to match
+    pub fn replace_versions(
+        &self,
+        buffer_manager: &mut BufferPoolManager,
+        key: &[u8],
+        old_versions: &[Version],
+        versions: &[Version],
+    ) -> Result<()> {
+        self.put_versions(buffer_manager, key, versions)?;
+        for unique_index in &self.unique_indices {
+            let old_skeys = unique_index.skeys(key, old_versions);
+            let skeys = unique_index.skeys(key, versions);
+            for skey in old_skeys.difference(&skeys) {
+                unique_index.delete_entry(buffer_manager, skey, key)?;
+            }
+            for skey in skeys.difference(&old_skeys) {
+                unique_index.insert_entry(buffer_manager, skey, key)?;
+            }
+        }
+        Ok(())
+    }
+
+    // removes the versions that ended before `horizon`, and records left without any; returns
+    // the number of versions removed
+    pub fn vacuum(&self, buffer_manager: &mut BufferPoolManager, horizon: Xid) -> Result<usize> {
+        let btree = BTree::new(self.meta_page_id);
+        let mut pruned = vec![];
+        let mut iter = btree.search(buffer_manager, SearchMode::Start)?;
+        while let Some((key, value)) = iter.next(buffer_manager)? {
+            let versions = mvcc::decode(&value)?;
+            if let Some(live) = mvcc::prune(&versions, horizon) {
+                pruned.push((key, versions, live));
+            }
+        }
+        drop(iter);
+        let mut num_removed = 0;
+        for (key, versions, live) in pruned {
+            self.replace_versions(buffer_manager, &key, &versions, &live)?;
+            num_removed += versions.len() - live.len();
+        }
+        Ok(num_removed)
+    }
+
+    // removes the versions created by `xids` and revives the ones they ended, as if those
+    // transactions had never run; returns the number of records changed
+    pub fn remove_versions(
+        &self,
+        buffer_manager: &mut BufferPoolManager,
+        xids: &BTreeSet<Xid>,
+    ) -> Result<usize> {
+        let btree = BTree::new(self.meta_page_id);
+        let mut changed = vec![];
+        let mut iter = btree.search(buffer_manager, SearchMode::Start)?;
+        while let Some((key, value)) = iter.next(buffer_manager)? {
+            let versions = mvcc::decode(&value)?;
+            let kept: Vec<_> = versions
+                .iter()
+                .filter(|version| !xids.contains(&version.xmin))
+                .map(|version| Version {
+                    xmax: version.xmax.filter(|xmax| !xids.contains(xmax)),
+                    ..version.clone()
+                })
+                .collect();
+            if kept != versions {
+                changed.push((key, versions, kept));
+            }
+        }
+        drop(iter);
+        for (key, versions, kept) in &changed {
+            self.replace_versions(buffer_manager, key, versions, kept)?;
+        }
+        Ok(changed.len())
+    }
+
+    pub fn check_unique(
+        &self,
+        buffer_manager: &mut BufferPoolManager,
+        pkey: &[u8],
+        record: &[&[u8]],
+        ended: impl Fn(Xid) -> bool,
+    ) -> Result<()> {
+        for unique_index in &self.unique_indices {
+            let skey = unique_index.encode(record);
+            for found in unique_index.lookup(buffer_manager, record)? {
+                if found == pkey {
+                    continue;
+                }
+                // the entry may be left over from an older version, which gives up the value only
+                // once the deletion that ended it is final; until then a rollback could bring it
+                // back
+                let versions = self.versions(buffer_manager, &found)?.unwrap_or_default();
+                let taken = versions.iter().any(|version| {
+                    version.xmax.is_none_or(|xmax| !ended(xmax))
+                        && unique_index.encode(&Self::decode(&found, &version.data)) == skey
+                });
+                if taken {
+                    return Err(btree::Error::DuplicateKey.into());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+pub struct UniqueIndex {
+    pub meta_page_id: PageId,
+    pub skey: Vec<usize>,
+}
+
+impl UniqueIndex {
+    pub fn create(&mut self, buffer_manager: &mut BufferPoolManager) -> Result<()> {
+        let btree = BTree::create(buffer_manager)?;
+        self.meta_page_id = btree.meta_page_id;
         Ok(())
     }
+
+    pub fn insert(
+        &self,
+        buffer_manager: &mut BufferPoolManager,
+        pkey: &[u8],
+        record: &[impl AsRef<[u8]>],
+    ) -> Result<()> {
+        self.insert_entry(buffer_manager, &self.encode(record), pkey)
+    }
+
+    // entries are keyed by the secondary key followed by the primary key, and hold the primary
+    // key
+    fn insert_entry(
+        &self,
+        buffer_manager: &mut BufferPoolManager,
+        skey: &[u8],
+        pkey: &[u8],
+    ) -> Result<()> {
+        let btree = BTree::new(self.meta_page_id);
+        btree.insert(buffer_manager, &[skey, pkey].concat(), pkey)?;
+        Ok(())
+    }
+
+    fn delete_entry(
+        &self,
+        buffer_manager: &mut BufferPoolManager,
+        skey: &[u8],
+        pkey: &[u8],
+    ) -> Result<()> {
+        let btree = BTree::new(self.meta_page_id);
+        btree.delete(buffer_manager, &[skey, pkey].concat())?;
+        Ok(())
+    }
+
+    // the primary keys of the records that have, or had, a version with the secondary key of
+    // `record`
+    pub fn lookup(
+        &self,
+        buffer_manager: &mut BufferPoolManager,
+        record: &[impl AsRef<[u8]>],
+    ) -> Result<Vec<Vec<u8>>> {
+        let btree = BTree::new(self.meta_page_id);
+        let skey = self.encode(record);
+        let mut iter = btree.search(buffer_manager, SearchMode::Key(skey.clone()))?;
+        let mut pkeys = vec![];
+        while let Some((key, pkey)) = iter.next(buffer_manager)? {
+            if !key.starts_with(&skey) {
+                break;
+            }
+            pkeys.push(pkey);
+        }
+        Ok(pkeys)
+    }
+
+    // the distinct secondary keys among `versions` of the record stored under `pkey`
+    fn skeys(&self, pkey: &[u8], versions: &[Version]) -> BTreeSet<Vec<u8>> {
+        versions
+            .iter()
+            .map(|version| self.encode(&SimpleTable::decode(pkey, &version.data)))
+            .collect()
+    }
+
+    // elements are self-delimiting, so this is a byte prefix of the keys of its entries
+    pub fn encode(&self, record: &[impl AsRef<[u8]>]) -> Vec<u8> {
+        let mut skey = vec![];
+        tuple::encode(
+            self.skey.iter().map(|&index| record[index].as_ref()),
+            &mut skey,
+        );
+        skey
+    }
 }
@@ -0,0 +1,908 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::rc::Rc;
+use std::sync::Arc;
+
+use anyhow::Result;
+
+use crate::buffer_pool_manager::BufferPoolManager;
+use crate::commit_log::{CommitLog, Status};
+use crate::lock_manager::{LockManager, LockMode};
+use crate::mvcc::{Snapshot, Version, Xid, FROZEN_XID};
+use crate::relly::btree;
+use crate::table::SimpleTable;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("could not write: the record was changed by a concurrent transaction")]
+    WriteConflict,
+}
+
+// hands out transaction ids, tracks the running transactions, so that vacuum knows which
+// versions some snapshot may still need, and records how each transaction ended
+#[derive(Clone)]
+pub struct TransactionManager {
+    state: Rc<RefCell<State>>,
+    locks: Arc<LockManager>,
+}
+
+struct State {
+    next_xid: Xid,
+    // running transactions and the horizons of their snapshots
+    active: BTreeMap<Xid, Xid>,
+    commit_log: CommitLog,
+    // transactions dropped without a commit or rollback; they count as running until `begin`
+    // rolls them back
+    abandoned: Vec<(Xid, Vec<UndoRecord>)>,
+    // transactions still running at the last shutdown; they count as running until `recover`
+    // removes their writes, so no snapshot sees them in the meantime
+    crashed: BTreeSet<Xid>,
+}
+
+impl TransactionManager {
+    // `next_xid` comes from the disk header, so ids are not reused across restarts
+    pub fn open(buffer_manager: &mut BufferPoolManager) -> Result<Self> {
+        let next_xid = buffer_manager.next_xid().max(FROZEN_XID + 1);
+        let commit_log = CommitLog::open(buffer_manager)?;
+        let crashed = (FROZEN_XID + 1..next_xid)
+            .filter(|&xid| commit_log.status(xid) == Status::InProgress)
+            .collect();
+        Ok(Self {
+            state: Rc::new(RefCell::new(State {
+                next_xid,
+                active: BTreeMap::new(),
+                commit_log,
+                abandoned: vec![],
+                crashed,
+            })),
+            locks: Arc::new(LockManager::new()),
+        })
+    }
+
+    // a transaction that neither committed nor rolled back before the last shutdown may have
+    // had its writes flushed by others; this removes them from `tables` and records it as
+    // aborted, and returns the number of such transactions
+    pub fn recover(
+        &self,
+        buffer_manager: &mut BufferPoolManager,
+        tables: &[SimpleTable],
+    ) -> Result<usize> {
+        let crashed = self.state.borrow().crashed.clone();
+        if crashed.is_empty() {
+            return Ok(0);
+        }
+        for table in tables {
+            table.remove_versions(buffer_manager, &crashed)?;
+        }
+        for &xid in &crashed {
+            self.set_status(buffer_manager, xid, Status::Aborted)?;
+        }
+        buffer_manager.flush()?;
+        self.state.borrow_mut().crashed.clear();
+        Ok(crashed.len())
+    }
+
+    pub fn begin(&self, buffer_manager: &mut BufferPoolManager) -> Result<Transaction> {
+        self.rollback_abandoned(buffer_manager)?;
+        let snapshot = {
+            let mut state = self.state.borrow_mut();
+            // taken before the xid is handed out, so the horizon covers the transaction's own
+            // writes
+            let mut snapshot = Self::take_snapshot(&state);
+            snapshot.xid = Some(state.next_xid);
+            state.next_xid += 1;
+            state
+                .active
+                .insert(snapshot.xid.unwrap(), snapshot.horizon());
+            snapshot
+        };
+        // any flush from here on may carry versions of this transaction, so the header must not
+        // hand its xid out again after a restart
+        buffer_manager.set_next_xid(self.next_xid());
+        Ok(Transaction {
+            xid: snapshot.xid.unwrap(),
+            snapshot,
+            manager: self.clone(),
+            undo_log: vec![],
+            finished: false,
+        })
+    }
+
+    // a failed rollback stays queued, and keeps holding back vacuum, until a later one succeeds
+    fn rollback_abandoned(&self, buffer_manager: &mut BufferPoolManager) -> Result<()> {
+        loop {
+            let abandoned = self.state.borrow_mut().abandoned.pop();
+            let (xid, mut undo_log) = match abandoned {
+                Some(abandoned) => abandoned,
+                None => return Ok(()),
+            };
+            let result = undo(buffer_manager, &mut undo_log, 0)
+                .and_then(|()| self.set_status(buffer_manager, xid, Status::Aborted));
+            if let Err(err) = result {
+                self.state.borrow_mut().abandoned.push((xid, undo_log));
+                return Err(err);
+            }
+            self.end(xid);
+        }
+    }
+
+    // a read-only view of the committed data
+    pub fn snapshot(&self) -> Snapshot {
+        Self::take_snapshot(&self.state.borrow())
+    }
+
+    fn take_snapshot(state: &State) -> Snapshot {
+        Snapshot {
+            xid: None,
+            xmax: state.next_xid,
+            active: state.active.keys().chain(&state.crashed).copied().collect(),
+        }
+    }
+
+    pub fn next_xid(&self) -> Xid {
+        self.state.borrow().next_xid
+    }
+
+    // versions that ended before this are invisible to every running and future transaction
+    pub fn horizon(&self) -> Xid {
+        let state = self.state.borrow();
+        state
+            .active
+            .values()
+            .chain(state.crashed.first())
+            .min()
+            .copied()
+            .unwrap_or(state.next_xid)
+    }
+
+    fn set_status(
+        &self,
+        buffer_manager: &mut BufferPoolManager,
+        xid: Xid,
+        status: Status,
+    ) -> Result<()> {
+        self.state
+            .borrow_mut()
+            .commit_log
+            .set_status(buffer_manager, xid, status)
+    }
+
+    pub fn lock_manager(&self) -> &LockManager {
+        &self.locks
+    }
+
+    fn end(&self, xid: Xid) {
+        self.state.borrow_mut().active.remove(&xid);
+        self.locks.release_all(xid);
+    }
+}
+
+// the versions stored under `key` before a write
+struct UndoRecord {
+    table: SimpleTable,
+    key: Vec<u8>,
+    versions: Vec<Version>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Expect {
+    Absent,
+    Present,
+    Any,
+}
+
+// writes create versions tagged with `xid` and end the ones they replace, so that other
+// snapshots keep seeing the old state; unique indices always point at the newest version.
+// Reads and writes lock their records until the transaction ends. All transactions share one
+// thread, so a lock held by another one fails the call with `lock_manager::Error::Blocked`
+// rather than waiting for it; the caller undoes the statement and runs it again after some
+// transaction has ended. A deadlock victim gets `lock_manager::Error::Deadlock` and has to roll
+// back
+pub struct Transaction {
+    xid: Xid,
+    snapshot: Snapshot,
+    manager: TransactionManager,
+    undo_log: Vec<UndoRecord>,
+    // committed or rolled back
+    finished: bool,
+}
+
+impl Transaction {
+    pub fn xid(&self) -> Xid {
+        self.xid
+    }
+
+    pub fn snapshot(&self) -> &Snapshot {
+        &self.snapshot
+    }
+
+    // versions that ended before this are invisible to every running transaction, this one
+    // included
+    pub fn horizon(&self) -> Xid {
+        self.manager.horizon()
+    }
+
+    // for statements that read or replace the table as a whole
+    pub fn lock_table(&self, table: &SimpleTable, mode: LockMode) -> Result<()> {
+        self.manager
+            .locks
+            .poll_lock_table(self.xid, table.meta_page_id, mode)?;
+        Ok(())
+    }
+
+    // for a statement that failed while blocked and is not run again
+    pub fn stop_waiting(&self) {
+        self.manager.locks.stop_waiting(self.xid);
+    }
+
+    pub fn get(
+        &self,
+        buffer_manager: &mut BufferPoolManager,
+        table: &SimpleTable,
+        pkey: &[&[u8]],
+    ) -> Result<Option<Vec<Vec<u8>>>> {
+        let key = table.encode_key(pkey);
+        self.manager.locks.poll_lock_record(
+            self.xid,
+            table.meta_page_id,
+            &key,
+            LockMode::Shared,
+        )?;
+        let versions = table.versions(buffer_manager, &key)?.unwrap_or_default();
+        Ok(self
+            .snapshot
+            .visible(&versions)
+            .map(|version| SimpleTable::decode(&key, &version.data)))
+    }
+
+    pub fn insert(
+        &mut self,
+        buffer_manager: &mut BufferPoolManager,
+        table: &SimpleTable,
+        record: &[&[u8]],
+    ) -> Result<()> {
+        let (key, data) = table.encode(record);
+        self.write(
+            buffer_manager,
+            table,
+            key,
+            Some((record, data)),
+            Expect::Absent,
+        )
+    }
+
+    pub fn update(
+        &mut self,
+        buffer_manager: &mut BufferPoolManager,
+        table: &SimpleTable,
+        record: &[&[u8]],
+    ) -> Result<()> {
+        let (key, data) = table.encode(record);
+        self.write(
+            buffer_manager,
+            table,
+            key,
+            Some((record, data)),
+            Expect::Present,
+        )
+    }
+
+    pub fn upsert(
+        &mut self,
+        buffer_manager: &mut BufferPoolManager,
+        table: &SimpleTable,
+        record: &[&[u8]],
+    ) -> Result<()> {
+        let (key, data) = table.encode(record);
+        self.write(
+            buffer_manager,
+            table,
+            key,
+            Some((record, data)),
+            Expect::Any,
+        )
+    }
+
+    pub fn delete(
+        &mut self,
+        buffer_manager: &mut BufferPoolManager,
+        table: &SimpleTable,
+        pkey: &[&[u8]],
+    ) -> Result<()> {
+        let key = table.encode_key(pkey);
+        self.write(buffer_manager, table, key, None, Expect::Present)
+    }
+
+    // fills an empty table in one pass; the records are this transaction's, so other snapshots
+    // see them only once it commits, and a rollback deletes them one by one
+    pub fn bulk_load(
+        &mut self,
+        buffer_manager: &mut BufferPoolManager,
+        table: &SimpleTable,
+        records: &[Vec<Vec<u8>>],
+    ) -> Result<()> {
+        table.bulk_load(
+            buffer_manager,
+            records,
+            self.xid,
+            btree::DEFAULT_FILL_FACTOR,
+        )?;
+        for record in records {
+            let record: Vec<&[u8]> = record.iter().map(Vec::as_slice).collect();
+            self.undo_log.push(UndoRecord {
+                table: table.clone(),
+                key: table.encode(&record).0,
+                versions: vec![],
+            });
+        }
+        Ok(())
+    }
+
+    fn write(
+        &mut self,
+        buffer_manager: &mut BufferPoolManager,
+        table: &SimpleTable,
+        key: Vec<u8>,
+        new: Option<(&[&[u8]], Vec<u8>)>,
+        expect: Expect,
+    ) -> Result<()> {
+        self.manager.locks.poll_lock_record(
+            self.xid,
+            table.meta_page_id,
+            &key,
+            LockMode::Exclusive,
+        )?;
+        let versions = table.versions(buffer_manager, &key)?.unwrap_or_default();
+        if !self.snapshot.can_write(&versions) {
+            return Err(Error::WriteConflict.into());
+        }
+        match (expect, self.snapshot.visible(&versions)) {
+            (Expect::Absent, Some(_)) => return Err(btree::Error::DuplicateKey.into()),
+            (Expect::Present, None) => return Err(btree::Error::KeyNotFound.into()),
+            _ => {}
+        }
+        if let Some((record, _)) = &new {
+            // a value freed by a transaction still running is held until that one commits
+            let now = self.manager.snapshot();
+            table.check_unique(buffer_manager, &key, record, |xmax| {
+                xmax == self.xid || now.sees(xmax)
+            })?;
+        }
+
+        let mut new_versions = versions.clone();
+        match new_versions.first_mut() {
+            // a version this transaction created is replaced, no one else has seen it
+            Some(newest) if newest.xmin == self.xid && newest.xmax.is_none() => {
+                new_versions.remove(0);
+            }
+            Some(newest) if newest.xmax.is_none() => newest.xmax = Some(self.xid),
+            _ => {}
+        }
+        if let Some((_, data)) = &new {
+            new_versions.insert(
+                0,
+                Version {
+                    xmin: self.xid,
+                    xmax: None,
+                    data: data.clone(),
+                },
+            );
+        }
+        table.replace_versions(buffer_manager, &key, &versions, &new_versions)?;
+        self.undo_log.push(UndoRecord {
+            table: table.clone(),
+            key,
+            versions,
+        });
+        Ok(())
+    }
+
+    // the flush carries the writes of other running transactions as well, but the commit log
+    // only has this one committed, and `TransactionManager::recover` drops the others' writes
+    // should they never commit
+    pub fn commit(mut self, buffer_manager: &mut BufferPoolManager) -> Result<()> {
+        self.manager
+            .set_status(buffer_manager, self.xid, Status::Committed)?;
+        self.finished = true;
+        buffer_manager.flush()?;
+        Ok(())
+    }
+
+    pub fn rollback(mut self, buffer_manager: &mut BufferPoolManager) -> Result<()> {
+        self.rollback_to(buffer_manager, 0)?;
+        self.manager
+            .set_status(buffer_manager, self.xid, Status::Aborted)?;
+        self.finished = true;
+        Ok(())
+    }
+
+    pub fn savepoint(&self) -> usize {
+        self.undo_log.len()
+    }
+
+    pub fn rollback_to(
+        &mut self,
+        buffer_manager: &mut BufferPoolManager,
+        savepoint: usize,
+    ) -> Result<()> {
+        undo(buffer_manager, &mut self.undo_log, savepoint)
+    }
+}
+
+// a transaction dropped halfway is rolled back by the next `begin`, as there is no buffer pool
+// at hand here
+impl Drop for Transaction {
+    fn drop(&mut self) {
+        if self.finished {
+            self.manager.end(self.xid);
+        } else {
+            let undo_log = std::mem::take(&mut self.undo_log);
+            self.manager
+                .state
+                .borrow_mut()
+                .abandoned
+                .push((self.xid, undo_log));
+        }
+    }
+}
+
+// undoes the records after `savepoint`, newest first; a record leaves the log only once it has
+// been undone
+fn undo(
+    buffer_manager: &mut BufferPoolManager,
+    undo_log: &mut Vec<UndoRecord>,
+    savepoint: usize,
+) -> Result<()> {
+    while undo_log.len() > savepoint {
+        let undo = undo_log.last().unwrap();
+        let versions = undo
+            .table
+            .versions(buffer_manager, &undo.key)?
+            .unwrap_or_default();
+        undo.table
+            .replace_versions(buffer_manager, &undo.key, &versions, &undo.versions)?;
+        undo_log.pop();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Bound;
+
+    use super::*;
+    use crate::buffer_pool_manager::BufferPool;
+    use crate::disk_manager::{self, DiskManager, PageId};
+    use crate::lock_manager;
+    use crate::query_executor::{PlanNode, SeqScan};
+    use crate::relly::btree::{BTree, SearchMode};
+    use crate::table::UniqueIndex;
+
+    fn dump(bufmgr: &mut BufferPoolManager, meta_page_id: PageId) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let btree = BTree::new(meta_page_id);
+        let mut iter = btree.search(bufmgr, SearchMode::Start).unwrap();
+        let mut pairs = vec![];
+        while let Some(pair) = iter.next(bufmgr).unwrap() {
+            pairs.push(pair);
+        }
+        pairs
+    }
+
+    fn to_slices(elems: &[Vec<u8>]) -> Vec<&[u8]> {
+        elems.iter().map(Vec::as_slice).collect()
+    }
+
+    fn record(id: u64, name: &str) -> Vec<Vec<u8>> {
+        vec![format!("{:04}", id).into_bytes(), name.as_bytes().to_vec()]
+    }
+
+    fn open(heap_file: &std::fs::File, log_file: &std::fs::File) -> BufferPoolManager {
+        let disk = DiskManager::with_wal(
+            heap_file.try_clone().unwrap(),
+            log_file.try_clone().unwrap(),
+        )
+        .unwrap();
+        BufferPoolManager::new(disk, BufferPool::new(10))
+    }
+
+    #[test]
+    fn test_rollback_and_commit() {
+        let heap_file = disk_manager::tempfile().unwrap();
+        let log_file = disk_manager::tempfile().unwrap();
+        let mut bufmgr = open(&heap_file, &log_file);
+        let mut table = SimpleTable {
+            meta_page_id: PageId::INVALID_PAGE_ID,
+            num_key_elems: 1,
+            unique_indices: vec![UniqueIndex {
+                meta_page_id: PageId::INVALID_PAGE_ID,
+                skey: vec![1],
+            }],
+        };
+        table.create(&mut bufmgr).unwrap();
+        let manager = TransactionManager::open(&mut bufmgr).unwrap();
+        let mut txn = manager.begin(&mut bufmgr).unwrap();
+        for id in 0..100 {
+            let record = record(id, &format!("name{}", id));
+            txn.insert(&mut bufmgr, &table, &to_slices(&record))
+                .unwrap();
+        }
+        txn.commit(&mut bufmgr).unwrap();
+
+        let index_meta_page_id = table.unique_indices[0].meta_page_id;
+        let before = (
+            dump(&mut bufmgr, table.meta_page_id),
+            dump(&mut bufmgr, index_meta_page_id),
+        );
+
+        let mut txn = manager.begin(&mut bufmgr).unwrap();
+        for id in 100..1000 {
+            let record = record(id, &format!("name{}", id));
+            txn.insert(&mut bufmgr, &table, &to_slices(&record))
+                .unwrap();
+        }
+        for id in 0..50 {
+            txn.delete(&mut bufmgr, &table, &to_slices(&record(id, "")))
+                .unwrap();
+        }
+        txn.update(&mut bufmgr, &table, &to_slices(&record(60, "renamed")))
+            .unwrap();
+        txn.upsert(&mut bufmgr, &table, &to_slices(&record(70, "upserted")))
+            .unwrap();
+        txn.upsert(&mut bufmgr, &table, &to_slices(&record(5000, "new")))
+            .unwrap();
+        assert!(txn
+            .insert(&mut bufmgr, &table, &to_slices(&record(6000, "renamed")))
+            .is_err());
+        assert_eq!(
+            Some(record(60, "renamed")),
+            table.get(&mut bufmgr, &[b"0060"]).unwrap()
+        );
+        assert!(table.get(&mut bufmgr, &[b"0010"]).unwrap().is_none());
+        txn.rollback(&mut bufmgr).unwrap();
+
+        let after = (
+            dump(&mut bufmgr, table.meta_page_id),
+            dump(&mut bufmgr, index_meta_page_id),
+        );
+        assert_eq!(before, after);
+
+        let mut txn = manager.begin(&mut bufmgr).unwrap();
+        txn.delete(&mut bufmgr, &table, &[b"0001"]).unwrap();
+        txn.commit(&mut bufmgr).unwrap();
+        let mut txn = manager.begin(&mut bufmgr).unwrap();
+        txn.delete(&mut bufmgr, &table, &[b"0002"]).unwrap();
+        drop(txn);
+        drop(bufmgr);
+
+        let mut bufmgr = open(&heap_file, &log_file);
+        assert!(table.get(&mut bufmgr, &[b"0001"]).unwrap().is_none());
+        assert_eq!(
+            Some(record(2, "name2")),
+            table.get(&mut bufmgr, &[b"0002"]).unwrap()
+        );
+        // the deleted record keeps its index entry until vacuum
+        assert_eq!(100, dump(&mut bufmgr, index_meta_page_id).len());
+        let manager = TransactionManager::open(&mut bufmgr).unwrap();
+        table.vacuum(&mut bufmgr, manager.horizon()).unwrap();
+        assert_eq!(99, dump(&mut bufmgr, index_meta_page_id).len());
+    }
+
+    #[test]
+    fn test_snapshot_isolation() {
+        let mut bufmgr = open(
+            &disk_manager::tempfile().unwrap(),
+            &disk_manager::tempfile().unwrap(),
+        );
+        let mut table = SimpleTable {
+            meta_page_id: PageId::INVALID_PAGE_ID,
+            num_key_elems: 1,
+            unique_indices: vec![],
+        };
+        table.create(&mut bufmgr).unwrap();
+        let manager = TransactionManager::open(&mut bufmgr).unwrap();
+        let mut txn = manager.begin(&mut bufmgr).unwrap();
+        for id in 0..3 {
+            txn.insert(&mut bufmgr, &table, &to_slices(&record(id, "old")))
+                .unwrap();
+        }
+        txn.commit(&mut bufmgr).unwrap();
+
+        let scan = |bufmgr: &mut BufferPoolManager, snapshot: &Snapshot| {
+            let plan = SeqScan {
+                table_meta_page_id: table.meta_page_id,
+                lower: Bound::Unbounded,
+                upper: Bound::Unbounded,
+                reverse: false,
+                snapshot: Some(snapshot),
+            };
+            let mut exec = plan.start(bufmgr).unwrap();
+            let mut records = vec![];
+            while let Some(record) = exec.next(bufmgr).unwrap() {
+                records.push(record);
+            }
+            records
+        };
+        let mut reader = manager.begin(&mut bufmgr).unwrap();
+        let mut writer = manager.begin(&mut bufmgr).unwrap();
+        writer
+            .update(&mut bufmgr, &table, &to_slices(&record(0, "new")))
+            .unwrap();
+        writer
+            .delete(&mut bufmgr, &table, &to_slices(&record(1, "")))
+            .unwrap();
+        writer
+            .insert(&mut bufmgr, &table, &to_slices(&record(3, "new")))
+            .unwrap();
+        let old = vec![record(0, "old"), record(1, "old"), record(2, "old")];
+        assert_eq!(old, scan(&mut bufmgr, reader.snapshot()));
+        let new = vec![record(0, "new"), record(2, "old"), record(3, "new")];
+        assert_eq!(new, scan(&mut bufmgr, writer.snapshot()));
+        writer.commit(&mut bufmgr).unwrap();
+        assert_eq!(old, scan(&mut bufmgr, reader.snapshot()));
+        assert_eq!(new, scan(&mut bufmgr, &manager.snapshot()));
+        assert_eq!(
+            Some(record(0, "old")),
+            reader.get(&mut bufmgr, &table, &[b"0000"]).unwrap()
+        );
+        let err = reader
+            .update(&mut bufmgr, &table, &to_slices(&record(0, "mine")))
+            .unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(Error::WriteConflict)));
+        reader
+            .update(&mut bufmgr, &table, &to_slices(&record(2, "mine")))
+            .unwrap();
+
+        // the versions the reader still sees survive vacuum until it is gone
+        assert_eq!(0, table.vacuum(&mut bufmgr, manager.horizon()).unwrap());
+        reader.rollback(&mut bufmgr).unwrap();
+        assert_eq!(2, table.vacuum(&mut bufmgr, manager.horizon()).unwrap());
+        assert_eq!(3, dump(&mut bufmgr, table.meta_page_id).len());
+        assert_eq!(new, scan(&mut bufmgr, &manager.snapshot()));
+    }
+
+    #[test]
+    fn test_locks() {
+        let mut bufmgr = open(
+            &disk_manager::tempfile().unwrap(),
+            &disk_manager::tempfile().unwrap(),
+        );
+        let mut table = SimpleTable {
+            meta_page_id: PageId::INVALID_PAGE_ID,
+            num_key_elems: 1,
+            unique_indices: vec![],
+        };
+        table.create(&mut bufmgr).unwrap();
+        let manager = TransactionManager::open(&mut bufmgr).unwrap();
+        let blocked = |result: Result<()>| match result.unwrap_err().downcast() {
+            Ok(lock_manager::Error::Blocked(holder)) => holder,
+            err => panic!("unexpected result: {:?}", err),
+        };
+
+        let mut first = manager.begin(&mut bufmgr).unwrap();
+        first
+            .insert(&mut bufmgr, &table, &to_slices(&record(0, "first")))
+            .unwrap();
+        let mut second = manager.begin(&mut bufmgr).unwrap();
+        let xid = first.xid();
+        assert_eq!(
+            xid,
+            blocked(second.insert(&mut bufmgr, &table, &to_slices(&record(0, "second"))))
+        );
+        assert_eq!(xid, blocked(second.lock_table(&table, LockMode::Exclusive)));
+        second
+            .insert(&mut bufmgr, &table, &to_slices(&record(1, "second")))
+            .unwrap();
+        assert_eq!(2, manager.lock_manager().held(xid).len());
+
+        // an abandoned transaction keeps its locks until it has been rolled back
+        drop(first);
+        assert_eq!(2, manager.lock_manager().held(xid).len());
+        let third = manager.begin(&mut bufmgr).unwrap();
+        assert!(manager.lock_manager().held(xid).is_empty());
+        second
+            .insert(&mut bufmgr, &table, &to_slices(&record(0, "second")))
+            .unwrap();
+        let xid = second.xid();
+        second.commit(&mut bufmgr).unwrap();
+        assert!(manager.lock_manager().held(xid).is_empty());
+        third.lock_table(&table, LockMode::Exclusive).unwrap();
+    }
+
+    #[test]
+    fn test_deadlock() {
+        let mut bufmgr = open(
+            &disk_manager::tempfile().unwrap(),
+            &disk_manager::tempfile().unwrap(),
+        );
+        let mut table = SimpleTable {
+            meta_page_id: PageId::INVALID_PAGE_ID,
+            num_key_elems: 1,
+            unique_indices: vec![],
+        };
+        table.create(&mut bufmgr).unwrap();
+        let manager = TransactionManager::open(&mut bufmgr).unwrap();
+        let mut txn = manager.begin(&mut bufmgr).unwrap();
+        for id in 0..2 {
+            txn.insert(&mut bufmgr, &table, &to_slices(&record(id, "old")))
+                .unwrap();
+        }
+        txn.commit(&mut bufmgr).unwrap();
+
+        // a read holds its record against writers, and waits for them
+        let reader = manager.begin(&mut bufmgr).unwrap();
+        let mut first = manager.begin(&mut bufmgr).unwrap();
+        reader.get(&mut bufmgr, &table, &[b"0000"]).unwrap();
+        let err = first
+            .update(&mut bufmgr, &table, &to_slices(&record(0, "first")))
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(&lock_manager::Error::Blocked(xid)) if xid == reader.xid()
+        ));
+        reader.commit(&mut bufmgr).unwrap();
+        first
+            .update(&mut bufmgr, &table, &to_slices(&record(0, "first")))
+            .unwrap();
+        let reader = manager.begin(&mut bufmgr).unwrap();
+        let err = reader.get(&mut bufmgr, &table, &[b"0000"]).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(&lock_manager::Error::Blocked(xid)) if xid == first.xid()
+        ));
+        reader.stop_waiting();
+        reader.commit(&mut bufmgr).unwrap();
+
+        // the request that closes the cycle fails, and the other one gets through once the
+        // victim has rolled back
+        let mut second = manager.begin(&mut bufmgr).unwrap();
+        second
+            .update(&mut bufmgr, &table, &to_slices(&record(1, "second")))
+            .unwrap();
+        assert!(first
+            .update(&mut bufmgr, &table, &to_slices(&record(1, "first")))
+            .is_err());
+        let err = second
+            .update(&mut bufmgr, &table, &to_slices(&record(0, "second")))
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(&lock_manager::Error::Deadlock(xid)) if xid == second.xid()
+        ));
+        let victim = second.xid();
+        second.rollback(&mut bufmgr).unwrap();
+        assert!(manager.lock_manager().held(victim).is_empty());
+        first
+            .update(&mut bufmgr, &table, &to_slices(&record(1, "first")))
+            .unwrap();
+        first.commit(&mut bufmgr).unwrap();
+        assert_eq!(
+            Some(record(1, "first")),
+            table.get(&mut bufmgr, &[b"0001"]).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_unique_until_commit() {
+        let heap_file = disk_manager::tempfile().unwrap();
+        let log_file = disk_manager::tempfile().unwrap();
+        let mut bufmgr = open(&heap_file, &log_file);
+        let mut table = SimpleTable {
+            meta_page_id: PageId::INVALID_PAGE_ID,
+            num_key_elems: 1,
+            unique_indices: vec![UniqueIndex {
+                meta_page_id: PageId::INVALID_PAGE_ID,
+                skey: vec![1],
+            }],
+        };
+        table.create(&mut bufmgr).unwrap();
+        let manager = TransactionManager::open(&mut bufmgr).unwrap();
+        let mut txn = manager.begin(&mut bufmgr).unwrap();
+        txn.insert(&mut bufmgr, &table, &to_slices(&record(0, "v")))
+            .unwrap();
+        txn.insert(&mut bufmgr, &table, &to_slices(&record(1, "w")))
+            .unwrap();
+        txn.commit(&mut bufmgr).unwrap();
+
+        // the values stay taken while the delete and the rename that free them may roll back
+        let mut t1 = manager.begin(&mut bufmgr).unwrap();
+        t1.delete(&mut bufmgr, &table, &[b"0000"]).unwrap();
+        t1.update(&mut bufmgr, &table, &to_slices(&record(1, "x")))
+            .unwrap();
+        let mut t2 = manager.begin(&mut bufmgr).unwrap();
+        for name in ["v", "w"] {
+            let err = t2
+                .insert(&mut bufmgr, &table, &to_slices(&record(2, name)))
+                .unwrap_err();
+            assert!(matches!(
+                err.downcast_ref(),
+                Some(btree::Error::DuplicateKey)
+            ));
+        }
+        // the transaction that freed them may reuse them
+        t1.insert(&mut bufmgr, &table, &to_slices(&record(3, "v")))
+            .unwrap();
+        t1.rollback(&mut bufmgr).unwrap();
+        t2.commit(&mut bufmgr).unwrap();
+        assert_eq!(
+            Some(record(0, "v")),
+            table.get(&mut bufmgr, &[b"0000"]).unwrap()
+        );
+        assert_eq!(
+            Some(record(1, "w")),
+            table.get(&mut bufmgr, &[b"0001"]).unwrap()
+        );
+
+        // once the delete commits the value is free
+        let mut t1 = manager.begin(&mut bufmgr).unwrap();
+        t1.delete(&mut bufmgr, &table, &[b"0000"]).unwrap();
+        let mut t2 = manager.begin(&mut bufmgr).unwrap();
+        t1.commit(&mut bufmgr).unwrap();
+        t2.insert(&mut bufmgr, &table, &to_slices(&record(2, "v")))
+            .unwrap();
+        t2.commit(&mut bufmgr).unwrap();
+    }
+
+    #[test]
+    fn test_recover_unfinished() {
+        let heap_file = disk_manager::tempfile().unwrap();
+        let log_file = disk_manager::tempfile().unwrap();
+        let mut bufmgr = open(&heap_file, &log_file);
+        let mut table = SimpleTable {
+            meta_page_id: PageId::INVALID_PAGE_ID,
+            num_key_elems: 1,
+            unique_indices: vec![UniqueIndex {
+                meta_page_id: PageId::INVALID_PAGE_ID,
+                skey: vec![1],
+            }],
+        };
+        table.create(&mut bufmgr).unwrap();
+        let manager = TransactionManager::open(&mut bufmgr).unwrap();
+        let mut txn = manager.begin(&mut bufmgr).unwrap();
+        txn.insert(&mut bufmgr, &table, &to_slices(&record(0, "old")))
+            .unwrap();
+        txn.commit(&mut bufmgr).unwrap();
+
+        // the committer's flush carries the running transaction's writes to disk
+        let mut running = manager.begin(&mut bufmgr).unwrap();
+        running
+            .update(&mut bufmgr, &table, &to_slices(&record(0, "new")))
+            .unwrap();
+        running
+            .insert(&mut bufmgr, &table, &to_slices(&record(1, "added")))
+            .unwrap();
+        let mut txn = manager.begin(&mut bufmgr).unwrap();
+        txn.insert(&mut bufmgr, &table, &to_slices(&record(2, "other")))
+            .unwrap();
+        txn.commit(&mut bufmgr).unwrap();
+        std::mem::forget(running);
+        drop(bufmgr);
+
+        let mut bufmgr = open(&heap_file, &log_file);
+        let manager = TransactionManager::open(&mut bufmgr).unwrap();
+        // the writes of the crashed transaction stay hidden until they are removed
+        let txn = manager.begin(&mut bufmgr).unwrap();
+        assert_eq!(
+            Some(record(0, "old")),
+            txn.get(&mut bufmgr, &table, &[b"0000"]).unwrap()
+        );
+        assert!(txn.get(&mut bufmgr, &table, &[b"0001"]).unwrap().is_none());
+        txn.commit(&mut bufmgr).unwrap();
+        assert_eq!(0, table.vacuum(&mut bufmgr, manager.horizon()).unwrap());
+        assert_eq!(1, manager.recover(&mut bufmgr, &[table.clone()]).unwrap());
+        let txn = manager.begin(&mut bufmgr).unwrap();
+        assert_eq!(
+            Some(record(0, "old")),
+            txn.get(&mut bufmgr, &table, &[b"0000"]).unwrap()
+        );
+        assert!(txn.get(&mut bufmgr, &table, &[b"0001"]).unwrap().is_none());
+        assert_eq!(
+            Some(record(2, "other")),
+            txn.get(&mut bufmgr, &table, &[b"0002"]).unwrap()
+        );
+        assert_eq!(
+            2,
+            dump(&mut bufmgr, table.unique_indices[0].meta_page_id).len()
+        );
+        assert_eq!(0, manager.recover(&mut bufmgr, &[table.clone()]).unwrap());
+    }
+}
@@ -0,0 +1,248 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, prelude::*, BufReader, SeekFrom};
+
+use crate::disk_manager::{self, PageId, CHECKSUM_SIZE, PAGE_SIZE};
+
+// every record starts with a page id; a commit record has no body, a page record is followed
+// by the full page image and its checksum
+const COMMIT_MARKER: PageId = PageId::INVALID_PAGE_ID;
+const HEADER_SIZE: u64 = 8;
+const FRAME_SIZE: u64 = HEADER_SIZE + (PAGE_SIZE + CHECKSUM_SIZE) as u64;
+
+pub struct Wal {
+    log_file: File,
+    frames: HashMap<PageId, u64>,
+    end: u64,
+}
+
+impl Wal {
+    // scans the log and keeps the committed frames only; a torn or uncommitted tail is discarded
+    pub fn open(mut log_file: File) -> io::Result<Self> {
+        let log_size = log_file.metadata()?.len();
+        log_file.seek(SeekFrom::Start(0))?;
+        let mut reader = BufReader::new(&mut log_file);
+        let mut frames = HashMap::new();
+        let mut pending = vec![];
+        let mut offset = 0;
+        let mut end = 0;
+        let mut header = [0u8; HEADER_SIZE as usize];
+        while offset + HEADER_SIZE <= log_size {
+            reader.read_exact(&mut header)?;
+            let page_id = PageId::from(&header[..]);
+            if page_id == COMMIT_MARKER {
+                offset += HEADER_SIZE;
+                frames.extend(pending.drain(..));
+                end = offset;
+                continue;
+            }
+            if offset + FRAME_SIZE > log_size {
+                break;
+            }
+            reader.seek_relative((FRAME_SIZE - HEADER_SIZE) as i64)?;
+            pending.push((page_id, offset + HEADER_SIZE));
+            offset += FRAME_SIZE;
+        }
+        drop(reader);
+        log_file.set_len(end)?;
+        log_file.sync_all()?;
+        Ok(Self {
+            log_file,
+            frames,
+            end,
+        })
+    }
+
+    // every frame appended since the last checkpoint, counting each image of a rewritten page
+    pub fn num_frames(&self) -> usize {
+        (self.end / FRAME_SIZE) as usize
+    }
+
+    pub fn append(&mut self, page_id: PageId, data: &[u8]) -> io::Result<()> {
+        self.log_file.seek(SeekFrom::Start(self.end))?;
+        self.log_file.write_all(&page_id.0.to_ne_bytes())?;
+        self.log_file.write_all(data)?;
+        self.log_file
+            .write_all(&disk_manager::checksum(page_id, data))?;
+        self.frames.insert(page_id, self.end + HEADER_SIZE);
+        self.end += FRAME_SIZE;
+        Ok(())
+    }
+
+    pub fn read(&mut self, page_id: PageId, data: &mut [u8]) -> io::Result<bool> {
+        let offset = match self.frames.get(&page_id) {
+            Some(&offset) => offset,
+            None => return Ok(false),
+        };
+        self.log_file.seek(SeekFrom::Start(offset))?;
+        self.log_file.read_exact(data)?;
+        if data.len() == PAGE_SIZE {
+            self.verify(page_id, data)?;
+        }
+        Ok(true)
+    }
+
+    // expects the file position right after the page image
+    fn verify(&mut self, page_id: PageId, data: &[u8]) -> io::Result<()> {
+        let mut stored = [0u8; CHECKSUM_SIZE];
+        self.log_file.read_exact(&mut stored)?;
+        disk_manager::verify_checksum(page_id, data, &stored)
+    }
+
+    pub fn commit(&mut self) -> io::Result<()> {
+        self.log_file.seek(SeekFrom::Start(self.end))?;
+        self.log_file.write_all(&COMMIT_MARKER.0.to_ne_bytes())?;
+        self.end += HEADER_SIZE;
+        self.log_file.sync_data()
+    }
+
+    // hands the latest image of every logged page to `write`; the caller must make the heap
+    // durable before calling `reset`
+    pub fn checkpoint(
+        &mut self,
+        mut write: impl FnMut(PageId, &[u8]) -> io::Result<()>,
+    ) -> io::Result<()> {
+        let mut frames: Vec<_> = self
+            .frames
+            .iter()
+            .map(|(&id, &offset)| (id, offset))
+            .collect();
+        frames.sort_by_key(|&(page_id, _)| page_id.0);
+        let mut data = vec![0u8; PAGE_SIZE];
+        for (page_id, offset) in frames {
+            self.log_file.seek(SeekFrom::Start(offset))?;
+            self.log_file.read_exact(&mut data)?;
+            self.verify(page_id, &data)?;
+            write(page_id, &data)?;
+        }
+        Ok(())
+    }
+
+    pub fn reset(&mut self) -> io::Result<()> {
+        self.log_file.set_len(0)?;
+        self.log_file.sync_all()?;
+        self.frames.clear();
+        self.end = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::buffer_pool_manager::{BufferPool, BufferPoolManager};
+    use crate::disk_manager::{self, DiskManager, CHECKPOINT_THRESHOLD};
+    use crate::relly::btree::{BTree, SearchMode};
+
+    fn read_all(file: &mut File) -> Vec<u8> {
+        let mut bytes = vec![];
+        file.seek(SeekFrom::Start(0)).unwrap();
+        file.read_to_end(&mut bytes).unwrap();
+        bytes
+    }
+
+    fn file_with(bytes: &[u8]) -> File {
+        let mut file = disk_manager::tempfile().unwrap();
+        file.write_all(bytes).unwrap();
+        file
+    }
+
+    fn key(i: u64) -> Vec<u8> {
+        format!("{:05}", i).into_bytes()
+    }
+
+    #[test]
+    fn test_crash_recovery() {
+        let mut heap_file = disk_manager::tempfile().unwrap();
+        let mut log_file = disk_manager::tempfile().unwrap();
+        let disk = DiskManager::with_wal(
+            heap_file.try_clone().unwrap(),
+            log_file.try_clone().unwrap(),
+        )
+        .unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(5));
+        let btree = BTree::create(&mut bufmgr).unwrap();
+
+        // the expected contents after each commit, alongside the log size at that commit
+        let mut expected = BTreeMap::new();
+        let mut commits = vec![];
+        for batch in 0..4u64 {
+            for i in (0..200).map(|i| i * 4 + batch) {
+                let value = vec![batch as u8; 100];
+                btree.insert(&mut bufmgr, &key(i), &value).unwrap();
+                expected.insert(key(i), value);
+            }
+            if batch == 3 {
+                for i in 0..300 {
+                    btree.delete(&mut bufmgr, &key(i)).unwrap();
+                    expected.remove(&key(i));
+                }
+            }
+            bufmgr.flush().unwrap();
+            commits.push((log_file.metadata().unwrap().len(), expected.clone()));
+        }
+
+        let heap = read_all(&mut heap_file);
+        let log = read_all(&mut log_file);
+        assert!(heap.is_empty());
+        assert_eq!(commits.last().unwrap().0, log.len() as u64);
+
+        let mut cuts: Vec<usize> = (0..=log.len()).step_by(PAGE_SIZE / 3).collect();
+        for &(offset, _) in &commits {
+            cuts.extend([offset as usize - 1, offset as usize]);
+        }
+        for cut in cuts {
+            let disk = DiskManager::with_wal(file_with(&heap), file_with(&log[..cut])).unwrap();
+            let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(5));
+            let committed = commits
+                .iter()
+                .rev()
+                .find(|&&(offset, _)| offset <= cut as u64);
+            let expected = match committed {
+                Some((_, expected)) => expected,
+                None => {
+                    assert_eq!(1, bufmgr.num_pages(), "cut at {}", cut);
+                    continue;
+                }
+            };
+            let btree = BTree::new(btree.meta_page_id);
+            let mut iter = btree.search(&mut bufmgr, SearchMode::Start).unwrap();
+            let mut actual = BTreeMap::new();
+            while let Some((key, value)) = iter.next(&mut bufmgr).unwrap() {
+                actual.insert(key, value);
+            }
+            assert_eq!(expected, &actual, "cut at {}", cut);
+            for (key, value) in expected {
+                assert_eq!(Some(value), btree.get(&mut bufmgr, key).unwrap().as_ref());
+            }
+        }
+    }
+
+    #[test]
+    fn test_checkpoint_hot_page() {
+        let mut heap_file = disk_manager::tempfile().unwrap();
+        let log_file = disk_manager::tempfile().unwrap();
+        let mut disk = DiskManager::with_wal(
+            heap_file.try_clone().unwrap(),
+            log_file.try_clone().unwrap(),
+        )
+        .unwrap();
+        let page_id = disk.allocate_page().unwrap();
+
+        // a single page rewritten over and over still gets the log checkpointed
+        for i in 0..3 * CHECKPOINT_THRESHOLD {
+            disk.write_page_data(page_id, &[i as u8; PAGE_SIZE])
+                .unwrap();
+            disk.sync().unwrap();
+            assert!(log_file.metadata().unwrap().len() <= CHECKPOINT_THRESHOLD as u64 * FRAME_SIZE);
+        }
+        assert!(!read_all(&mut heap_file).is_empty());
+
+        let mut disk = DiskManager::with_wal(heap_file, log_file).unwrap();
+        let mut data = vec![0u8; PAGE_SIZE];
+        disk.read_page_data(page_id, &mut data).unwrap();
+        assert_eq!(vec![(3 * CHECKPOINT_THRESHOLD - 1) as u8; PAGE_SIZE], data);
+    }
+}
@@ -0,0 +1,41 @@
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::thread;
+
+use tiny_rdbms::buffer_pool_manager::{BufferPool, BufferPoolManager};
+use tiny_rdbms::disk_manager::DiskManager;
+use tiny_rdbms::net::server::{Protocol, Server};
+
+// a server over a fresh database file, which is removed along with its log when this is dropped
+pub struct TestServer {
+    pub addr: SocketAddr,
+    path: PathBuf,
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        remove_database(&self.path);
+    }
+}
+
+fn remove_database(path: &Path) {
+    let mut log_path = path.as_os_str().to_owned();
+    log_path.push(".wal");
+    let _ = std::fs::remove_file(path);
+    let _ = std::fs::remove_file(log_path);
+}
+
+pub fn start_server(name: &str, protocol: Protocol) -> TestServer {
+    let path = std::env::temp_dir().join(format!("tiny_rdbms-{}-{}", name, std::process::id()));
+    remove_database(&path);
+    let server = Server::bind("127.0.0.1:0", protocol).unwrap();
+    let addr = server.local_addr().unwrap();
+    let db_path = path.clone();
+    thread::spawn(move || {
+        server.run(move || {
+            let disk = DiskManager::open(&db_path)?;
+            Ok(BufferPoolManager::new(disk, BufferPool::new(32)))
+        })
+    });
+    TestServer { addr, path }
+}
@@ -0,0 +1,228 @@
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+
+use tiny_rdbms::net::server::Protocol;
+
+mod common;
+
+// what the backend sent, with the parts these tests look at decoded
+#[derive(Debug, PartialEq)]
+enum Reply {
+    Columns(Vec<(String, i32)>),
+    Row(Vec<Option<String>>),
+    Complete(String),
+    Error(String, String),
+    Empty,
+    Ready(u8),
+    Other(u8),
+}
+
+// just enough of a frontend to drive the simple query protocol
+struct Frontend {
+    stream: TcpStream,
+}
+
+impl Frontend {
+    fn connect(addr: SocketAddr) -> Self {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        // psql asks for TLS first and carries on in plain text when refused
+        stream
+            .write_all(&[0, 0, 0, 8, 0x04, 0xd2, 0x16, 0x2f])
+            .unwrap();
+        let mut answer = [0u8];
+        stream.read_exact(&mut answer).unwrap();
+        assert_eq!(b'N', answer[0]);
+
+        let mut body = (3u32 << 16).to_be_bytes().to_vec();
+        body.extend(b"user\0tester\0database\0test\0\0");
+        let mut message = (body.len() as u32 + 4).to_be_bytes().to_vec();
+        message.extend(body);
+        stream.write_all(&message).unwrap();
+        let mut frontend = Self { stream };
+        let replies = frontend.replies();
+        assert_eq!(Reply::Other(b'R'), replies[0]);
+        assert!(replies.contains(&Reply::Other(b'S')) && replies.contains(&Reply::Other(b'K')));
+        frontend
+    }
+
+    fn send(&mut self, kind: u8, body: &[u8]) {
+        let mut message = vec![kind];
+        message.extend((body.len() as u32 + 4).to_be_bytes());
+        message.extend(body);
+        self.stream.write_all(&message).unwrap();
+    }
+
+    fn query(&mut self, sql: &str) -> Vec<Reply> {
+        self.send(b'Q', format!("{}\0", sql).as_bytes());
+        self.replies()
+    }
+
+    // everything up to and including the next ReadyForQuery
+    fn replies(&mut self) -> Vec<Reply> {
+        let mut replies = vec![];
+        loop {
+            let mut header = [0u8; 5];
+            self.stream.read_exact(&mut header).unwrap();
+            let len = u32::from_be_bytes(header[1..].try_into().unwrap()) as usize;
+            let mut body = vec![0u8; len - 4];
+            self.stream.read_exact(&mut body).unwrap();
+            let mut body = &body[..];
+            let reply = match header[0] {
+                b'T' => {
+                    let count = take_i16(&mut body);
+                    Reply::Columns(
+                        (0..count)
+                            .map(|_| {
+                                let name = take_cstring(&mut body);
+                                let (_, rest) = body.split_at(6);
+                                body = rest;
+                                let type_oid = i32::from_be_bytes(body[..4].try_into().unwrap());
+                                body = &body[12..];
+                                (name, type_oid)
+                            })
+                            .collect(),
+                    )
+                }
+                b'D' => {
+                    let count = take_i16(&mut body);
+                    Reply::Row(
+                        (0..count)
+                            .map(|_| {
+                                let len = i32::from_be_bytes(body[..4].try_into().unwrap());
+                                body = &body[4..];
+                                (len >= 0).then(|| {
+                                    let (value, rest) = body.split_at(len as usize);
+                                    body = rest;
+                                    String::from_utf8(value.to_vec()).unwrap()
+                                })
+                            })
+                            .collect(),
+                    )
+                }
+                b'C' => Reply::Complete(take_cstring(&mut body)),
+                b'E' => {
+                    let (mut code, mut message) = (String::new(), String::new());
+                    while body[0] != 0 {
+                        let field = body[0];
+                        body = &body[1..];
+                        let value = take_cstring(&mut body);
+                        match field {
+                            b'C' => code = value,
+                            b'M' => message = value,
+                            _ => {}
+                        }
+                    }
+                    Reply::Error(code, message)
+                }
+                b'I' => Reply::Empty,
+                b'Z' => Reply::Ready(body[0]),
+                kind => Reply::Other(kind),
+            };
+            let done = matches!(reply, Reply::Ready(_));
+            replies.push(reply);
+            if done {
+                return replies;
+            }
+        }
+    }
+}
+
+fn take_i16(body: &mut &[u8]) -> i16 {
+    let value = i16::from_be_bytes(body[..2].try_into().unwrap());
+    *body = &body[2..];
+    value
+}
+
+fn take_cstring(body: &mut &[u8]) -> String {
+    let end = body.iter().position(|&byte| byte == 0).unwrap();
+    let s = String::from_utf8(body[..end].to_vec()).unwrap();
+    *body = &body[end + 1..];
+    s
+}
+
+fn complete(tag: &str) -> Reply {
+    Reply::Complete(tag.to_string())
+}
+
+fn row(values: &[Option<&str>]) -> Reply {
+    Reply::Row(
+        values
+            .iter()
+            .map(|value| value.map(str::to_string))
+            .collect(),
+    )
+}
+
+#[test]
+fn test_simple_query() {
+    let server = common::start_server("postgres", Protocol::Postgres);
+    let addr = server.addr;
+    let mut frontend = Frontend::connect(addr);
+
+    assert_eq!(
+        vec![complete("CREATE TABLE"), Reply::Ready(b'I')],
+        frontend.query("CREATE TABLE t (id TEXT, n INT, ok BOOL, PRIMARY KEY (id))")
+    );
+    assert_eq!(
+        vec![
+            complete("BEGIN"),
+            complete("INSERT 0 2"),
+            Reply::Ready(b'T')
+        ],
+        frontend.query("BEGIN; INSERT INTO t VALUES ('a;', 1, TRUE), ('b', NULL, FALSE);")
+    );
+    assert_eq!(
+        vec![
+            Reply::Columns(vec![
+                ("id".to_string(), 25),
+                ("n".to_string(), 20),
+                ("ok".to_string(), 16)
+            ]),
+            row(&[Some("a;"), Some("1"), Some("t")]),
+            row(&[Some("b"), None, Some("f")]),
+            complete("SELECT 2"),
+            complete("COMMIT"),
+            Reply::Ready(b'I')
+        ],
+        frontend.query("SELECT * FROM t; COMMIT")
+    );
+    // the types come from the schema, even with no values to go by
+    assert_eq!(
+        vec![
+            Reply::Columns(vec![("n".to_string(), 20), ("ok".to_string(), 16)]),
+            complete("SELECT 0"),
+            Reply::Ready(b'I')
+        ],
+        frontend.query("SELECT n, ok FROM t WHERE id = 'none'")
+    );
+
+    // the statements after a failing one are skipped
+    assert_eq!(
+        vec![
+            Reply::Error("23505".to_string(), "duplicate key".to_string()),
+            Reply::Ready(b'I')
+        ],
+        frontend.query("INSERT INTO t VALUES ('b', 2, TRUE); DROP TABLE t")
+    );
+    let replies = frontend.query("SELEC 1");
+    assert!(matches!(&replies[0], Reply::Error(code, _) if code == "42601"));
+    let replies = frontend.query("SELECT * FROM missing");
+    assert!(matches!(&replies[0], Reply::Error(code, _) if code == "42P01"));
+    assert_eq!(
+        vec![Reply::Empty, Reply::Ready(b'I')],
+        frontend.query(" ; ")
+    );
+
+    // the extended protocol is refused once, then ignored up to Sync
+    frontend.send(b'P', b"\0SELECT 1\0\0\0");
+    frontend.send(b'B', b"\0\0\0\0\0\0\0\0");
+    frontend.send(b'S', b"");
+    let replies = frontend.replies();
+    assert!(matches!(&replies[..], [Reply::Error(code, _), Reply::Ready(b'I')] if code == "0A000"));
+    assert_eq!(
+        vec![row(&[Some("b"), None, Some("f")])],
+        frontend.query("SELECT * FROM t WHERE id = 'b'")[1..2]
+    );
+
+    frontend.send(b'X', b"");
+}
@@ -0,0 +1,124 @@
+use std::thread;
+use std::time::Duration;
+
+use tiny_rdbms::net::client::Client;
+use tiny_rdbms::net::server::Protocol;
+use tiny_rdbms::net::Error;
+use tiny_rdbms::relly::value::Value;
+
+mod common;
+
+fn text(s: &str) -> Value {
+    Value::Text(s.to_string())
+}
+
+#[test]
+fn test_sessions() {
+    let server = common::start_server("sessions", Protocol::Native);
+    let addr = server.addr;
+    let mut alice = Client::connect(addr).unwrap();
+    let mut bob = Client::connect(addr).unwrap();
+
+    let response = alice
+        .query("CREATE TABLE t (id TEXT, n INT, PRIMARY KEY (id))")
+        .unwrap();
+    assert_eq!("CREATE TABLE", response.tag);
+    assert!(response.columns.is_empty());
+    let response = bob
+        .query("INSERT INTO t VALUES ('a', 1), ('b', 2);")
+        .unwrap();
+    assert_eq!("INSERT 2", response.tag);
+
+    // alice's transaction stays invisible to bob until it commits
+    alice.query("BEGIN").unwrap();
+    alice.query("INSERT INTO t VALUES ('c', 3)").unwrap();
+    alice.query("DELETE FROM t WHERE id = 'a'").unwrap();
+    let response = bob.query("SELECT * FROM t").unwrap();
+    assert_eq!(vec!["id", "n"], response.columns);
+    assert_eq!(
+        vec![
+            vec![text("a"), Value::Int(1)],
+            vec![text("b"), Value::Int(2)]
+        ],
+        response.rows
+    );
+    assert_eq!("SELECT 2", response.tag);
+    // bob's delete waits for alice's lock on the record, and then finds it gone
+    let mut bob = thread::scope(|scope| {
+        let delete = scope.spawn(move || {
+            let response = bob.query("DELETE FROM t WHERE id = 'a'").unwrap();
+            (bob, response)
+        });
+        thread::sleep(Duration::from_millis(50));
+        assert!(!delete.is_finished());
+        alice.query("COMMIT").unwrap();
+        let (bob, response) = delete.join().unwrap();
+        assert_eq!("DELETE 0", response.tag);
+        bob
+    });
+    let response = bob.query("SELECT id FROM t").unwrap();
+    assert_eq!(vec![vec![text("b")], vec![text("c")]], response.rows);
+
+    // errors leave the connection usable, and a dropped connection loses its transaction
+    match bob.query("SELECT * FROM missing") {
+        Err(Error::Server(message)) => assert_eq!("no such table: missing", message),
+        result => panic!("unexpected result: {:?}", result),
+    }
+    bob.query("BEGIN").unwrap();
+    bob.query("INSERT INTO t VALUES ('d', 4)").unwrap();
+    drop(bob);
+    // the server notices the disconnect on its own time, and until then carol waits for the row
+    let mut carol = Client::connect(addr).unwrap();
+    let response = carol.query("INSERT INTO t VALUES ('d', 5)").unwrap();
+    assert_eq!("INSERT 1", response.tag);
+    let response = alice.query("SELECT n FROM t WHERE id = 'd'").unwrap();
+    assert_eq!(vec![vec![Value::Int(5)]], response.rows);
+}
+
+#[test]
+fn test_concurrent_clients() {
+    let server = common::start_server("concurrent", Protocol::Native);
+    let addr = server.addr;
+    let mut client = Client::connect(addr).unwrap();
+    client
+        .query("CREATE TABLE t (id TEXT, n INT, PRIMARY KEY (id))")
+        .unwrap();
+    thread::scope(|scope| {
+        for t in 0..4 {
+            scope.spawn(move || {
+                let mut client = Client::connect(addr).unwrap();
+                for i in 0..50 {
+                    let sql = format!("INSERT INTO t VALUES ('{}-{:02}', {})", t, i, i);
+                    assert_eq!("INSERT 1", client.query(&sql).unwrap().tag);
+                }
+            });
+        }
+    });
+    let response = client.query("SELECT * FROM t").unwrap();
+    assert_eq!(200, response.rows.len());
+    assert_eq!(
+        vec![text("3-49"), Value::Int(49)],
+        *response.rows.last().unwrap()
+    );
+}
+
+#[test]
+fn test_copy_refused() {
+    let server = common::start_server("copy", Protocol::Native);
+    let mut client = Client::connect(server.addr).unwrap();
+    client
+        .query("CREATE TABLE t (id TEXT, PRIMARY KEY (id))")
+        .unwrap();
+    // the file would be read by the server, with the server's permissions
+    let path = std::env::temp_dir().join(format!("tiny_rdbms-refused-{}.csv", std::process::id()));
+    std::fs::write(&path, "secret\n").unwrap();
+    let result = client.query(&format!("COPY t FROM '{}'", path.display()));
+    let _ = std::fs::remove_file(&path);
+    match result {
+        Err(Error::Server(message)) => {
+            assert_eq!("COPY FROM a file is not allowed in this session", message)
+        }
+        result => panic!("unexpected result: {:?}", result),
+    }
+    assert!(client.query("SELECT * FROM t").unwrap().rows.is_empty());
+}